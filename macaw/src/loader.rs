@@ -1,4 +1,9 @@
+use std::path::Path;
+
 use bevy::{asset::LoadState, prelude::*};
+use shared::block::registry::BlockRegistry;
+
+use crate::util::get_file;
 
 /// A representation of whether or not the project has completed the loading
 /// process for filesystem resources.
@@ -31,6 +36,26 @@ impl LoaderAssets {
 pub struct MacawLoaderPlugin;
 
 impl MacawLoaderPlugin {
+    /// Fills the block registry with the built-in blocks and any found in
+    /// the `blocks` assets folder, then installs it for the whole game.
+    ///
+    /// This runs before anything else can look at blocks.
+    fn load_blocks() {
+        let mut registry = BlockRegistry::with_builtins();
+
+        let blocks_dir = get_file("blocks");
+        if Path::new(&blocks_dir).is_dir() {
+            match registry.load_dir(Path::new(&blocks_dir)) {
+                Ok(added) => tracing::info!("Loaded {} blocks from `{blocks_dir}`.", added.len()),
+                Err(e) => tracing::error!("Failed to load blocks from `{blocks_dir}`: {e}"),
+            }
+        }
+
+        if let Err(e) = registry.install() {
+            tracing::error!("Couldn't install the block registry: {e}");
+        }
+    }
+
     /// Begins loading resources.
     fn load(mut commands: Commands, asset_server: Res<AssetServer>) {
         let loader_assets = LoaderAssets::new(vec![asset_server
@@ -69,6 +94,7 @@ impl MacawLoaderPlugin {
 impl Plugin for MacawLoaderPlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<LoaderState>();
+        app.add_systems(PreStartup, MacawLoaderPlugin::load_blocks);
         app.add_systems(Startup, MacawLoaderPlugin::load);
         app.add_systems(
             Update,
@@ -1,11 +1,16 @@
 use bevy::{
+    app::AppExit,
     input::mouse::MouseMotion,
     prelude::*,
     utils::Uuid,
     window::{CursorGrabMode, PrimaryWindow},
 };
+use shared::world::{player::PlayerData, MacawWorld};
 
-use crate::{loader::LoaderState, renderer::skybox};
+use crate::{
+    loader::LoaderState,
+    renderer::{meshing::RenderedWorld, skybox},
+};
 
 #[derive(Component)]
 pub struct PlayerCamera;
@@ -14,7 +19,9 @@ pub struct MacawPlayerPlugin;
 
 impl Plugin for MacawPlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, Player::setup);
+        app.add_systems(Startup, Player::setup)
+            .add_systems(PostStartup, Player::join_world)
+            .add_systems(Last, Player::save_on_exit);
 
         // add skybox when we finish loading the game
         app.add_systems(
@@ -28,22 +35,27 @@ impl Plugin for MacawPlayerPlugin {
 
 // For entities that are players, such as in multiplayer
 #[derive(Component)]
-#[allow(unused)] // TODO: make it used!
 pub struct Player {
     uuid: Uuid,
     username: String,
 }
 
 impl Player {
+    /// The UUID of the person playing on this computer.
+    ///
+    /// Singleplayer only ever has one player, so it always uses the same
+    /// UUID to find its saved data again.
+    pub const LOCAL_UUID: Uuid = Uuid::from_u128(0x6d61_6361_775f_706c_6179_6572_0000_0001);
+
     pub fn setup(mut commands: Commands) {
-        // TODO?: use
+        // the player is moved to where they belong once they join the world
         let _player = commands
             .spawn((
                 Player {
-                    uuid: Uuid::new_v4(),
+                    uuid: Self::LOCAL_UUID,
                     username: "Player".to_owned(),
                 },
-                Transform::from_xyz(0.0, 17.0, 10.0),
+                Transform::default(),
                 GlobalTransform::default(),
             ))
             .insert(Name::new("Player"))
@@ -53,6 +65,64 @@ impl Player {
             .id();
     }
 
+    /// Puts players back where they were when they last left the world, or
+    /// at its spawn location if they've never been there.
+    pub fn join_world(
+        mut world: ResMut<RenderedWorld>,
+        mut players: Query<(&Player, &mut Transform)>,
+    ) {
+        for (player, mut transform) in players.iter_mut() {
+            let data = match world.0.join(player.uuid, player.username.clone()) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::error!("Couldn't read `{}`'s saved data: {e}", player.username);
+                    PlayerData::new(
+                        player.uuid,
+                        player.username.clone(),
+                        world.0.spawn_location(),
+                    )
+                }
+            };
+
+            transform.translation = Vec3::from_array(data.position);
+            transform.rotation = Quat::from_array(data.rotation);
+        }
+    }
+
+    /// Writes down where every player is in the world's player records, so
+    /// the next save keeps them there.
+    pub fn update_records(world: &mut MacawWorld, players: &Query<(&Player, &Transform)>) {
+        for (player, transform) in players.iter() {
+            let Some(data) = world.player(player.uuid) else {
+                continue;
+            };
+
+            let data = PlayerData {
+                position: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+                ..data.clone()
+            };
+            world.update_player(data);
+        }
+    }
+
+    /// Saves every player, along with the rest of the world, as the game
+    /// closes.
+    pub fn save_on_exit(
+        mut exit: EventReader<AppExit>,
+        mut world: ResMut<RenderedWorld>,
+        players: Query<(&Player, &Transform)>,
+    ) {
+        if exit.read().next().is_none() {
+            return;
+        }
+
+        Self::update_records(&mut world.0, &players);
+        if let Err(e) = world.0.save() {
+            tracing::error!("Failed to save the world on exit: {e}");
+        }
+    }
+
     /// Controls the player using application input.
     pub fn player_input_system(
         mut mouse_input: EventReader<MouseMotion>,
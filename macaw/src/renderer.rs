@@ -1,49 +1,124 @@
+use std::{sync::Arc, time::Duration};
+
 use bevy::{
     prelude::*,
     window::{CursorGrabMode, PrimaryWindow},
 };
-use shared::world::{generation::generators::fixed::Generate, MacawWorld};
+use shared::{
+    time::GameTick,
+    world::{
+        coordinates::GlobalCoordinate, generation::generators::fixed::Generate,
+        storage::FilesystemStorage, streaming::RenderDistance, MacawWorld,
+    },
+};
+
+use self::meshing::RenderedWorld;
+use crate::player::Player;
 
 pub mod meshing;
 pub mod skybox;
+
+/// How often the world is saved while it's being played.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far around each player chunks are loaded and drawn.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct ViewDistance(pub RenderDistance);
+
 pub struct MacawRendererPlugin;
 
 impl Plugin for MacawRendererPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Startup, Self::setup);
+        app.init_resource::<ViewDistance>()
+            .insert_resource(Time::<Fixed>::from_hz(GameTick::TICKS_PER_SECOND as f64))
+            .add_systems(Startup, Self::setup)
+            .add_systems(FixedUpdate, Self::tick_world)
+            .add_systems(
+                Update,
+                (
+                    Self::autosave,
+                    Self::stream_chunks,
+                    Self::pick_up_loaded_chunks,
+                    meshing::remesh_changed_chunks,
+                    meshing::despawn_unloaded_chunks,
+                )
+                    .chain(),
+            );
     }
 }
 
 impl MacawRendererPlugin {
-    fn setup(
-        mut commands: Commands,
-        meshes: ResMut<Assets<Mesh>>,
-        materials: ResMut<Assets<StandardMaterial>>,
-        mut window_query: Query<&mut Window, With<PrimaryWindow>>,
-        asset_server: Res<AssetServer>,
-    ) {
+    fn setup(mut commands: Commands, mut window_query: Query<&mut Window, With<PrimaryWindow>>) {
         // enable mouse lock
         let mut window = window_query.single_mut();
         window.cursor.grab_mode = CursorGrabMode::Locked;
 
-        // create a new world and save it
-        let mut world = Generate::testing_world();
+        // create a new world and save it in the game's folder
+        let mut world = Generate::testing_world(Arc::new(FilesystemStorage::default()));
         world.save().expect("world should save");
 
-        // load back this world
-        let loaded_world = MacawWorld::load(world.metadata()).expect("would should load");
-
-        // render the loaded world!
-        meshing::render_clusters(
-            &mut commands,
-            meshes,
-            &loaded_world,
-            asset_server,
-            materials,
-        );
+        // load back this world. its chunks trickle in over the next few frames
+        let loaded_world =
+            MacawWorld::load_async(world.metadata(), world.storage()).expect("would should load");
 
         world
             .save()
             .expect("world should be able to save onto its old self");
+
+        // render the loaded world! its chunks get meshed as they change
+        commands.insert_resource(RenderedWorld(loaded_world));
+    }
+
+    /// Moves the world along by one game tick. This runs as often as the
+    /// game ticks, not every frame.
+    fn tick_world(mut world: ResMut<RenderedWorld>) {
+        world.0.tick();
+    }
+
+    /// Loads the chunks around each player, and unloads the ones nobody's
+    /// near anymore.
+    fn stream_chunks(
+        mut world: ResMut<RenderedWorld>,
+        distance: Res<ViewDistance>,
+        players: Query<&Transform, With<Player>>,
+    ) {
+        let centres = players
+            .iter()
+            .map(|transform| {
+                let [x, y, z] = transform.translation.floor().to_array();
+                GlobalCoordinate::new(x as i64, y as i64, z as i64).chunk()
+            })
+            .collect::<Vec<_>>();
+
+        let unloaded = world.0.stream_around(&centres, distance.0);
+        if !unloaded.is_empty() {
+            tracing::debug!("{} chunks were unloaded", unloaded.len());
+        }
+    }
+
+    /// Brings in chunks that finished loading in the background.
+    fn pick_up_loaded_chunks(mut world: ResMut<RenderedWorld>) {
+        let arrived = world.0.poll();
+
+        if !arrived.is_empty() {
+            tracing::debug!("{} chunks finished loading", arrived.len());
+        }
+    }
+
+    /// Saves the world, players included, every so often. Everything is
+    /// written in the background, so this doesn't hold up the frame.
+    fn autosave(
+        time: Res<Time>,
+        mut timer: Local<Option<Timer>>,
+        mut world: ResMut<RenderedWorld>,
+        players: Query<(&Player, &Transform)>,
+    ) {
+        let timer =
+            timer.get_or_insert_with(|| Timer::new(AUTOSAVE_INTERVAL, TimerMode::Repeating));
+
+        if timer.tick(time.delta()).just_finished() {
+            Player::update_records(&mut world.0, &players);
+            world.0.save_async();
+        }
     }
 }
@@ -8,8 +8,8 @@ use std::collections::HashSet;
 use shared::{
     block::{Block, BlockSide, BlockType},
     world::{
-        chunk::Chunk,
-        coordinates::{BoundingBox, ChunkBlockCoordinate},
+        chunk::{journal::JournalKind, Chunk, CHUNK_LENGTH},
+        coordinates::{BoundingBox, ChunkBlockCoordinate, ChunkCoordinate},
         MacawWorld,
     },
 };
@@ -174,13 +174,30 @@ impl Render for Chunk {
 
     /// Creates a list of the chunk's block `Cluster`s to be rendered.
     fn cluster(&self) -> Vec<Cluster> {
+        // uniform chunks are either empty or one big cluster
+        if let Some(block) = self.uniform_block() {
+            if block.block_type == BlockType::AIR {
+                return Vec::new();
+            }
+
+            let last = CHUNK_LENGTH - 1;
+            return vec![Cluster::new(
+                block.block_type,
+                BoundingBox::new(
+                    ChunkBlockCoordinate::ORIGIN,
+                    ChunkBlockCoordinate::new(last, last, last),
+                ),
+                self,
+            )];
+        }
+
         let mut blocks_to_check = self.blocks();
         let mut completed_blocks = HashSet::new();
         let mut clusters = Vec::new();
 
         for (coordinate, block) in blocks_to_check
             .iter_mut() // don't check air
-            .filter(|(_, block)| block.block_type != BlockType::Air)
+            .filter(|(_, block)| block.block_type != BlockType::AIR)
         {
             // don't check blocks we've finished
             if completed_blocks.contains(coordinate) {
@@ -214,72 +231,106 @@ impl Render for Chunk {
     // ...
 }
 
-/// A function called by Bevy's renderer when it's collected a `World`'s `Cluster`s.
-///
-/// This will eventually take a cluster to render (i.e. chunk coordinate) so
-/// we don't update the whole world.
-pub fn render_clusters(
-    commands: &mut Commands,
+/// The world being drawn on screen.
+#[derive(Resource)]
+pub struct RenderedWorld(pub MacawWorld);
+
+/// Marks a mesh as belonging to a chunk, so it can be replaced when that
+/// chunk changes.
+#[derive(Clone, Copy, Component, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkMesh(pub ChunkCoordinate);
+
+/// A system that re-meshes the chunks that changed since they were last
+/// meshed. Chunks that haven't changed keep their old meshes.
+pub fn remesh_changed_chunks(
+    mut commands: Commands,
+    mut world: ResMut<RenderedWorld>,
+    chunk_meshes: Query<(Entity, &ChunkMesh)>,
     mut meshes: ResMut<Assets<Mesh>>,
-    world: &MacawWorld,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let clusters = world
+    let changed = world
+        .0
         .chunks()
         .iter()
-        .flat_map(|(_, f)| f.cluster())
-        .map(|c| c.build())
-        .collect::<Vec<_>>();
-
-    for (block_type, transform, mesh) in clusters {
-        tracing::debug!(
-            "Rendering cluster of block type: `{block_type:?}` at `{}`",
-            transform.translation
-        );
+        .filter(|(_, chunk)| chunk.is_dirty(JournalKind::Mesh))
+        .map(|(coords, _)| *coords)
+        .collect::<HashSet<_>>();
 
-        commands.spawn(PbrBundle {
-            mesh: meshes.add(mesh),
-            transform,
-            material: get_texture_from_block_type(&block_type, &mut materials, &asset_server),
-            ..Default::default()
-        });
+    if changed.is_empty() {
+        return;
     }
-    // render things
-}
 
-fn get_texture_from_block_type(
-    block_type: &BlockType,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    asset_server: &Res<AssetServer>,
-) -> Handle<StandardMaterial> {
-    fn handle_builder(assets: &Res<AssetServer>, path: &str) -> Handle<Image> {
-        assets.load(path.to_owned())
+    // throw out the old meshes
+    for (entity, chunk_mesh) in chunk_meshes.iter() {
+        if changed.contains(&chunk_mesh.0) {
+            commands.entity(entity).despawn();
+        }
     }
 
-    fn material_builder(
-        materials: &mut ResMut<'_, Assets<StandardMaterial>>,
-        image_handle: Handle<Image>,
-    ) -> Handle<StandardMaterial> {
-        materials.add(StandardMaterial {
-            base_color_texture: Some(image_handle),
-            reflectance: 1.0,
-            metallic: 0.0,
-            ..Default::default()
-        })
+    for coords in changed {
+        let Some(chunk) = world.0.chunk(coords) else {
+            continue;
+        };
+        chunk.mark_clean(JournalKind::Mesh);
+
+        for (block_type, transform, mesh) in chunk.cluster().into_iter().map(|c| c.build()) {
+            tracing::debug!(
+                "Rendering cluster of block type: `{block_type:?}` at `{}`",
+                transform.translation
+            );
+
+            commands.spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    transform,
+                    material: get_texture_from_block_type(
+                        &block_type,
+                        &mut materials,
+                        &asset_server,
+                    ),
+                    ..Default::default()
+                },
+                ChunkMesh(coords),
+            ));
+        }
     }
+}
 
-    let stone_handle: Handle<Image> = handle_builder(asset_server, &get_file("stone.png"));
-    let grass_handle: Handle<Image> = handle_builder(asset_server, &get_file("grass.png"));
-    let dirt_handle: Handle<Image> = handle_builder(asset_server, &get_file("dirt.png"));
+/// A system that throws out the meshes of chunks that were unloaded.
+pub fn despawn_unloaded_chunks(
+    mut commands: Commands,
+    world: Res<RenderedWorld>,
+    chunk_meshes: Query<(Entity, &ChunkMesh)>,
+) {
+    for (entity, chunk_mesh) in chunk_meshes.iter() {
+        if !world.0.chunks().contains_key(&chunk_mesh.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
 
-    let stone_material = material_builder(materials, stone_handle);
-    let dirt_material = material_builder(materials, dirt_handle);
-    let grass_material = material_builder(materials, grass_handle);
+/// The texture used for blocks that don't have one of their own.
+const FALLBACK_TEXTURE: &str = "stone.png";
 
-    match block_type {
-        BlockType::Grass => grass_material.clone(),
-        BlockType::Dirt => dirt_material.clone(),
-        _ => stone_material.clone(),
-    }
+fn get_texture_from_block_type(
+    block_type: &BlockType,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    asset_server: &Res<AssetServer>,
+) -> Handle<StandardMaterial> {
+    // clusters are drawn with a single texture, so use the top one
+    let texture = block_type
+        .definition()
+        .and_then(|d| d.textures.for_side(&BlockSide::PositiveY))
+        .unwrap_or(FALLBACK_TEXTURE);
+
+    let image_handle: Handle<Image> = asset_server.load(get_file(texture));
+
+    materials.add(StandardMaterial {
+        base_color_texture: Some(image_handle),
+        reflectance: 1.0,
+        metallic: 0.0,
+        ..Default::default()
+    })
 }
@@ -1,53 +1,179 @@
+use std::fmt::Display;
+
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use self::{
+    registry::{BlockDefinition, BlockRegistry},
+    state::{BlockState, BlockStateError, Property, PropertyValue},
+};
+
+pub mod entity;
+pub mod legacy;
+pub mod registry;
+pub mod state;
+
 #[derive(
     Clone, Component, Debug, Default, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize,
 )]
 pub struct Block {
     pub block_type: BlockType,
-    pub state: u32, // TODO
+    /// The encoded form of this block's `BlockState`. See `Block::state`.
+    state: u32,
 }
 
 impl Block {
-    /// Creates a new block.
+    /// Creates a new block from a raw, encoded state.
+    ///
+    /// A raw state of `0` is always the block type's default state. To build
+    /// other states, see `Block::with_state`.
     pub const fn new(block_type: BlockType, state: u32) -> Self {
         Self { block_type, state }
     }
 
+    /// Creates a new block from a typed state, checking that the state
+    /// belongs to this block type.
+    pub fn with_state(block_type: BlockType, state: &BlockState) -> Result<Self, BlockStateError> {
+        let raw = state.encode();
+
+        // make sure the state decodes the same way for this type
+        if BlockState::decode(block_type, block_type.state_schema(), raw)? != *state {
+            return Err(BlockStateError::UndecodableState {
+                block_type,
+                state: raw,
+            });
+        }
+
+        Ok(Self::new(block_type, raw))
+    }
+
+    /// The raw, encoded state of this block, as it's stored on disk.
+    pub const fn raw_state(&self) -> u32 {
+        self.state
+    }
+
+    /// Decodes this block's state using its type's schema.
+    pub fn state(&self) -> Result<BlockState, BlockStateError> {
+        BlockState::decode(self.block_type, self.block_type.state_schema(), self.state)
+    }
+
+    /// Gets the value of one of this block's properties.
+    ///
+    /// Returns `None` if the block doesn't have that property or its state
+    /// can't be decoded.
+    pub fn get(&self, property: Property) -> Option<PropertyValue> {
+        self.state().ok()?.get(property)
+    }
+
+    /// Sets the value of one of this block's properties.
+    ///
+    /// The block is left untouched if the value is out of range or the block
+    /// doesn't have that property.
+    pub fn set(&mut self, value: PropertyValue) -> Result<(), BlockStateError> {
+        let mut state = self.state()?;
+        state.set(self.block_type, value)?;
+
+        self.state = state.encode();
+        Ok(())
+    }
+
     /// If a block type is transparent, like air, liquids, or glass, this
     /// method will let you know.
-    pub const fn is_transparent(&self) -> bool {
-        // if it's one of these, then yes.
-        // otherwise, no.
-        matches!(self.block_type, BlockType::Air | BlockType::Water)
+    ///
+    /// Unregistered blocks are treated as opaque.
+    pub fn is_transparent(&self) -> bool {
+        self.block_type.definition().is_some_and(|d| d.transparent)
+    }
+
+    /// Whether entities collide with this block.
+    ///
+    /// Unregistered blocks are treated as solid.
+    pub fn is_solid(&self) -> bool {
+        self.block_type.definition().is_none_or(|d| d.solid)
+    }
+
+    /// How much light this block gives off, from 0 to 15.
+    pub fn light_emission(&self) -> u8 {
+        self.block_type.definition().map_or(0, |d| d.light_emission)
+    }
+
+    /// How much this block dims light passing through it, from 0 to 15.
+    ///
+    /// Unregistered blocks block all light.
+    pub fn light_opacity(&self) -> u8 {
+        self.block_type
+            .definition()
+            .map_or(15, |d| d.light_opacity())
     }
 
     /// Checks to see if this block has the same kind and state as another block.
+    ///
+    /// Properties that don't change how a block looks are ignored, so these
+    /// blocks can be meshed together.
     pub fn same_kind_as(&self, other: &Block) -> bool {
-        self.block_type == other.block_type && self.state == other.state
+        let mask = state::visual_mask(self.block_type.state_schema());
+        self.block_type == other.block_type && self.state & mask == other.state & mask
     }
 }
 
 /// A type (mostly material) of block.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize)]
-#[allow(unused)]
-pub enum BlockType {
-    Air,
-    Water,
-    Stone,
-    Log,
-    Dirt,
-    Grass,
-    Sand,
-    Sandstone,
-    Ice,
-    Leaves,
+///
+/// This is the block's stable numeric id. Everything else about it lives in
+/// its `BlockDefinition`, found in the `BlockRegistry`.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct BlockType(u16);
+
+impl BlockType {
+    pub const AIR: BlockType = BlockType(0);
+    pub const WATER: BlockType = BlockType(1);
+    pub const STONE: BlockType = BlockType(2);
+    pub const LOG: BlockType = BlockType(3);
+    pub const DIRT: BlockType = BlockType(4);
+    pub const GRASS: BlockType = BlockType(5);
+    pub const SAND: BlockType = BlockType(6);
+    pub const SANDSTONE: BlockType = BlockType(7);
+    pub const ICE: BlockType = BlockType(8);
+    pub const LEAVES: BlockType = BlockType(9);
+    pub const CHEST: BlockType = BlockType(10);
+    pub const FURNACE: BlockType = BlockType(11);
+    pub const SIGN: BlockType = BlockType(12);
+    pub const NOTE_BLOCK: BlockType = BlockType(13);
+    pub const MOB_SPAWNER: BlockType = BlockType(14);
+    pub const TORCH: BlockType = BlockType(15);
+
+    /// Creates a `BlockType` from its numeric id.
+    pub const fn new(id: u16) -> Self {
+        Self(id)
+    }
+
+    /// The numeric id of this block type.
+    pub const fn id(&self) -> u16 {
+        self.0
+    }
+
+    /// This block type's definition in the global `BlockRegistry`, if it's
+    /// registered.
+    pub fn definition(&self) -> Option<&'static BlockDefinition> {
+        BlockRegistry::global().get(*self)
+    }
+
+    /// The ordered list of properties that make up this block type's state.
+    ///
+    /// Unregistered block types have no properties.
+    pub fn state_schema(&self) -> &'static [Property] {
+        self.definition().map_or(&[], |d| d.states.as_slice())
+    }
 }
 
-impl Default for BlockType {
-    fn default() -> Self {
-        Self::Air
+impl Display for BlockType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.definition() {
+            Some(definition) => write!(f, "{}", definition.name),
+            None => write!(f, "#{}", self.0),
+        }
     }
 }
 
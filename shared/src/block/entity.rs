@@ -0,0 +1,155 @@
+//! # Entity
+//!
+//! Block entities (a.k.a. tile entities) hold the data that a block's `u32`
+//! state can't, like a chest's items or a sign's text.
+//!
+//! A block's `BlockDefinition` says which kind of block entity it has, if
+//! any. Chunks create and remove them as those blocks are placed and broken.
+
+use serde::{Deserialize, Serialize};
+
+/// The data attached to a single block in the world.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub enum BlockEntity {
+    Chest(Inventory),
+    Furnace(Furnace),
+    Sign(Sign),
+    NoteBlock(NoteBlock),
+    MobSpawner(MobSpawner),
+}
+
+impl BlockEntity {
+    /// The kind of this block entity.
+    pub const fn kind(&self) -> BlockEntityKind {
+        match self {
+            BlockEntity::Chest(_) => BlockEntityKind::Chest,
+            BlockEntity::Furnace(_) => BlockEntityKind::Furnace,
+            BlockEntity::Sign(_) => BlockEntityKind::Sign,
+            BlockEntity::NoteBlock(_) => BlockEntityKind::NoteBlock,
+            BlockEntity::MobSpawner(_) => BlockEntityKind::MobSpawner,
+        }
+    }
+}
+
+/// The kinds of block entity. Block definitions refer to these by name, like
+/// `block_entity = "chest"`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockEntityKind {
+    Chest,
+    Furnace,
+    Sign,
+    NoteBlock,
+    MobSpawner,
+}
+
+impl BlockEntityKind {
+    /// Creates a fresh block entity of this kind, like the one made when its
+    /// block is first placed.
+    pub fn create(&self) -> BlockEntity {
+        match self {
+            BlockEntityKind::Chest => BlockEntity::Chest(Inventory::new(Inventory::CHEST_SIZE)),
+            BlockEntityKind::Furnace => BlockEntity::Furnace(Furnace::default()),
+            BlockEntityKind::Sign => BlockEntity::Sign(Sign::default()),
+            BlockEntityKind::NoteBlock => BlockEntity::NoteBlock(NoteBlock::default()),
+            BlockEntityKind::MobSpawner => BlockEntity::MobSpawner(MobSpawner::default()),
+        }
+    }
+}
+
+/// A stack of items sitting in an inventory slot.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct ItemStack {
+    /// The numeric id of the item.
+    pub item: u16,
+    /// How many items are in the stack.
+    pub count: u8,
+    /// The item's damage, or its variant for items like dyes.
+    pub damage: u16,
+}
+
+/// A fixed number of item slots.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+impl Inventory {
+    /// The number of slots in a single chest.
+    pub const CHEST_SIZE: usize = 27;
+
+    /// The number of slots a player carries, including their hotbar.
+    pub const PLAYER_SIZE: usize = 36;
+
+    /// Creates an empty inventory with the given number of slots.
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+        }
+    }
+
+    /// Gets the stack in a slot, if there is one.
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// Puts a stack into a slot, returning whatever was there before.
+    ///
+    /// Slots outside of the inventory are ignored.
+    pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) -> Option<ItemStack> {
+        let existing = self.slots.get_mut(slot)?;
+        std::mem::replace(existing, stack)
+    }
+
+    /// All slots in the inventory, in order.
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+}
+
+/// A furnace's items and progress.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Furnace {
+    pub input: Option<ItemStack>,
+    pub fuel: Option<ItemStack>,
+    pub output: Option<ItemStack>,
+    /// Ticks left until the current fuel runs out.
+    pub burn_time: u16,
+    /// Ticks spent cooking the current input.
+    pub cook_time: u16,
+}
+
+/// The four lines of text on a sign.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct Sign {
+    pub lines: [String; 4],
+}
+
+/// The pitch a note block plays.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct NoteBlock {
+    /// The note, from 0 to 24.
+    pub note: u8,
+}
+
+/// What a mob spawner spawns, and when.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct MobSpawner {
+    /// The name of the mob to spawn, like `pig`.
+    pub mob: String,
+    /// Ticks until the next spawn attempt.
+    pub delay: u16,
+}
+
+impl Default for MobSpawner {
+    fn default() -> Self {
+        Self {
+            mob: "pig".into(),
+            delay: 20,
+        }
+    }
+}
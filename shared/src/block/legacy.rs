@@ -0,0 +1,212 @@
+//! # Legacy
+//!
+//! Maps Minecraft Beta 1.7.3's numeric block ids and data values onto Macaw
+//! blocks and back, for importing and exporting old worlds and structures.
+//!
+//! Beta stores every block as a `u8` id, plus four bits of 'data' that mean
+//! something different for each id. Only blocks with a Macaw equivalent can
+//! be mapped. Everything else is left for the caller to report.
+
+use super::{
+    state::{Facing, Property, PropertyValue},
+    Block, BlockType,
+};
+
+/// Beta's numeric block ids for blocks that Macaw also has.
+pub mod ids {
+    pub const AIR: u8 = 0;
+    pub const STONE: u8 = 1;
+    pub const GRASS: u8 = 2;
+    pub const DIRT: u8 = 3;
+    pub const FLOWING_WATER: u8 = 8;
+    pub const WATER: u8 = 9;
+    pub const SAND: u8 = 12;
+    pub const LOG: u8 = 17;
+    pub const LEAVES: u8 = 18;
+    pub const SANDSTONE: u8 = 24;
+    pub const NOTE_BLOCK: u8 = 25;
+    pub const TORCH: u8 = 50;
+    pub const MOB_SPAWNER: u8 = 52;
+    pub const CHEST: u8 = 54;
+    pub const FURNACE: u8 = 61;
+    pub const LIT_FURNACE: u8 = 62;
+    pub const SIGN_POST: u8 = 63;
+    pub const WALL_SIGN: u8 = 68;
+    pub const ICE: u8 = 79;
+}
+
+/// Finds the Macaw block for a Beta block id and its data.
+///
+/// Gives back `None` for blocks Macaw doesn't have.
+///
+/// ```
+/// # use shared::block::{legacy, state::{Property, PropertyValue}, BlockType};
+/// #
+/// let water = legacy::from_legacy(9, 0b1010).unwrap();
+/// assert_eq!(water.block_type, BlockType::WATER);
+/// assert_eq!(water.get(Property::FluidLevel), Some(PropertyValue::FluidLevel(2)));
+/// assert_eq!(water.get(Property::Falling), Some(PropertyValue::Falling(true)));
+///
+/// assert_eq!(legacy::from_legacy(35, 14), None); // red wool
+/// ```
+pub fn from_legacy(id: u8, data: u8) -> Option<Block> {
+    let (block_type, values) = match id {
+        ids::AIR => (BlockType::AIR, vec![]),
+        ids::STONE => (BlockType::STONE, vec![]),
+        ids::GRASS => (BlockType::GRASS, vec![]),
+        ids::DIRT => (BlockType::DIRT, vec![]),
+        ids::FLOWING_WATER | ids::WATER => (
+            BlockType::WATER,
+            vec![
+                PropertyValue::FluidLevel(data & 0x7),
+                PropertyValue::Falling(data & 0x8 != 0),
+            ],
+        ),
+        ids::SAND => (BlockType::SAND, vec![]),
+        // the data is the kind of wood, which Macaw doesn't have yet
+        ids::LOG => (BlockType::LOG, vec![]),
+        ids::LEAVES => (
+            BlockType::LEAVES,
+            vec![PropertyValue::CheckDecay(data & 0x8 != 0)],
+        ),
+        ids::SANDSTONE => (BlockType::SANDSTONE, vec![]),
+        ids::NOTE_BLOCK => (BlockType::NOTE_BLOCK, vec![]),
+        ids::TORCH => (
+            BlockType::TORCH,
+            vec![PropertyValue::Facing(match data {
+                1 => Facing::East,
+                2 => Facing::West,
+                3 => Facing::South,
+                4 => Facing::North,
+                _ => Facing::Up,
+            })],
+        ),
+        ids::MOB_SPAWNER => (BlockType::MOB_SPAWNER, vec![]),
+        // chests in Beta don't face anywhere in particular
+        ids::CHEST => (BlockType::CHEST, vec![]),
+        ids::FURNACE | ids::LIT_FURNACE => (
+            BlockType::FURNACE,
+            vec![PropertyValue::Facing(wall_facing(data))],
+        ),
+        ids::SIGN_POST => {
+            // sign posts can face 16 ways, so round to the nearest of four
+            let facing = match (data + 2) / 4 % 4 {
+                0 => Facing::South,
+                1 => Facing::West,
+                2 => Facing::North,
+                _ => Facing::East,
+            };
+            (BlockType::SIGN, vec![PropertyValue::Facing(facing)])
+        }
+        ids::WALL_SIGN => (
+            BlockType::SIGN,
+            vec![PropertyValue::Facing(wall_facing(data))],
+        ),
+        ids::ICE => (BlockType::ICE, vec![]),
+        _ => return None,
+    };
+
+    let mut block = Block::new(block_type, 0);
+    for value in values {
+        block.set(value).ok()?;
+    }
+
+    Some(block)
+}
+
+/// Finds the Beta block id and data for a Macaw block.
+///
+/// Gives back `None` for blocks Beta doesn't have.
+///
+/// ```
+/// # use shared::block::{legacy, state::PropertyValue, Block, BlockType};
+/// #
+/// let mut water = Block::new(BlockType::WATER, 0);
+/// assert_eq!(legacy::to_legacy(&water), Some((9, 0)));
+///
+/// water.set(PropertyValue::FluidLevel(2)).unwrap();
+/// assert_eq!(legacy::to_legacy(&water), Some((8, 2)));
+/// ```
+pub fn to_legacy(block: &Block) -> Option<(u8, u8)> {
+    let facing = match block.get(Property::Facing) {
+        Some(PropertyValue::Facing(facing)) => Some(facing),
+        _ => None,
+    };
+    let flag = |property: Property| {
+        matches!(
+            block.get(property),
+            Some(PropertyValue::Falling(true) | PropertyValue::CheckDecay(true))
+        )
+    };
+
+    let legacy = match block.block_type {
+        BlockType::AIR => (ids::AIR, 0),
+        BlockType::STONE => (ids::STONE, 0),
+        BlockType::GRASS => (ids::GRASS, 0),
+        BlockType::DIRT => (ids::DIRT, 0),
+        BlockType::WATER => {
+            let level = match block.get(Property::FluidLevel) {
+                Some(PropertyValue::FluidLevel(level)) => level & 0x7,
+                _ => 0,
+            };
+            let falling = flag(Property::Falling);
+
+            // only untouched sources count as still water
+            if level == 0 && !falling {
+                (ids::WATER, 0)
+            } else {
+                (ids::FLOWING_WATER, level | ((falling as u8) << 3))
+            }
+        }
+        BlockType::SAND => (ids::SAND, 0),
+        BlockType::LOG => (ids::LOG, 0),
+        BlockType::LEAVES => (ids::LEAVES, (flag(Property::CheckDecay) as u8) << 3),
+        BlockType::SANDSTONE => (ids::SANDSTONE, 0),
+        BlockType::NOTE_BLOCK => (ids::NOTE_BLOCK, 0),
+        BlockType::TORCH => {
+            let data = match facing {
+                Some(Facing::East) => 1,
+                Some(Facing::West) => 2,
+                Some(Facing::South) => 3,
+                Some(Facing::North) => 4,
+                _ => 5,
+            };
+            (ids::TORCH, data)
+        }
+        BlockType::MOB_SPAWNER => (ids::MOB_SPAWNER, 0),
+        BlockType::CHEST => (ids::CHEST, 0),
+        BlockType::FURNACE => {
+            let data = match facing {
+                Some(Facing::South) => 3,
+                Some(Facing::West) => 4,
+                Some(Facing::East) => 5,
+                _ => 2,
+            };
+            (ids::FURNACE, data)
+        }
+        // Macaw signs don't know if they're on a wall, so they all stand up
+        BlockType::SIGN => {
+            let data = match facing {
+                Some(Facing::West) => 4,
+                Some(Facing::North) => 8,
+                Some(Facing::East) => 12,
+                _ => 0,
+            };
+            (ids::SIGN_POST, data)
+        }
+        BlockType::ICE => (ids::ICE, 0),
+        _ => return None,
+    };
+
+    Some(legacy)
+}
+
+/// The facing of blocks placed against walls, like furnaces and wall signs.
+fn wall_facing(data: u8) -> Facing {
+    match data {
+        3 => Facing::South,
+        4 => Facing::West,
+        5 => Facing::East,
+        _ => Facing::North,
+    }
+}
@@ -0,0 +1,523 @@
+//! # Registry
+//!
+//! A data-driven list of every kind of block in the game.
+//!
+//! Each block has a namespaced name (like `macaw:stone`), a stable numeric id
+//! that chunks store on disk, and a set of properties. The registry starts
+//! out with Macaw's built-in blocks and can be extended with TOML files that
+//! look like this:
+//!
+//! ```toml
+//! [[block]]
+//! id = 200
+//! name = "my_mod:marble"
+//! hardness = 1.5
+//! sounds = "stone"
+//! textures = { all = "marble.png" }
+//! ```
+
+use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{entity::BlockEntityKind, state::Property, BlockSide, BlockType};
+
+/// The built-in block definitions.
+const BUILTIN_BLOCKS: &str = include_str!("blocks.toml");
+
+/// The registry that `Block` methods query. See `BlockRegistry::global`.
+static GLOBAL_REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+/// Maps block names and numeric ids to their definitions.
+#[derive(Clone, Debug, Default)]
+pub struct BlockRegistry {
+    definitions: HashMap<BlockType, BlockDefinition>,
+    names: HashMap<BlockName, BlockType>,
+}
+
+impl BlockRegistry {
+    /// Creates an empty registry. You probably want
+    /// `BlockRegistry::with_builtins` instead!
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing all of Macaw's built-in blocks.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry
+            .load_toml_str(BUILTIN_BLOCKS)
+            .expect("built-in block definitions should be valid");
+        registry
+    }
+
+    /// The registry used by the whole game.
+    ///
+    /// If nothing has been installed with `BlockRegistry::install`, this is
+    /// a registry of the built-in blocks.
+    pub fn global() -> &'static BlockRegistry {
+        GLOBAL_REGISTRY.get_or_init(Self::with_builtins)
+    }
+
+    /// Makes this registry the global one.
+    ///
+    /// This can only happen once, and must happen before anything asks for
+    /// `BlockRegistry::global`.
+    pub fn install(self) -> Result<(), RegistryError> {
+        GLOBAL_REGISTRY
+            .set(self)
+            .map_err(|_| RegistryError::AlreadyInstalled)
+    }
+
+    /// Adds a new block definition to the registry.
+    ///
+    /// This fails if its id or name is already taken, or if its properties
+    /// are out of range.
+    pub fn register(&mut self, definition: BlockDefinition) -> Result<BlockType, RegistryError> {
+        self.check(&definition)?;
+        Ok(self.insert(definition))
+    }
+
+    /// Registers every block in a TOML string, returning their types.
+    ///
+    /// If any of them can't be registered, none of them are.
+    pub fn load_toml_str(&mut self, s: &str) -> Result<Vec<BlockType>, RegistryError> {
+        let definitions = parse_toml_str(s)?;
+        self.register_all(definitions)
+    }
+
+    /// Registers every block in a TOML file, returning their types.
+    ///
+    /// If any of them can't be registered, none of them are.
+    pub fn load_toml_file(&mut self, path: &Path) -> Result<Vec<BlockType>, RegistryError> {
+        let definitions = parse_toml_file(path)?;
+        self.register_all(definitions)
+    }
+
+    /// Registers the blocks in every `.toml` file within a directory.
+    ///
+    /// Files are loaded in name order, so errors are reported consistently.
+    /// If any block in any of them can't be registered, none of them are.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<Vec<BlockType>, RegistryError> {
+        let entries = std::fs::read_dir(dir).map_err(|e| {
+            RegistryError::ReadFailed(format!("`{}`: {}", dir.to_string_lossy(), e))
+        })?;
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut definitions = Vec::new();
+        for path in paths {
+            definitions.extend(parse_toml_file(&path)?);
+        }
+
+        self.register_all(definitions)
+    }
+
+    /// Gets the definition of a block type, if it's registered.
+    pub fn get(&self, block_type: BlockType) -> Option<&BlockDefinition> {
+        self.definitions.get(&block_type)
+    }
+
+    /// Finds a block type by its namespaced name, like `macaw:stone`.
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        let name = BlockName::from_str(name).ok()?;
+        self.names.get(&name).copied()
+    }
+
+    /// Iterates over every registered definition, in no particular order.
+    pub fn definitions(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.values()
+    }
+
+    /// Registers a batch of definitions all at once, after checking every one
+    /// of them against the registry and each other.
+    fn register_all(
+        &mut self,
+        definitions: Vec<BlockDefinition>,
+    ) -> Result<Vec<BlockType>, RegistryError> {
+        let mut batch = Self::new();
+        for definition in &definitions {
+            self.check(definition)?;
+            batch.check(definition)?;
+            batch.insert(definition.clone());
+        }
+
+        Ok(definitions
+            .into_iter()
+            .map(|definition| self.insert(definition))
+            .collect())
+    }
+
+    /// Makes sure a definition can be registered: its id and name are free,
+    /// and its properties are in range.
+    fn check(&self, definition: &BlockDefinition) -> Result<(), RegistryError> {
+        let name = || definition.name.clone();
+
+        if let Some(existing) = self.definitions.get(&definition.block_type()) {
+            return Err(RegistryError::DuplicateId {
+                id: definition.id,
+                existing: existing.name.clone(),
+                new: name(),
+            });
+        }
+
+        if self.names.contains_key(&definition.name) {
+            return Err(RegistryError::DuplicateName(name()));
+        }
+
+        if definition.light_emission > 15 {
+            return Err(RegistryError::InvalidProperty {
+                name: name(),
+                reason: format!(
+                    "light emission must be at most 15, but it's {}",
+                    definition.light_emission
+                ),
+            });
+        }
+
+        if definition.light_opacity.is_some_and(|o| o > 15) {
+            return Err(RegistryError::InvalidProperty {
+                name: name(),
+                reason: format!(
+                    "light opacity must be at most 15, but it's {}",
+                    definition.light_opacity.unwrap_or_default()
+                ),
+            });
+        }
+
+        let state_bits: u32 = definition.states.iter().map(|p| p.bits()).sum();
+        if state_bits > u32::BITS {
+            return Err(RegistryError::InvalidProperty {
+                name: name(),
+                reason: format!("its states need {state_bits} bits, but only 32 are available"),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Adds a definition that's already been checked.
+    fn insert(&mut self, definition: BlockDefinition) -> BlockType {
+        let block_type = definition.block_type();
+
+        tracing::debug!(
+            "Registered block `{}` as {}",
+            definition.name,
+            definition.id
+        );
+        self.names.insert(definition.name.clone(), block_type);
+        self.definitions.insert(block_type, definition);
+        block_type
+    }
+}
+
+/// Everything the game knows about a kind of block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockDefinition {
+    /// The stable numeric id stored in chunks.
+    pub id: u16,
+    /// The unique, namespaced name of the block.
+    pub name: BlockName,
+    /// Whether entities collide with this block.
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Whether you can see through this block, like air, liquids, or glass.
+    #[serde(default)]
+    pub transparent: bool,
+    /// How much light this block gives off, from 0 to 15.
+    #[serde(default)]
+    pub light_emission: u8,
+    /// How much this block dims light passing through it, from 0 to 15.
+    ///
+    /// When left out, transparent blocks let all light through and other
+    /// blocks stop it entirely.
+    #[serde(default)]
+    pub light_opacity: Option<u8>,
+    /// How long this block takes to break.
+    #[serde(default)]
+    pub hardness: f32,
+    /// The textures drawn on this block's faces.
+    #[serde(default)]
+    pub textures: BlockTextures,
+    /// The sounds made when walking on, breaking, or placing this block.
+    #[serde(default)]
+    pub sounds: Option<SoundGroup>,
+    /// The ordered list of properties making up this block's state.
+    #[serde(default)]
+    pub states: Vec<Property>,
+    /// The kind of block entity that's attached to this block, if any.
+    #[serde(default)]
+    pub block_entity: Option<BlockEntityKind>,
+}
+
+impl BlockDefinition {
+    /// The `BlockType` that refers to this definition.
+    pub fn block_type(&self) -> BlockType {
+        BlockType::new(self.id)
+    }
+
+    /// How much this block dims light passing through it, from 0 to 15.
+    pub fn light_opacity(&self) -> u8 {
+        match self.light_opacity {
+            Some(opacity) => opacity,
+            None if self.transparent => 0,
+            None => 15,
+        }
+    }
+}
+
+/// The on-disk layout of a block definition file.
+#[derive(Deserialize)]
+struct BlockDefinitionFile {
+    #[serde(default)]
+    block: Vec<BlockDefinition>,
+}
+
+/// Reads the block definitions out of a TOML string.
+fn parse_toml_str(s: &str) -> Result<Vec<BlockDefinition>, RegistryError> {
+    let file: BlockDefinitionFile =
+        toml::from_str(s).map_err(|e| RegistryError::ParseFailed(e.to_string()))?;
+    Ok(file.block)
+}
+
+/// Reads the block definitions out of a TOML file.
+fn parse_toml_file(path: &Path) -> Result<Vec<BlockDefinition>, RegistryError> {
+    let s = std::fs::read_to_string(path)
+        .map_err(|e| RegistryError::ReadFailed(format!("`{}`: {}", path.to_string_lossy(), e)))?;
+    parse_toml_str(&s)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The textures for each face of a block. Paths are relative to the game's
+/// assets folder.
+///
+/// `all` is used for any face that doesn't have its own texture.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockTextures {
+    pub all: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub side: Option<String>,
+}
+
+impl BlockTextures {
+    /// Gets the texture for the given face, if there is one.
+    pub fn for_side(&self, side: &BlockSide) -> Option<&str> {
+        let specific = match side {
+            BlockSide::PositiveY => &self.top,
+            BlockSide::NegativeY => &self.bottom,
+            _ => &self.side,
+        };
+
+        specific.as_ref().or(self.all.as_ref()).map(|s| s.as_str())
+    }
+}
+
+/// A set of sounds shared between similar blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundGroup {
+    Stone,
+    Wood,
+    Gravel,
+    Grass,
+    Sand,
+    Glass,
+    Cloth,
+    Metal,
+}
+
+/// A namespaced block name, like `macaw:stone`.
+///
+/// Both parts may only contain lowercase letters, digits, `_`, `-`, and `.`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BlockName {
+    namespace: String,
+    path: String,
+}
+
+impl BlockName {
+    /// The part before the colon, like `macaw`.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The part after the colon, like `stone`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl FromStr for BlockName {
+    type Err = RegistryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part.chars().all(|c| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.')
+                })
+        };
+
+        match s.split_once(':') {
+            Some((namespace, path)) if valid(namespace) && valid(path) => Ok(Self {
+                namespace: namespace.to_owned(),
+                path: path.to_owned(),
+            }),
+            _ => Err(RegistryError::InvalidName(s.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<String> for BlockName {
+    type Error = RegistryError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl From<BlockName> for String {
+    fn from(value: BlockName) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for BlockName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+/// An error while building a `BlockRegistry`.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum RegistryError {
+    #[error("Block id `{id}` is used by both `{existing}` and `{new}`.")]
+    DuplicateId {
+        id: u16,
+        existing: BlockName,
+        new: BlockName,
+    },
+    #[error("A block named `{0}` is already registered.")]
+    DuplicateName(BlockName),
+    #[error("`{0}` isn't a valid block name. Names look like `namespace:block`.")]
+    InvalidName(String),
+    #[error("Block `{name}` is invalid: {reason}")]
+    InvalidProperty { name: BlockName, reason: String },
+    #[error("Failed to parse block definitions: `{0}`")]
+    ParseFailed(String),
+    #[error("Failed to read block definitions from {0}")]
+    ReadFailed(String),
+    #[error("A global block registry has already been installed.")]
+    AlreadyInstalled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockRegistry, RegistryError};
+    use crate::block::{state::Property, BlockSide, BlockType};
+
+    #[test]
+    fn builtins_match_constants() {
+        let registry = BlockRegistry::with_builtins();
+
+        for (block_type, name) in [
+            (BlockType::AIR, "macaw:air"),
+            (BlockType::WATER, "macaw:water"),
+            (BlockType::STONE, "macaw:stone"),
+            (BlockType::LOG, "macaw:log"),
+            (BlockType::DIRT, "macaw:dirt"),
+            (BlockType::GRASS, "macaw:grass"),
+            (BlockType::SAND, "macaw:sand"),
+            (BlockType::SANDSTONE, "macaw:sandstone"),
+            (BlockType::ICE, "macaw:ice"),
+            (BlockType::LEAVES, "macaw:leaves"),
+            (BlockType::CHEST, "macaw:chest"),
+            (BlockType::FURNACE, "macaw:furnace"),
+            (BlockType::SIGN, "macaw:sign"),
+            (BlockType::NOTE_BLOCK, "macaw:note_block"),
+            (BlockType::MOB_SPAWNER, "macaw:mob_spawner"),
+            (BlockType::TORCH, "macaw:torch"),
+        ] {
+            assert_eq!(registry.by_name(name), Some(block_type));
+        }
+
+        let grass = registry.get(BlockType::GRASS).unwrap();
+        assert_eq!(
+            grass.textures.for_side(&BlockSide::PositiveY),
+            Some("grass.png")
+        );
+        assert_eq!(
+            grass.textures.for_side(&BlockSide::PositiveX),
+            Some("grass_side.png")
+        );
+        assert_eq!(grass.states, vec![Property::Snowy]);
+    }
+
+    #[test]
+    fn toml_blocks() {
+        let mut registry = BlockRegistry::with_builtins();
+
+        let added = registry
+            .load_toml_str(
+                r#"
+                [[block]]
+                id = 200
+                name = "test:glowstone"
+                light_emission = 15
+                transparent = true
+                "#,
+            )
+            .unwrap();
+
+        let glowstone = registry.get(added[0]).unwrap();
+        assert!(glowstone.solid);
+        assert!(glowstone.transparent);
+        assert_eq!(glowstone.light_emission, 15);
+
+        // ids and names can't be reused
+        let duplicate_id = registry.load_toml_str("[[block]]\nid = 2\nname = \"test:rock\"");
+        assert!(matches!(
+            duplicate_id,
+            Err(RegistryError::DuplicateId { .. })
+        ));
+
+        let bad_name = registry.load_toml_str("[[block]]\nid = 201\nname = \"Rock\"");
+        assert!(matches!(bad_name, Err(RegistryError::ParseFailed(_))));
+
+        // one bad block keeps the rest of its file out too
+        let half_bad = registry.load_toml_str(
+            r#"
+            [[block]]
+            id = 202
+            name = "test:marble"
+
+            [[block]]
+            id = 203
+            name = "test:lamp"
+            light_emission = 16
+            "#,
+        );
+        assert!(matches!(
+            half_bad,
+            Err(RegistryError::InvalidProperty { .. })
+        ));
+        assert_eq!(registry.by_name("test:marble"), None);
+
+        // and so does a block clashing with another in the same file
+        let clashing = registry.load_toml_str(
+            "[[block]]\nid = 204\nname = \"test:slate\"\n[[block]]\nid = 204\nname = \"test:shale\"",
+        );
+        assert!(matches!(clashing, Err(RegistryError::DuplicateId { .. })));
+        assert_eq!(registry.by_name("test:slate"), None);
+    }
+}
@@ -0,0 +1,402 @@
+//! # State
+//!
+//! Typed block states.
+//!
+//! On disk, a block's state is just a `u32`. Each `BlockType` has a schema -
+//! an ordered list of `Property`s - that says what those bits mean. The
+//! properties are packed one after another, starting at the lowest bit, so a
+//! state of `0` is always a block's default state.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::BlockType;
+
+/// A property that a block's state can have.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Property {
+    /// The direction a block faces, like a furnace or door.
+    Facing,
+    /// The axis a block lies along, like a log on its side.
+    Axis,
+    /// How far a fluid has flowed from its source. `0` is a source block.
+    FluidLevel,
+    /// Whether a fluid is falling down from above.
+    Falling,
+    /// The amount of snow layers, from 1 to 8.
+    SnowLayers,
+    /// How far a plant has grown, from 0 to 7.
+    GrowthStage,
+    /// Whether a block is powered by redstone.
+    Powered,
+    /// Whether a block (like a door or trapdoor) is open.
+    Open,
+    /// Which half of a two-block-tall block this is.
+    Half,
+    /// Whether leaves should check if they're still attached to a log.
+    CheckDecay,
+    /// Whether a block has snow on top of it.
+    Snowy,
+}
+
+impl Property {
+    /// The amount of bits this property takes up in an encoded state.
+    pub const fn bits(&self) -> u32 {
+        match self {
+            Property::Facing
+            | Property::FluidLevel
+            | Property::SnowLayers
+            | Property::GrowthStage => 3,
+            Property::Axis => 2,
+            Property::Falling
+            | Property::Powered
+            | Property::Open
+            | Property::Half
+            | Property::CheckDecay
+            | Property::Snowy => 1,
+        }
+    }
+
+    /// Whether two blocks that differ in this property look different.
+    ///
+    /// Blocks that only differ in non-visual properties can be meshed
+    /// together.
+    pub const fn is_visual(&self) -> bool {
+        !matches!(self, Property::CheckDecay)
+    }
+
+    /// The value this property has in a block's default state.
+    pub const fn default_value(&self) -> PropertyValue {
+        match self {
+            Property::Facing => PropertyValue::Facing(Facing::North),
+            Property::Axis => PropertyValue::Axis(Axis::Y),
+            Property::FluidLevel => PropertyValue::FluidLevel(0),
+            Property::Falling => PropertyValue::Falling(false),
+            Property::SnowLayers => PropertyValue::SnowLayers(1),
+            Property::GrowthStage => PropertyValue::GrowthStage(0),
+            Property::Powered => PropertyValue::Powered(false),
+            Property::Open => PropertyValue::Open(false),
+            Property::Half => PropertyValue::Half(Half::Lower),
+            Property::CheckDecay => PropertyValue::CheckDecay(false),
+            Property::Snowy => PropertyValue::Snowy(false),
+        }
+    }
+
+    /// A mask covering this property's bits, before shifting.
+    const fn mask(&self) -> u32 {
+        (1 << self.bits()) - 1
+    }
+}
+
+/// The value of a single `Property`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub enum PropertyValue {
+    Facing(Facing),
+    Axis(Axis),
+    FluidLevel(u8),
+    Falling(bool),
+    SnowLayers(u8),
+    GrowthStage(u8),
+    Powered(bool),
+    Open(bool),
+    Half(Half),
+    CheckDecay(bool),
+    Snowy(bool),
+}
+
+impl PropertyValue {
+    /// The property this is a value of.
+    pub const fn property(&self) -> Property {
+        match self {
+            PropertyValue::Facing(_) => Property::Facing,
+            PropertyValue::Axis(_) => Property::Axis,
+            PropertyValue::FluidLevel(_) => Property::FluidLevel,
+            PropertyValue::Falling(_) => Property::Falling,
+            PropertyValue::SnowLayers(_) => Property::SnowLayers,
+            PropertyValue::GrowthStage(_) => Property::GrowthStage,
+            PropertyValue::Powered(_) => Property::Powered,
+            PropertyValue::Open(_) => Property::Open,
+            PropertyValue::Half(_) => Property::Half,
+            PropertyValue::CheckDecay(_) => Property::CheckDecay,
+            PropertyValue::Snowy(_) => Property::Snowy,
+        }
+    }
+
+    /// Checks that this value is in range for its property.
+    pub const fn is_valid(&self) -> bool {
+        match self {
+            PropertyValue::FluidLevel(level) => *level <= 7,
+            PropertyValue::SnowLayers(layers) => *layers >= 1 && *layers <= 8,
+            PropertyValue::GrowthStage(stage) => *stage <= 7,
+            _ => true,
+        }
+    }
+
+    /// Encodes this value into its property's bits (unshifted).
+    fn encode(&self) -> u32 {
+        match *self {
+            PropertyValue::Facing(facing) => facing as u32,
+            PropertyValue::Axis(axis) => axis as u32,
+            PropertyValue::FluidLevel(level) => level as u32,
+            PropertyValue::SnowLayers(layers) => layers as u32 - 1,
+            PropertyValue::GrowthStage(stage) => stage as u32,
+            PropertyValue::Half(half) => half as u32,
+            PropertyValue::Falling(b)
+            | PropertyValue::Powered(b)
+            | PropertyValue::Open(b)
+            | PropertyValue::CheckDecay(b)
+            | PropertyValue::Snowy(b) => b as u32,
+        }
+    }
+
+    /// Decodes a property's bits (unshifted) into a value.
+    fn decode(property: Property, bits: u32) -> Option<Self> {
+        let value = match property {
+            Property::Facing => PropertyValue::Facing(match bits {
+                0 => Facing::North,
+                1 => Facing::South,
+                2 => Facing::East,
+                3 => Facing::West,
+                4 => Facing::Up,
+                5 => Facing::Down,
+                _ => return None,
+            }),
+            Property::Axis => PropertyValue::Axis(match bits {
+                0 => Axis::Y,
+                1 => Axis::X,
+                2 => Axis::Z,
+                _ => return None,
+            }),
+            Property::Half => PropertyValue::Half(match bits {
+                0 => Half::Lower,
+                _ => Half::Upper,
+            }),
+            Property::FluidLevel => PropertyValue::FluidLevel(bits as u8),
+            Property::SnowLayers => PropertyValue::SnowLayers(bits as u8 + 1),
+            Property::GrowthStage => PropertyValue::GrowthStage(bits as u8),
+            Property::Falling => PropertyValue::Falling(bits == 1),
+            Property::Powered => PropertyValue::Powered(bits == 1),
+            Property::Open => PropertyValue::Open(bits == 1),
+            Property::CheckDecay => PropertyValue::CheckDecay(bits == 1),
+            Property::Snowy => PropertyValue::Snowy(bits == 1),
+        };
+
+        Some(value)
+    }
+}
+
+/// The six directions a block can face.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub enum Facing {
+    North,
+    South,
+    East,
+    West,
+    Up,
+    Down,
+}
+
+/// The axis a block lies along.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub enum Axis {
+    Y,
+    X,
+    Z,
+}
+
+/// One half of a two-block-tall block.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub enum Half {
+    Lower,
+    Upper,
+}
+
+/// A decoded block state: one value for each property in a schema, in order.
+#[derive(Clone, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub struct BlockState {
+    values: Vec<PropertyValue>,
+}
+
+impl BlockState {
+    /// The default state for a schema.
+    pub fn default_for(schema: &[Property]) -> Self {
+        Self {
+            values: schema.iter().map(|p| p.default_value()).collect(),
+        }
+    }
+
+    /// Decodes a raw state using the given schema.
+    ///
+    /// This fails if any bits are set outside of the schema, or if any
+    /// property's bits don't make a valid value.
+    pub fn decode(
+        block_type: BlockType,
+        schema: &[Property],
+        raw: u32,
+    ) -> Result<Self, BlockStateError> {
+        let undecodable = || BlockStateError::UndecodableState {
+            block_type,
+            state: raw,
+        };
+
+        let mut values = Vec::with_capacity(schema.len());
+        let mut shift = 0;
+
+        for property in schema {
+            let bits = (raw >> shift) & property.mask();
+            values.push(PropertyValue::decode(*property, bits).ok_or_else(undecodable)?);
+            shift += property.bits();
+        }
+
+        // leftover bits mean this state came from some other schema
+        if shift < u32::BITS && raw >> shift != 0 {
+            return Err(undecodable());
+        }
+
+        Ok(Self { values })
+    }
+
+    /// Encodes this state back into its raw form.
+    pub fn encode(&self) -> u32 {
+        let mut raw = 0;
+        let mut shift = 0;
+
+        for value in &self.values {
+            raw |= value.encode() << shift;
+            shift += value.property().bits();
+        }
+
+        raw
+    }
+
+    /// Gets the value of a property, if this state has it.
+    pub fn get(&self, property: Property) -> Option<PropertyValue> {
+        self.values
+            .iter()
+            .find(|v| v.property() == property)
+            .copied()
+    }
+
+    /// Sets the value of a property.
+    ///
+    /// This fails if the state doesn't have that property or the value is
+    /// out of range.
+    pub fn set(
+        &mut self,
+        block_type: BlockType,
+        value: PropertyValue,
+    ) -> Result<(), BlockStateError> {
+        if !value.is_valid() {
+            return Err(BlockStateError::InvalidValue(value));
+        }
+
+        let slot = self
+            .values
+            .iter_mut()
+            .find(|v| v.property() == value.property())
+            .ok_or(BlockStateError::UnknownProperty {
+                block_type,
+                property: value.property(),
+            })?;
+
+        *slot = value;
+        Ok(())
+    }
+
+    /// All values in this state, in schema order.
+    pub fn values(&self) -> &[PropertyValue] {
+        &self.values
+    }
+}
+
+/// A mask over the raw state bits that change how a block looks.
+pub fn visual_mask(schema: &[Property]) -> u32 {
+    let mut mask = 0;
+    let mut shift = 0;
+
+    for property in schema {
+        if property.is_visual() {
+            mask |= property.mask() << shift;
+        }
+        shift += property.bits();
+    }
+
+    mask
+}
+
+/// An error from reading or changing a block's state.
+#[derive(Clone, Copy, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum BlockStateError {
+    #[error("Blocks of type `{block_type}` don't have the `{property:?}` property.")]
+    UnknownProperty {
+        block_type: BlockType,
+        property: Property,
+    },
+    #[error("Property value `{0:?}` is out of range.")]
+    InvalidValue(PropertyValue),
+    #[error("State `{state:#x}` isn't valid for blocks of type `{block_type}`.")]
+    UndecodableState { block_type: BlockType, state: u32 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_state_is_zero() {
+        let schema = [Property::FluidLevel, Property::Falling];
+        let state = BlockState::default_for(&schema);
+
+        assert_eq!(state.encode(), 0);
+        assert_eq!(
+            BlockState::decode(BlockType::WATER, &schema, 0).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let schema = [Property::Facing, Property::Open, Property::Half];
+        let mut state = BlockState::default_for(&schema);
+
+        state
+            .set(BlockType::LOG, PropertyValue::Facing(Facing::West))
+            .unwrap();
+        state
+            .set(BlockType::LOG, PropertyValue::Half(Half::Upper))
+            .unwrap();
+
+        let decoded = BlockState::decode(BlockType::LOG, &schema, state.encode()).unwrap();
+        assert_eq!(
+            decoded.get(Property::Facing),
+            Some(PropertyValue::Facing(Facing::West))
+        );
+        assert_eq!(
+            decoded.get(Property::Open),
+            Some(PropertyValue::Open(false))
+        );
+        assert_eq!(
+            decoded.get(Property::Half),
+            Some(PropertyValue::Half(Half::Upper))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let schema = [Property::Axis];
+        let mut state = BlockState::default_for(&schema);
+
+        // out-of-range and missing properties
+        assert!(state
+            .set(BlockType::LOG, PropertyValue::GrowthStage(3))
+            .is_err());
+        assert!(BlockState::default_for(&[Property::SnowLayers])
+            .set(BlockType::LOG, PropertyValue::SnowLayers(9))
+            .is_err());
+
+        // axis only has three values, and nothing lives past its two bits
+        assert!(BlockState::decode(BlockType::LOG, &schema, 0b11).is_err());
+        assert!(BlockState::decode(BlockType::LOG, &schema, 0b100).is_err());
+    }
+}
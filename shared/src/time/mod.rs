@@ -1,2 +1,5 @@
 pub mod day;
 pub mod tick;
+
+pub use day::Day;
+pub use tick::GameTick;
@@ -2,13 +2,17 @@
 //!
 //! A module that handles game ticks.
 
-use std::ops::Add;
+use std::ops::{Add, AddAssign};
+
+use serde::{Deserialize, Serialize};
 
 use super::day::Day;
 
 /// A game tick. These power various game systems.
-#[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq, Hash)]
-struct GameTick {
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct GameTick {
     /// The current tick value. There are 20 ticks per second, and 24000 ticks
     /// per day.
     id: u16,
@@ -37,6 +41,17 @@ impl GameTick {
     pub fn day(&self) -> Day {
         self.day
     }
+
+    /// Creates a `GameTick` from the number of ticks since day 0 began.
+    pub fn from_ticks(ticks: u64) -> Self {
+        let per_day = Self::TICKS_PER_DAY as u64;
+        Self::new((ticks % per_day) as u16, ticks / per_day)
+    }
+
+    /// The number of ticks since day 0 began.
+    pub fn ticks(&self) -> u64 {
+        self.day.value() * Self::TICKS_PER_DAY as u64 + self.id as u64
+    }
 }
 
 impl Add<u16> for GameTick {
@@ -53,11 +68,18 @@ impl Add<u16> for GameTick {
     /// assert_eq!(tick.day(), Day::new(2));
     /// ```
     fn add(self, rhs: u16) -> Self::Output {
-        let id = self.id + rhs;
+        let ticks = self.id as u64 + rhs as u64;
+        let per_day = Self::TICKS_PER_DAY as u64;
 
         Self {
-            id: id % 24000,
-            day: Day::new((id / 24000) as u64),
+            id: (ticks % per_day) as u16,
+            day: self.day + ticks / per_day,
         }
     }
 }
+
+impl AddAssign<u16> for GameTick {
+    fn add_assign(&mut self, rhs: u16) {
+        *self = *self + rhs;
+    }
+}
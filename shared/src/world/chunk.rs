@@ -1,64 +1,122 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::block::{Block, BlockSide, BlockType};
+use serde::{Deserialize, Serialize};
 
-use super::{coordinates::BoundingBox, GlobalCoordinate};
+use crate::block::{entity::BlockEntity, Block, BlockSide, BlockType};
+
+use self::{
+    journal::{ChangeJournal, JournalKind},
+    light::{LightKind, LightStorage},
+    storage::BlockStorage,
+};
+use super::{
+    coordinates::{BoundingBox, ChunkCoordinate, RegionCoordinate},
+    error::WorldError,
+    GlobalCoordinate,
+};
 use crate::world::coordinates::ChunkBlockCoordinate;
 
+pub mod journal;
+pub mod light;
+pub mod palette;
+pub mod storage;
+
 /// The height, width, and *length* of all chunks.
 pub const CHUNK_LENGTH: u8 = 16;
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Hash, Ord, Serialize, Deserialize)]
 pub struct Chunk {
-    /// A list of blocks within this loaded chunk.
-    blocks: Vec<Block>,
-    /// The global coordinates of this chunk. This is at 1/16th the scale of
+    /// The blocks within this loaded chunk. Chunks holding a single kind of
+    /// block store just that block; others keep a palette of distinct blocks
+    /// and bit-packed indices into it.
+    blocks: BlockStorage,
+    /// The coordinates of this chunk. This is at 1/16th the scale of
     /// typical block coordinates.
-    coords: GlobalCoordinate,
+    coords: ChunkCoordinate,
+    /// Extra data for blocks that need more than their state, like chests
+    /// and signs.
+    block_entities: BTreeMap<ChunkBlockCoordinate, BlockEntity>,
+    /// How much sky light reaches each block.
+    sky_light: LightStorage,
+    /// How much light from nearby glowing blocks reaches each block.
+    block_light: LightStorage,
+    /// Changes since this chunk was last written to disk.
+    #[serde(skip)]
+    save_journal: ChangeJournal,
+    /// Changes since this chunk was last meshed. Chunks coming off the disk
+    /// haven't been meshed yet!
+    #[serde(skip, default = "ChangeJournal::everything")]
+    mesh_journal: ChangeJournal,
 }
 
 impl Chunk {
     /// Creates a new, 'empty' chunk (where all elements are air).
-    pub fn new(coords: GlobalCoordinate) -> Self {
+    pub fn new(coords: ChunkCoordinate) -> Self {
         Chunk {
-            blocks: vec![Block::default(); 16 * 16 * 16],
+            blocks: BlockStorage::Uniform(Block::default()),
             coords,
+            block_entities: BTreeMap::new(),
+            sky_light: LightStorage::default(),
+            block_light: LightStorage::default(),
+            save_journal: ChangeJournal::everything(),
+            mesh_journal: ChangeJournal::everything(),
         }
     }
 
     /// Creates a new chunk that's filled entirely with the given block.
-    pub fn new_filled(block: Block, coords: GlobalCoordinate) -> Self {
-        Chunk {
-            blocks: vec![block; 16 * 16 * 16],
-            coords,
-        }
+    pub fn new_filled(block: Block, coords: ChunkCoordinate) -> Self {
+        let mut chunk = Chunk::new(coords);
+        chunk.fill(block, Self::bounds());
+        chunk
+    }
+
+    /// A bounding box around every block in a chunk.
+    pub fn bounds() -> BoundingBox<ChunkBlockCoordinate> {
+        let last = CHUNK_LENGTH - 1;
+        BoundingBox::new(
+            ChunkBlockCoordinate::ORIGIN,
+            ChunkBlockCoordinate::new(last, last, last),
+        )
     }
 
     /// Returns the coordinates of the Chunk.
-    pub fn coords(&self) -> GlobalCoordinate {
+    pub fn coords(&self) -> ChunkCoordinate {
         self.coords
     }
 
     /// Returns the coordinates of the region that this chunk belongs to.
-    pub fn region(&self, coords: GlobalCoordinate) -> GlobalCoordinate {
-        (coords / 32) * 32
+    pub fn region(&self) -> RegionCoordinate {
+        self.coords.region()
+    }
+
+    /// If every block in this chunk is the same, returns that block.
+    ///
+    /// Uniform chunks let callers skip per-block work entirely.
+    pub fn uniform_block(&self) -> Option<&Block> {
+        self.blocks.uniform()
     }
 
     /// Gives out a list of blocks in the chunk with their coordinates.
     ///
     /// This doesn't include `None` 'blocks' or Air blocks.
     pub fn blocks(&self) -> Vec<(ChunkBlockCoordinate, Block)> {
-        let mut blocks = Vec::new();
+        // chunks of only air have nothing to give
+        if let Some(block) = self.uniform_block() {
+            if block.block_type == BlockType::AIR {
+                return Vec::new();
+            }
+        }
+
+        let mut blocks = Vec::with_capacity(palette::CHUNK_VOLUME);
 
         for x in 0..CHUNK_LENGTH {
             for y in 0..CHUNK_LENGTH {
                 for z in 0..CHUNK_LENGTH {
                     let coord = ChunkBlockCoordinate::new(x, y, z);
+                    let b = self.blocks.get(self.block_index(&coord));
 
-                    if let Some(b) = self.block(&coord) {
-                        if b.block_type != BlockType::Air {
-                            blocks.push((coord, b));
-                        }
+                    if b.block_type != BlockType::AIR {
+                        blocks.push((coord, b.clone()));
                     }
                 }
             }
@@ -68,19 +126,171 @@ impl Chunk {
     }
 
     /// Sets the `Block` at the given `ChunkBlockCoordinate`.
+    ///
+    /// When the block type changes, the old block's entity is removed and
+    /// the new block gets a fresh one, if its type has one.
     pub fn set_block(&mut self, block: Block, coord: ChunkBlockCoordinate) {
         let index = self.block_index(&coord);
-        self.blocks[index] = block;
+        let new_type = block.block_type;
+        let old = self.blocks.set(index, block);
+
+        if self.blocks.get(index) != &old {
+            self.record_change(coord);
+        }
+
+        if old.block_type != new_type {
+            self.block_entities.remove(&coord);
+            self.create_block_entity(new_type, coord);
+        }
     }
 
     /// Fills in blocks in the chunk given fill bounds.
     /// This overwrites existing blocks!
     pub fn fill(&mut self, block: Block, bounds: BoundingBox<ChunkBlockCoordinate>) {
+        // filling the whole chunk makes it uniform again
+        if bounds == Self::bounds() {
+            let kind = block.block_type.definition().and_then(|d| d.block_entity);
+            self.blocks.fill(block);
+            self.save_journal.record_everything();
+            self.mesh_journal.record_everything();
+
+            self.block_entities.clear();
+            if let Some(kind) = kind {
+                for coord in bounds.all_coordinates() {
+                    self.block_entities.insert(coord, kind.create());
+                }
+            }
+            return;
+        }
+
         for coord in bounds.all_coordinates() {
             self.set_block(block.clone(), coord);
         }
     }
 
+    /// The block entity attached to the block at `coord`, if it has one.
+    pub fn block_entity(&self, coord: &ChunkBlockCoordinate) -> Option<&BlockEntity> {
+        self.block_entities.get(coord)
+    }
+
+    /// The block entity attached to the block at `coord` in a mutable form,
+    /// if it has one.
+    pub fn block_entity_mut(&mut self, coord: &ChunkBlockCoordinate) -> Option<&mut BlockEntity> {
+        // we can't tell what the caller will do, so assume it's changed
+        if self.block_entities.contains_key(coord) {
+            self.save_journal.record(*coord);
+        }
+
+        self.block_entities.get_mut(coord)
+    }
+
+    /// All block entities in this chunk.
+    pub fn block_entities(&self) -> &BTreeMap<ChunkBlockCoordinate, BlockEntity> {
+        &self.block_entities
+    }
+
+    /// Replaces the block entity at `coord`.
+    ///
+    /// This fails if the block there doesn't hold this kind of block entity.
+    pub fn set_block_entity(
+        &mut self,
+        coord: ChunkBlockCoordinate,
+        entity: BlockEntity,
+    ) -> Result<(), WorldError> {
+        let block_type = self.blocks.get(self.block_index(&coord)).block_type;
+        let expected = block_type.definition().and_then(|d| d.block_entity);
+
+        if expected != Some(entity.kind()) {
+            return Err(WorldError::BlockEntityMismatch {
+                block_type,
+                kind: entity.kind(),
+            });
+        }
+
+        self.block_entities.insert(coord, entity);
+        self.save_journal.record(coord);
+        Ok(())
+    }
+
+    /// Gives the block at `coord` a fresh block entity, if its type has one.
+    fn create_block_entity(&mut self, block_type: BlockType, coord: ChunkBlockCoordinate) {
+        if let Some(kind) = block_type.definition().and_then(|d| d.block_entity) {
+            self.block_entities.insert(coord, kind.create());
+        }
+    }
+
+    /// The light level of the given kind at `coord`.
+    pub fn light(&self, kind: LightKind, coord: &ChunkBlockCoordinate) -> u8 {
+        let index = self.block_index(coord);
+
+        match kind {
+            LightKind::Sky => self.sky_light.get(index),
+            LightKind::Block => self.block_light.get(index),
+        }
+    }
+
+    /// Sets the light level of the given kind at `coord`.
+    ///
+    /// This doesn't spread the light anywhere else! Use
+    /// `MacawWorld::set_block()` to keep lighting up to date.
+    pub fn set_light(&mut self, kind: LightKind, coord: ChunkBlockCoordinate, level: u8) {
+        if self.light(kind, &coord) == level {
+            return;
+        }
+
+        let index = self.block_index(&coord);
+        match kind {
+            LightKind::Sky => self.sky_light.set(index, level),
+            LightKind::Block => self.block_light.set(index, level),
+        }
+
+        self.record_change(coord);
+    }
+
+    /// How bright the block at `coord` is: the brighter of its sky light and
+    /// block light.
+    pub fn light_level(&self, coord: &ChunkBlockCoordinate) -> u8 {
+        self.light(LightKind::Sky, coord)
+            .max(self.light(LightKind::Block, coord))
+    }
+
+    /// The changes to this chunk since the given consumer last caught up.
+    pub fn journal(&self, kind: JournalKind) -> &ChangeJournal {
+        match kind {
+            JournalKind::Save => &self.save_journal,
+            JournalKind::Mesh => &self.mesh_journal,
+        }
+    }
+
+    /// Whether this chunk has changed since the given consumer last caught
+    /// up.
+    pub fn is_dirty(&self, kind: JournalKind) -> bool {
+        self.journal(kind).is_dirty()
+    }
+
+    /// Forgets the changes tracked for the given consumer, like after a save.
+    pub fn mark_clean(&mut self, kind: JournalKind) {
+        match kind {
+            JournalKind::Save => self.save_journal.clear(),
+            JournalKind::Mesh => self.mesh_journal.clear(),
+        }
+    }
+
+    /// Marks the whole chunk as changed for the given consumer, like after a
+    /// save that didn't make it to disk.
+    pub fn mark_dirty(&mut self, kind: JournalKind) {
+        match kind {
+            JournalKind::Save => self.save_journal.record_everything(),
+            JournalKind::Mesh => self.mesh_journal.record_everything(),
+        }
+    }
+
+    /// Records a change at `coord` for everything watching this chunk.
+    fn record_change(&mut self, coord: ChunkBlockCoordinate) {
+        self.save_journal.record(coord);
+        self.mesh_journal.record(coord);
+    }
+
     /// Given a local coordinate, this method returns a list of blocks that
     /// are surrounding the given block.
     pub fn adjacent_blocks(&self, coord: &ChunkBlockCoordinate) -> Vec<Block> {
@@ -119,7 +329,7 @@ impl Chunk {
     }
 
     /// Given a local block coordinate, returns the block's index in the
-    /// `blocks` vector.
+    /// `blocks` storage.
     pub(crate) fn block_index(&self, local_coord: &ChunkBlockCoordinate) -> usize {
         (local_coord.z() as usize) * (16 * 16)
             + (local_coord.y() as usize) * 16
@@ -128,49 +338,27 @@ impl Chunk {
 
     /// Given a local block coordinate, returns that block's global coordinate.
     pub fn global_block_coord(&self, local_coord: ChunkBlockCoordinate) -> GlobalCoordinate {
-        let local_to_global = |cc: i64, l: u8| -> i64 { cc * 16 + l as i64 };
-
-        GlobalCoordinate {
-            x: local_to_global(self.coords.x, local_coord.x()),
-            y: local_to_global(self.coords.y, local_coord.y()),
-            z: local_to_global(self.coords.z, local_coord.z()),
-        }
+        self.coords.block(local_coord)
     }
 
     /// Given a global block coordinate, returns that block.
-    pub fn block_from_global_coords(&mut self, coords: GlobalCoordinate) -> Option<&mut Block> {
-        // calculate how far from chunk's min the global coord is
-        let relative_pos = |global_coord: i64, chunk_coord: i64| -> Option<u8> {
-            let r: u8 = (global_coord - (chunk_coord * CHUNK_LENGTH as i64))
-                .try_into()
-                .ok()?;
-            if (0..CHUNK_LENGTH).contains(&r) {
-                Some(r)
-            } else {
-                None
-            }
-        };
-
-        // see if new coord is in bounds
-        if let (Some(x), Some(y), Some(z)) = (
-            relative_pos(coords.x, self.coords.x),
-            relative_pos(coords.y, self.coords.y),
-            relative_pos(coords.z, self.coords.z),
-        ) {
-            let local_coord = ChunkBlockCoordinate::new(x, y, z);
-            let index = self.block_index(&local_coord);
-            self.blocks.get_mut(index)
-        } else {
-            None
+    ///
+    /// If the block is in some other chunk, you'll get `None` back.
+    pub fn block_from_global_coords(&self, coords: GlobalCoordinate) -> Option<Block> {
+        if coords.chunk() != self.coords {
+            return None;
         }
+
+        self.block(&coords.local())
     }
 
     /// Given a **local coordinate** (i.e. within 16 x 16 x 16), this method
-    /// gets the block from the internal `blocks` vector.
+    /// gets the block from the internal `blocks` storage.
     ///
     /// If the block doesn't exist, you'll get `None` back instead.
     pub fn block(&self, local_coord: &ChunkBlockCoordinate) -> Option<Block> {
-        self.blocks.get(self.block_index(local_coord)).cloned()
+        let index = self.block_index(local_coord);
+        (index < palette::CHUNK_VOLUME).then(|| self.blocks.get(index).clone())
     }
 
     pub fn next_block(
@@ -192,28 +380,26 @@ impl Chunk {
 mod tests {
     use crate::{
         block::Block,
-        world::coordinates::{ChunkBlockCoordinate, GlobalCoordinate},
+        world::coordinates::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
     };
 
     use super::Chunk;
 
     #[test]
     fn check_coordinates() {
-        let dirt = Block::new(crate::block::BlockType::Dirt, 0);
+        let dirt = Block::new(crate::block::BlockType::DIRT, 0);
 
-        let mut chunk = Chunk::new_filled(dirt.clone(), GlobalCoordinate::new(4, 4, 4));
+        let mut chunk = Chunk::new_filled(dirt.clone(), ChunkCoordinate::new(4, 4, 4));
 
         chunk.set_block(
-            Block::new(crate::block::BlockType::Ice, 0),
+            Block::new(crate::block::BlockType::ICE, 0),
             ChunkBlockCoordinate::new(0, 0, 0),
         );
 
-        let sixty_four_g = chunk
-            .block_from_global_coords(GlobalCoordinate::new(64, 64, 64))
-            .cloned();
+        let sixty_four_g = chunk.block_from_global_coords(GlobalCoordinate::new(64, 64, 64));
 
         let converted = chunk.global_block_coord(ChunkBlockCoordinate::new(0, 0, 0));
-        let cb = chunk.block_from_global_coords(converted).cloned();
+        let cb = chunk.block_from_global_coords(converted);
         assert_eq!(cb, sixty_four_g);
 
         let sixty_four_l = chunk.block(&ChunkBlockCoordinate::new(0, 0, 0));
@@ -240,9 +426,116 @@ mod tests {
             .is_none());
 
         // the diagonal chunk over should start there, though!
-        let mut next_chunk = Chunk::new_filled(dirt, GlobalCoordinate::new(5, 5, 5));
+        let next_chunk = Chunk::new_filled(dirt, ChunkCoordinate::new(5, 5, 5));
         assert!(next_chunk
             .block_from_global_coords(GlobalCoordinate::new(80, 80, 80))
             .is_some());
+
+        // negative chunks hold blocks below zero
+        let negative_chunk = Chunk::new_filled(Block::default(), ChunkCoordinate::new(-1, -1, -1));
+        assert!(negative_chunk
+            .block_from_global_coords(GlobalCoordinate::new(-1, -16, -9))
+            .is_some());
+        assert!(negative_chunk
+            .block_from_global_coords(GlobalCoordinate::new(0, -1, -1))
+            .is_none());
+    }
+
+    #[test]
+    fn palettes_stay_small_through_many_states() -> anyhow::Result<()> {
+        let mut chunk = Chunk::new(ChunkCoordinate::ORIGIN);
+
+        // far more distinct blocks than 16 bits could ever index, but only a
+        // few hundred at a time
+        for state in 0..70_000 {
+            chunk.set_block(
+                Block::new(crate::block::BlockType::WATER, state),
+                ChunkBlockCoordinate::new((state % 16) as u8, (state / 16 % 16) as u8, 0),
+            );
+        }
+
+        let bytes = bincode::serialize(&chunk)?;
+        assert_eq!(bincode::deserialize::<Chunk>(&bytes)?, chunk);
+
+        Ok(())
+    }
+
+    #[test]
+    fn uniform_until_differing_block() {
+        let stone = Block::new(crate::block::BlockType::STONE, 0);
+        let mut chunk = Chunk::new_filled(stone.clone(), ChunkCoordinate::ORIGIN);
+
+        // placing the same block changes nothing
+        chunk.set_block(stone.clone(), ChunkBlockCoordinate::new(3, 3, 3));
+        assert_eq!(chunk.uniform_block(), Some(&stone));
+
+        // but anything else does
+        chunk.set_block(Block::default(), ChunkBlockCoordinate::new(3, 3, 3));
+        assert!(chunk.uniform_block().is_none());
+        assert_eq!(chunk.blocks().len(), 16 * 16 * 16 - 1);
+
+        // and it's still equal to a chunk that was never uniform
+        let mut other = Chunk::new(ChunkCoordinate::ORIGIN);
+        for (coord, block) in chunk.blocks() {
+            other.set_block(block, coord);
+        }
+        assert_eq!(chunk, other);
+
+        // empty chunks have no blocks to give out
+        assert!(Chunk::new(ChunkCoordinate::ORIGIN).blocks().is_empty());
+    }
+
+    #[test]
+    fn block_entities_follow_blocks() {
+        use crate::block::{entity::BlockEntity, BlockType};
+
+        let mut chunk = Chunk::new(ChunkCoordinate::ORIGIN);
+        let coord = ChunkBlockCoordinate::new(1, 2, 3);
+
+        // placing a chest gives it an empty inventory
+        chunk.set_block(Block::new(BlockType::CHEST, 0), coord);
+        assert!(matches!(
+            chunk.block_entity(&coord),
+            Some(BlockEntity::Chest(_))
+        ));
+
+        // other blocks can't hold one
+        let sign = crate::block::entity::BlockEntityKind::Sign.create();
+        assert!(chunk.set_block_entity(coord, sign.clone()).is_err());
+        assert!(chunk
+            .set_block_entity(ChunkBlockCoordinate::ORIGIN, sign)
+            .is_err());
+
+        // breaking the chest removes its inventory
+        chunk.set_block(Block::default(), coord);
+        assert!(chunk.block_entity(&coord).is_none());
+        assert!(chunk.block_entities().is_empty());
+    }
+
+    #[test]
+    fn journals_track_changes_separately() {
+        use super::journal::JournalKind;
+
+        let mut chunk = Chunk::new(ChunkCoordinate::ORIGIN);
+        assert!(chunk.journal(JournalKind::Save).changed_everything());
+
+        chunk.mark_clean(JournalKind::Save);
+        chunk.mark_clean(JournalKind::Mesh);
+
+        // placing the same block again isn't a change
+        chunk.set_block(Block::default(), ChunkBlockCoordinate::ORIGIN);
+        assert!(!chunk.is_dirty(JournalKind::Save));
+
+        let coord = ChunkBlockCoordinate::new(3, 4, 5);
+        chunk.set_block(Block::new(crate::block::BlockType::DIRT, 0), coord);
+        assert!(chunk
+            .journal(JournalKind::Save)
+            .positions()
+            .contains(&coord));
+
+        // meshing doesn't count as saving
+        chunk.mark_clean(JournalKind::Mesh);
+        assert!(!chunk.is_dirty(JournalKind::Mesh));
+        assert!(chunk.is_dirty(JournalKind::Save));
     }
 }
@@ -0,0 +1,113 @@
+//! # Journal
+//!
+//! Tracks which parts of a chunk have changed, so saving and meshing can skip
+//! chunks that haven't.
+//!
+//! Each chunk keeps one journal per consumer. Consumers clear their own
+//! journal once they've caught up, without affecting the others.
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeSet,
+    hash::{Hash, Hasher},
+};
+
+use crate::world::coordinates::ChunkBlockCoordinate;
+
+/// The things that care about changes to a chunk.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum JournalKind {
+    /// Changes since the chunk was last written to disk.
+    Save,
+    /// Changes since the chunk was last meshed by the renderer.
+    Mesh,
+}
+
+/// The positions in a chunk that changed since a consumer last caught up.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeJournal {
+    /// Individual positions that changed.
+    positions: BTreeSet<ChunkBlockCoordinate>,
+    /// Whether the whole chunk changed at once, like when it's filled or
+    /// first created.
+    everything: bool,
+}
+
+impl ChangeJournal {
+    /// A journal where nothing has changed.
+    pub fn clean() -> Self {
+        Self::default()
+    }
+
+    /// A journal where the whole chunk has changed.
+    pub fn everything() -> Self {
+        Self {
+            positions: BTreeSet::new(),
+            everything: true,
+        }
+    }
+
+    /// Whether anything has changed.
+    pub fn is_dirty(&self) -> bool {
+        self.everything || !self.positions.is_empty()
+    }
+
+    /// Whether the whole chunk has changed at once.
+    pub fn changed_everything(&self) -> bool {
+        self.everything
+    }
+
+    /// The individual positions that have changed.
+    ///
+    /// This can be empty when the whole chunk has changed, so check
+    /// `changed_everything()` first.
+    pub fn positions(&self) -> &BTreeSet<ChunkBlockCoordinate> {
+        &self.positions
+    }
+
+    /// Records a change at a single position.
+    pub fn record(&mut self, coord: ChunkBlockCoordinate) {
+        // no need to track positions when everything changed anyways
+        if !self.everything {
+            self.positions.insert(coord);
+        }
+    }
+
+    /// Records a change to the whole chunk.
+    pub fn record_everything(&mut self) {
+        self.positions.clear();
+        self.everything = true;
+    }
+
+    /// Forgets all changes.
+    pub fn clear(&mut self) {
+        *self = Self::clean();
+    }
+}
+
+// journals are bookkeeping, not chunk content. chunks with the same blocks are
+// equal no matter what's changed in them
+
+impl PartialEq for ChangeJournal {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ChangeJournal {}
+
+impl PartialOrd for ChangeJournal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ChangeJournal {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Hash for ChangeJournal {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
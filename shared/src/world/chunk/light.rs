@@ -0,0 +1,93 @@
+//! # Light
+//!
+//! Per-block light levels for chunks.
+//!
+//! Light levels run from 0 to 15, so two of them fit in every byte. Each
+//! chunk keeps one of these arrays for sky light and another for block light.
+
+use serde::{Deserialize, Serialize};
+
+use super::palette::CHUNK_VOLUME;
+
+/// The brightest light level a block can have.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The two kinds of light in the world.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum LightKind {
+    /// Light coming down from the sky.
+    Sky,
+    /// Light given off by blocks, like torches or lava.
+    Block,
+}
+
+/// A light level for every block in a chunk, packed at 4 bits each.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize)]
+pub struct LightStorage {
+    data: Vec<u8>,
+}
+
+impl LightStorage {
+    /// Creates storage where every block has the given light level.
+    pub fn new(level: u8) -> Self {
+        debug_assert!(level <= MAX_LIGHT);
+
+        Self {
+            data: vec![level | level << 4; CHUNK_VOLUME / 2],
+        }
+    }
+
+    /// Gets the light level at the given index.
+    ///
+    /// The index must be less than `CHUNK_VOLUME`.
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.data[index / 2];
+
+        if index.is_multiple_of(2) {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        }
+    }
+
+    /// Sets the light level at the given index.
+    ///
+    /// The index must be less than `CHUNK_VOLUME`, and the level can't be
+    /// brighter than `MAX_LIGHT`.
+    pub fn set(&mut self, index: usize, level: u8) {
+        debug_assert!(level <= MAX_LIGHT);
+        let byte = &mut self.data[index / 2];
+
+        if index.is_multiple_of(2) {
+            *byte = (*byte & 0xF0) | level;
+        } else {
+            *byte = (*byte & 0x0F) | level << 4;
+        }
+    }
+}
+
+impl Default for LightStorage {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LightStorage;
+
+    #[test]
+    fn neighbors_share_bytes() {
+        let mut storage = LightStorage::default();
+        storage.set(10, 15);
+        storage.set(11, 7);
+
+        assert_eq!(storage.get(10), 15);
+        assert_eq!(storage.get(11), 7);
+        assert_eq!(storage.get(12), 0);
+
+        storage.set(10, 3);
+        assert_eq!(storage.get(10), 3);
+        assert_eq!(storage.get(11), 7);
+    }
+}
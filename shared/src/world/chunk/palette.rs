@@ -0,0 +1,434 @@
+//! # Palette
+//!
+//! Compact block storage for chunks.
+//!
+//! Instead of keeping one `Block` per position, a chunk keeps a small list
+//! (the 'palette') of every distinct block it holds. Each position then only
+//! stores an index into that list, bit-packed into `u64` words. A chunk of
+//! stone and air needs 4 bits per block instead of a whole `Block`!
+
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::block::Block;
+
+/// The number of blocks in a chunk.
+pub const CHUNK_VOLUME: usize = 16 * 16 * 16;
+
+/// Block storage made of a palette and bit-packed indices into it.
+///
+/// Indices never span two words, so some bits at the end of each word may go
+/// unused. That keeps reads and writes to a single word each.
+///
+/// Stored storage is checked over as it's deserialized, so a damaged chunk
+/// fails to load instead of panicking the first time it's read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "StoredPalettedStorage")]
+pub struct PalettedStorage {
+    /// The distinct blocks in this storage. Some might not be used anymore,
+    /// until the palette runs out of room.
+    palette: Vec<Block>,
+    /// The amount of bits each index takes up in `data`.
+    bits_per_index: u8,
+    /// The bit-packed palette indices for all `CHUNK_VOLUME` blocks.
+    data: Vec<u64>,
+}
+
+/// `PalettedStorage` as it's stored, before it's been checked over.
+#[derive(Deserialize)]
+struct StoredPalettedStorage {
+    palette: Vec<Block>,
+    bits_per_index: u8,
+    data: Vec<u64>,
+}
+
+/// Why stored `PalettedStorage` couldn't be used.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PaletteError {
+    #[error("Indices can't take up {0} bits each")]
+    BadBitsPerIndex(u8),
+    #[error("Expected {expected} words of indices, but found {found}")]
+    WrongLength { expected: usize, found: usize },
+    #[error("A palette of {len} blocks doesn't fit in {bits} bits per index")]
+    PaletteTooBig { len: usize, bits: u8 },
+    #[error("Block {index} points at palette entry {palette_index}, but there are only {len}")]
+    IndexOutOfRange {
+        index: usize,
+        palette_index: usize,
+        len: usize,
+    },
+}
+
+impl TryFrom<StoredPalettedStorage> for PalettedStorage {
+    type Error = PaletteError;
+
+    fn try_from(stored: StoredPalettedStorage) -> Result<Self, Self::Error> {
+        let StoredPalettedStorage {
+            palette,
+            bits_per_index: bits,
+            data,
+        } = stored;
+
+        if !(Self::MIN_BITS_PER_INDEX..=Self::MAX_BITS_PER_INDEX).contains(&bits) {
+            return Err(PaletteError::BadBitsPerIndex(bits));
+        }
+
+        let expected = Self::words_needed(bits);
+        if data.len() != expected {
+            return Err(PaletteError::WrongLength {
+                expected,
+                found: data.len(),
+            });
+        }
+
+        if palette.len() > 1 << bits {
+            return Err(PaletteError::PaletteTooBig {
+                len: palette.len(),
+                bits,
+            });
+        }
+
+        let storage = Self {
+            palette,
+            bits_per_index: bits,
+            data,
+        };
+        for index in 0..CHUNK_VOLUME {
+            let palette_index = storage.palette_index(index);
+            if palette_index >= storage.palette.len() {
+                return Err(PaletteError::IndexOutOfRange {
+                    index,
+                    palette_index,
+                    len: storage.palette.len(),
+                });
+            }
+        }
+
+        Ok(storage)
+    }
+}
+
+impl PalettedStorage {
+    /// The smallest amount of bits used per index. Growing from here is
+    /// cheap, and most chunks never have more than 16 kinds of block.
+    const MIN_BITS_PER_INDEX: u8 = 4;
+
+    /// The most bits an index can use.
+    ///
+    /// Unused entries are dropped before the palette grows, so it never
+    /// holds more than one entry past a chunk's worth of blocks. That only
+    /// takes 13 bits, leaving plenty of room.
+    const MAX_BITS_PER_INDEX: u8 = 16;
+
+    /// Creates storage where every position holds the given block.
+    pub fn new(block: Block) -> Self {
+        Self {
+            palette: vec![block],
+            bits_per_index: Self::MIN_BITS_PER_INDEX,
+            data: vec![0; Self::words_needed(Self::MIN_BITS_PER_INDEX)],
+        }
+    }
+
+    /// Gets the block at the given index.
+    ///
+    /// The index must be less than `CHUNK_VOLUME`.
+    pub fn get(&self, index: usize) -> &Block {
+        &self.palette[self.palette_index(index)]
+    }
+
+    /// Sets the block at the given index, returning the block that was there
+    /// before.
+    ///
+    /// The index must be less than `CHUNK_VOLUME`.
+    pub fn set(&mut self, index: usize, block: Block) -> Block {
+        let old = self.get(index).clone();
+
+        let new_palette_index = match self.palette.iter().position(|b| *b == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                // make room for the new palette entry if we've run out. old
+                // entries nothing uses anymore go first
+                if self.palette.len() == 1 << self.bits_per_index {
+                    self.compact(index);
+                }
+                if self.palette.len() == 1 << self.bits_per_index {
+                    self.grow();
+                }
+
+                self.palette.push(block);
+                self.palette.len() - 1
+            }
+        };
+
+        self.write_palette_index(index, new_palette_index);
+        old
+    }
+
+    /// Overwrites every position with the given block.
+    ///
+    /// This also throws away the old palette and indices.
+    pub fn fill(&mut self, block: Block) {
+        *self = Self::new(block);
+    }
+
+    /// Iterates over every block, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> + '_ {
+        (0..CHUNK_VOLUME).map(|index| self.get(index))
+    }
+
+    /// The distinct blocks that have been placed in this storage.
+    ///
+    /// Entries stay in the palette after their last block is overwritten,
+    /// until the palette runs out of room or the storage is filled again.
+    pub fn palette(&self) -> &[Block] {
+        &self.palette
+    }
+
+    /// The amount of bits each index currently uses.
+    pub fn bits_per_index(&self) -> u8 {
+        self.bits_per_index
+    }
+
+    /// Finds the palette index stored at the given block index.
+    fn palette_index(&self, index: usize) -> usize {
+        let (word, offset) = self.position(index);
+        ((self.data[word] >> offset) & self.mask()) as usize
+    }
+
+    /// Writes a palette index at the given block index.
+    fn write_palette_index(&mut self, index: usize, palette_index: usize) {
+        let (word, offset) = self.position(index);
+        let mask = self.mask();
+
+        self.data[word] &= !(mask << offset);
+        self.data[word] |= (palette_index as u64 & mask) << offset;
+    }
+
+    /// Drops the palette entries no block uses anymore.
+    ///
+    /// The block at `replacing` doesn't count, since it's about to be
+    /// overwritten. It's left pointing at the first entry.
+    fn compact(&mut self, replacing: usize) {
+        let indices = (0..CHUNK_VOLUME)
+            .map(|index| self.palette_index(index))
+            .collect::<Vec<_>>();
+
+        let mut used = vec![false; self.palette.len()];
+        for (index, palette_index) in indices.iter().enumerate() {
+            used[*palette_index] |= index != replacing;
+        }
+
+        // where each kept entry ends up
+        let mut moved_to = vec![0; self.palette.len()];
+        let old_palette = std::mem::take(&mut self.palette);
+        for (palette_index, block) in old_palette.into_iter().enumerate() {
+            if used[palette_index] {
+                moved_to[palette_index] = self.palette.len();
+                self.palette.push(block);
+            }
+        }
+
+        for (index, palette_index) in indices.into_iter().enumerate() {
+            self.write_palette_index(index, moved_to[palette_index]);
+        }
+    }
+
+    /// Re-packs all indices with one more bit each.
+    fn grow(&mut self) {
+        let indices = (0..CHUNK_VOLUME)
+            .map(|index| self.palette_index(index))
+            .collect::<Vec<_>>();
+
+        self.bits_per_index += 1;
+        self.data = vec![0; Self::words_needed(self.bits_per_index)];
+
+        for (index, palette_index) in indices.into_iter().enumerate() {
+            self.write_palette_index(index, palette_index);
+        }
+    }
+
+    /// Returns the (word, bit offset) pair holding the given block index.
+    fn position(&self, index: usize) -> (usize, usize) {
+        let per_word = Self::indices_per_word(self.bits_per_index);
+        (
+            index / per_word,
+            (index % per_word) * self.bits_per_index as usize,
+        )
+    }
+
+    /// A mask covering a single index.
+    fn mask(&self) -> u64 {
+        (1 << self.bits_per_index) - 1
+    }
+
+    fn indices_per_word(bits_per_index: u8) -> usize {
+        64 / bits_per_index as usize
+    }
+
+    fn words_needed(bits_per_index: u8) -> usize {
+        CHUNK_VOLUME.div_ceil(Self::indices_per_word(bits_per_index))
+    }
+}
+
+// two storages are the same if they hold the same blocks, no matter how
+// their palettes happen to be laid out
+
+impl PartialEq for PalettedStorage {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for PalettedStorage {}
+
+impl PartialOrd for PalettedStorage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PalettedStorage {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl Hash for PalettedStorage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for block in self.iter() {
+            block.hash(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PaletteError, PalettedStorage, StoredPalettedStorage, CHUNK_VOLUME};
+    use crate::block::{Block, BlockType};
+
+    #[test]
+    fn grows_with_palette() {
+        let mut storage = PalettedStorage::new(Block::default());
+        assert_eq!(storage.bits_per_index(), 4);
+
+        // 17 distinct blocks (every water state and air) need five bits
+        for state in 0..16 {
+            storage.set(state as usize, Block::new(BlockType::WATER, state));
+        }
+        assert_eq!(storage.palette().len(), 17);
+        assert_eq!(storage.bits_per_index(), 5);
+
+        // nothing should've moved while growing
+        for state in 0..16 {
+            assert_eq!(
+                storage.get(state as usize),
+                &Block::new(BlockType::WATER, state)
+            );
+        }
+        assert_eq!(storage.get(CHUNK_VOLUME - 1), &Block::default());
+    }
+
+    #[test]
+    fn unused_entries_make_room() {
+        let mut storage = PalettedStorage::new(Block::default());
+
+        // one block changing over and over never needs more than 4 bits
+        for state in 0..70_000 {
+            let old = storage.set(3, Block::new(BlockType::WATER, state));
+            if state > 0 {
+                assert_eq!(old, Block::new(BlockType::WATER, state - 1));
+            }
+        }
+
+        assert_eq!(storage.bits_per_index(), 4);
+        assert_eq!(storage.get(3), &Block::new(BlockType::WATER, 69_999));
+        assert_eq!(storage.get(4), &Block::default());
+    }
+
+    #[test]
+    fn set_returns_old_block() {
+        let mut storage = PalettedStorage::new(Block::new(BlockType::DIRT, 0));
+
+        let old = storage.set(42, Block::new(BlockType::GRASS, 0));
+        assert_eq!(old, Block::new(BlockType::DIRT, 0));
+        assert_eq!(storage.get(42), &Block::new(BlockType::GRASS, 0));
+        assert_eq!(storage.get(41), &Block::new(BlockType::DIRT, 0));
+    }
+
+    #[test]
+    fn equality_ignores_palette_order() {
+        let (stone, dirt) = (
+            Block::new(BlockType::STONE, 0),
+            Block::new(BlockType::DIRT, 0),
+        );
+
+        let mut a = PalettedStorage::new(Block::default());
+        a.set(0, stone.clone());
+        a.set(1, dirt.clone());
+
+        let mut b = PalettedStorage::new(Block::default());
+        b.set(1, dirt);
+        b.set(0, stone);
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn damaged_storage_is_refused() -> anyhow::Result<()> {
+        let stored = |palette: Vec<Block>, bits_per_index: u8, data: Vec<u64>| {
+            PalettedStorage::try_from(StoredPalettedStorage {
+                palette,
+                bits_per_index,
+                data,
+            })
+        };
+        let words = PalettedStorage::words_needed(4);
+
+        assert_eq!(
+            stored(vec![Block::default()], 0, Vec::new()),
+            Err(PaletteError::BadBitsPerIndex(0))
+        );
+        assert_eq!(
+            stored(vec![Block::default()], 4, vec![0; 3]),
+            Err(PaletteError::WrongLength {
+                expected: words,
+                found: 3
+            })
+        );
+        assert_eq!(
+            stored(vec![Block::default(); 17], 4, vec![0; words]),
+            Err(PaletteError::PaletteTooBig { len: 17, bits: 4 })
+        );
+
+        // the second block points past the end of the palette
+        let mut data = vec![0; words];
+        data[0] = 1 << 4;
+        assert_eq!(
+            stored(vec![Block::default()], 4, data),
+            Err(PaletteError::IndexOutOfRange {
+                index: 1,
+                palette_index: 1,
+                len: 1
+            })
+        );
+
+        // while storage that was saved properly comes back as it was
+        let mut storage = PalettedStorage::new(Block::default());
+        storage.set(7, Block::new(BlockType::STONE, 0));
+        let bytes = bincode::serialize(&storage)?;
+        assert_eq!(bincode::deserialize::<PalettedStorage>(&bytes)?, storage);
+
+        // and damaged storage fails to deserialize
+        let mut bytes = bytes;
+        let last = bytes.len() - 1;
+        bytes[last] = 0xFF;
+        assert!(bincode::deserialize::<PalettedStorage>(&bytes).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! # Storage
+//!
+//! The block storage behind every `Chunk`.
+//!
+//! Most chunks above and below the terrain hold nothing but air or stone.
+//! Those stay `Uniform` - a single block - until something different is
+//! placed in them, at which point they switch over to a `PalettedStorage`.
+
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::palette::{PalettedStorage, CHUNK_VOLUME};
+use crate::block::Block;
+
+/// A chunk's blocks, in whichever representation is cheapest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BlockStorage {
+    /// Every position holds this same block.
+    Uniform(Block),
+    /// Positions hold differing blocks.
+    Paletted(PalettedStorage),
+}
+
+impl BlockStorage {
+    /// Gets the block at the given index.
+    ///
+    /// The index must be less than `CHUNK_VOLUME`.
+    pub fn get(&self, index: usize) -> &Block {
+        match self {
+            BlockStorage::Uniform(block) => block,
+            BlockStorage::Paletted(storage) => storage.get(index),
+        }
+    }
+
+    /// Sets the block at the given index, returning the block that was there
+    /// before.
+    ///
+    /// Uniform storage only becomes paletted when the new block differs.
+    pub fn set(&mut self, index: usize, block: Block) -> Block {
+        match self {
+            BlockStorage::Uniform(uniform) if *uniform == block => block,
+            BlockStorage::Uniform(uniform) => {
+                let old = uniform.clone();
+                let mut storage = PalettedStorage::new(old.clone());
+                storage.set(index, block);
+
+                *self = BlockStorage::Paletted(storage);
+                old
+            }
+            BlockStorage::Paletted(storage) => storage.set(index, block),
+        }
+    }
+
+    /// Overwrites every position with the given block, making the storage
+    /// uniform again.
+    pub fn fill(&mut self, block: Block) {
+        *self = BlockStorage::Uniform(block);
+    }
+
+    /// If every position holds the same block, returns that block.
+    pub fn uniform(&self) -> Option<&Block> {
+        match self {
+            BlockStorage::Uniform(block) => Some(block),
+            BlockStorage::Paletted(_) => None,
+        }
+    }
+
+    /// Iterates over every block, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> + '_ {
+        (0..CHUNK_VOLUME).map(|index| self.get(index))
+    }
+}
+
+// as with `PalettedStorage`, these compare the stored blocks rather than
+// the representation they're stored in
+
+impl PartialEq for BlockStorage {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BlockStorage::Uniform(a), BlockStorage::Uniform(b)) => a == b,
+            _ => self.iter().eq(other.iter()),
+        }
+    }
+}
+
+impl Eq for BlockStorage {}
+
+impl PartialOrd for BlockStorage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BlockStorage {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (BlockStorage::Uniform(a), BlockStorage::Uniform(b)) => a.cmp(b),
+            _ => self.iter().cmp(other.iter()),
+        }
+    }
+}
+
+impl Hash for BlockStorage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for block in self.iter() {
+            block.hash(state);
+        }
+    }
+}
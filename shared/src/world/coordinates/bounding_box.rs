@@ -3,9 +3,9 @@
 //! Helps define an area in the world of Macaw.
 
 use bevy::math::primitives::Cuboid;
-use std::{collections::HashSet, ops::Div};
+use std::collections::HashSet;
 
-use super::{ChunkBlockCoordinate, GlobalCoordinate};
+use super::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate, RegionCoordinate};
 use crate::{block::BlockSide, world::chunk::Chunk};
 
 /// A bounding box around two `Coordinate`s.
@@ -106,6 +106,18 @@ impl BoundingBox<GlobalCoordinate> {
         )
     }
 
+    /// Checks to see if a block is within the box.
+    pub fn contains(&self, coords: GlobalCoordinate) -> bool {
+        (self.smaller.x..=self.larger.x).contains(&coords.x)
+            && (self.smaller.y..=self.larger.y).contains(&coords.y)
+            && (self.smaller.z..=self.larger.z).contains(&coords.z)
+    }
+
+    /// The box of all chunks that hold any of these blocks.
+    pub fn chunks(&self) -> BoundingBox<ChunkCoordinate> {
+        BoundingBox::new(self.smaller.chunk(), self.larger.chunk())
+    }
+
     /// Returns a set of all coordinates within the box.
     pub fn all_coordinates(&self) -> HashSet<GlobalCoordinate> {
         let mut set = HashSet::new();
@@ -195,10 +207,48 @@ impl BoundingBox<ChunkBlockCoordinate> {
     }
 }
 
-impl Div<i64> for BoundingBox<GlobalCoordinate> {
-    type Output = Self;
+impl BoundingBox<ChunkCoordinate> {
+    /// Returns a set of all chunk coordinates within the box.
+    pub fn all_coordinates(&self) -> HashSet<ChunkCoordinate> {
+        let mut set = HashSet::new();
+
+        for x in self.smaller.x..=self.larger.x {
+            for y in self.smaller.y..=self.larger.y {
+                for z in self.smaller.z..=self.larger.z {
+                    set.insert(ChunkCoordinate::new(x, y, z));
+                }
+            }
+        }
+
+        set
+    }
+
+    /// Checks to see if a chunk is within the box.
+    pub fn contains(&self, chunk: ChunkCoordinate) -> bool {
+        (self.smaller.x..=self.larger.x).contains(&chunk.x)
+            && (self.smaller.y..=self.larger.y).contains(&chunk.y)
+            && (self.smaller.z..=self.larger.z).contains(&chunk.z)
+    }
+
+    /// The box of all regions that hold any of these chunks.
+    pub fn regions(&self) -> BoundingBox<RegionCoordinate> {
+        BoundingBox::new(self.smaller.region(), self.larger.region())
+    }
+}
+
+impl BoundingBox<RegionCoordinate> {
+    /// Returns a set of all region coordinates within the box.
+    pub fn all_coordinates(&self) -> HashSet<RegionCoordinate> {
+        let mut set = HashSet::new();
 
-    fn div(self, rhs: i64) -> Self::Output {
-        Self::new(self.smaller() / rhs, self.larger() / rhs)
+        for x in self.smaller.x..=self.larger.x {
+            for y in self.smaller.y..=self.larger.y {
+                for z in self.smaller.z..=self.larger.z {
+                    set.insert(RegionCoordinate::new(x, y, z));
+                }
+            }
+        }
+
+        set
     }
 }
@@ -0,0 +1,127 @@
+//! # Chunk
+//!
+//! Coordinates of whole chunks in the world.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ChunkBlockCoordinate, ColumnCoordinate, GlobalCoordinate, RegionCoordinate};
+use crate::world::chunk::CHUNK_LENGTH;
+
+/// The coordinate of a chunk. This is at 1/16th the scale of block
+/// coordinates, so the chunk at (1, 0, 0) starts at block (16, 0, 0).
+#[derive(
+    Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize,
+)]
+pub struct ChunkCoordinate {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl ChunkCoordinate {
+    /// The chunk at (0, 0, 0).
+    pub const ORIGIN: ChunkCoordinate = ChunkCoordinate::new(0, 0, 0);
+
+    /// Creates a `ChunkCoordinate`.
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Destructures self into (x, y, z) coordinates.
+    pub fn free(self) -> (i64, i64, i64) {
+        (self.x, self.y, self.z)
+    }
+
+    /// Returns the chunk that's offset from this one by the given amounts.
+    pub fn offset(self, x: i64, y: i64, z: i64) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    /// The region that this chunk belongs to.
+    ///
+    /// ```
+    /// # use shared::world::coordinates::{ChunkCoordinate, RegionCoordinate};
+    /// #
+    /// assert_eq!(ChunkCoordinate::new(31, 32, -1).region(), RegionCoordinate::new(0, 1, -1));
+    /// ```
+    pub fn region(&self) -> RegionCoordinate {
+        let region = |c: i64| c.div_euclid(RegionCoordinate::CHUNKS_PER_REGION);
+        RegionCoordinate::new(region(self.x), region(self.y), region(self.z))
+    }
+
+    /// The column that this chunk is stacked in.
+    pub fn column(&self) -> ColumnCoordinate {
+        ColumnCoordinate::new(self.x, self.z)
+    }
+
+    /// The global coordinate of this chunk's first block, at local (0, 0, 0).
+    pub fn min_block(&self) -> GlobalCoordinate {
+        self.block(ChunkBlockCoordinate::ORIGIN)
+    }
+
+    /// Given a block's local coordinate in this chunk, returns its global
+    /// coordinate.
+    pub fn block(&self, local: ChunkBlockCoordinate) -> GlobalCoordinate {
+        let global = |c: i64, l: u8| c * CHUNK_LENGTH as i64 + l as i64;
+
+        GlobalCoordinate::new(
+            global(self.x, local.x()),
+            global(self.y, local.y()),
+            global(self.z, local.z()),
+        )
+    }
+}
+
+impl Display for ChunkCoordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}, {}", self.x, self.y, self.z)
+    }
+}
+
+impl super::Coordinate for ChunkCoordinate {
+    type Value = i64;
+
+    fn x(&self) -> Self::Value {
+        self.x
+    }
+
+    fn y(&self) -> Self::Value {
+        self.y
+    }
+
+    fn z(&self) -> Self::Value {
+        self.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::coordinates::{
+        ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate, RegionCoordinate,
+    };
+
+    #[test]
+    fn negative_blocks_floor() {
+        // block -1 is the last block of chunk -1, not part of chunk 0
+        let block = GlobalCoordinate::new(-1, -16, -17);
+        assert_eq!(block.chunk(), ChunkCoordinate::new(-1, -1, -2));
+        assert_eq!(block.local(), ChunkBlockCoordinate::new(15, 0, 15));
+
+        // converting back should land on the same block
+        assert_eq!(block.chunk().block(block.local()), block);
+    }
+
+    #[test]
+    fn negative_chunks_floor() {
+        let chunk = ChunkCoordinate::new(-1, -32, -33);
+        assert_eq!(chunk.region(), RegionCoordinate::new(-1, -1, -2));
+
+        let region = RegionCoordinate::new(-1, 0, 0);
+        assert_eq!(region.min_chunk(), ChunkCoordinate::new(-32, 0, 0));
+        assert_eq!(region.max_chunk(), ChunkCoordinate::new(-1, 31, 31));
+        assert!(region.contains(ChunkCoordinate::new(-1, 0, 0)));
+        assert!(!region.contains(ChunkCoordinate::ORIGIN));
+    }
+}
@@ -0,0 +1,40 @@
+//! # Column
+//!
+//! Coordinates of whole columns of chunks, stacked from the bottom of the
+//! world to the top.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ChunkCoordinate, RegionCoordinate};
+
+/// The coordinate of a chunk column: every chunk sharing an `x` and `z`,
+/// regardless of height.
+#[derive(
+    Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize,
+)]
+pub struct ColumnCoordinate {
+    pub x: i64,
+    pub z: i64,
+}
+
+impl ColumnCoordinate {
+    /// Creates a `ColumnCoordinate`.
+    pub const fn new(x: i64, z: i64) -> Self {
+        Self { x, z }
+    }
+
+    /// The region this column's heightmap is stored in: the one holding the
+    /// column's chunk at `y = 0`. Other regions in the column don't keep a
+    /// copy.
+    pub fn region(&self) -> RegionCoordinate {
+        ChunkCoordinate::new(self.x, 0, self.z).region()
+    }
+}
+
+impl Display for ColumnCoordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.x, self.z)
+    }
+}
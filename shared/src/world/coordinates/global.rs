@@ -6,9 +6,13 @@ use std::{
 use bevy::math::Vec3;
 use serde::{Deserialize, Serialize};
 
+use super::{ChunkBlockCoordinate, ChunkCoordinate, ColumnCoordinate};
+use crate::{block::BlockSide, world::chunk::CHUNK_LENGTH};
+
 /// A coordinate found in the world - globally.
 ///
-/// These can represent a block or even a chunk!
+/// These represent blocks. For chunks and regions, see `ChunkCoordinate` and
+/// `RegionCoordinate`.
 #[derive(
     Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize,
 )]
@@ -62,6 +66,38 @@ impl GlobalCoordinate {
         (self.x, self.y, self.z)
     }
 
+    /// The chunk that this block belongs to.
+    ///
+    /// This rounds towards negative infinity, so block -1 is in chunk -1.
+    ///
+    /// ```
+    /// # use shared::world::coordinates::{ChunkCoordinate, GlobalCoordinate};
+    /// #
+    /// let block = GlobalCoordinate::new(47, 16, -1);
+    /// assert_eq!(block.chunk(), ChunkCoordinate::new(2, 1, -1));
+    /// ```
+    pub fn chunk(&self) -> ChunkCoordinate {
+        let chunk = |c: i64| c.div_euclid(CHUNK_LENGTH as i64);
+        ChunkCoordinate::new(chunk(self.x), chunk(self.y), chunk(self.z))
+    }
+
+    /// The chunk column that this block is in.
+    pub fn column(&self) -> ColumnCoordinate {
+        self.chunk().column()
+    }
+
+    /// This block's position within its chunk.
+    pub fn local(&self) -> ChunkBlockCoordinate {
+        let local = |c: i64| c.rem_euclid(CHUNK_LENGTH as i64) as u8;
+        ChunkBlockCoordinate::new(local(self.x), local(self.y), local(self.z))
+    }
+
+    /// The neighboring block on the given side of this one.
+    pub fn next(&self, direction: &BlockSide) -> GlobalCoordinate {
+        let (x, y, z) = direction.position_offset();
+        GlobalCoordinate::new(self.x + x as i64, self.y + y as i64, self.z + z as i64)
+    }
+
     /// If any of the contained coordinates match a given closure's comparison,
     /// this function will return true.
     ///
@@ -5,12 +5,15 @@
 use std::fmt::Display;
 
 use bevy::math::Vec3;
+use serde::{Deserialize, Serialize};
 
 use crate::{block::BlockSide, world::chunk::CHUNK_LENGTH};
 
 /// A coordinate in a chunk. Chunks are 16x16x16, so all values must be in the
 /// range [0, 15].
-#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Hash, Ord)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Hash, Ord, Serialize, Deserialize,
+)]
 pub struct ChunkBlockCoordinate {
     x: u8,
     y: u8,
@@ -1,11 +1,17 @@
 pub mod bounding_box;
+pub mod chunk;
+pub mod column;
 pub mod global;
 pub mod local;
 pub mod mob;
+pub mod region;
 
 pub use bounding_box::BoundingBox;
+pub use chunk::ChunkCoordinate;
+pub use column::ColumnCoordinate;
 pub use global::GlobalCoordinate;
 pub use local::ChunkBlockCoordinate;
+pub use region::RegionCoordinate;
 
 /// A marker trait that indicates a type of coordinate.
 pub trait Coordinate {
@@ -0,0 +1,77 @@
+//! # Region
+//!
+//! Coordinates of whole regions in the world.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::ChunkCoordinate;
+
+/// The coordinate of a region. That's 32 times larger than chunks, and
+/// (16 * 32) times larger than blocks!
+#[derive(
+    Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize,
+)]
+pub struct RegionCoordinate {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl RegionCoordinate {
+    /// The length of a region, in chunks.
+    pub const CHUNKS_PER_REGION: i64 = 32;
+
+    /// The region at (0, 0, 0).
+    pub const ORIGIN: RegionCoordinate = RegionCoordinate::new(0, 0, 0);
+
+    /// Creates a `RegionCoordinate`.
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Destructures self into (x, y, z) coordinates.
+    pub fn free(self) -> (i64, i64, i64) {
+        (self.x, self.y, self.z)
+    }
+
+    /// The smallest chunk coordinate within this region.
+    pub fn min_chunk(&self) -> ChunkCoordinate {
+        let min = |r: i64| r * Self::CHUNKS_PER_REGION;
+        ChunkCoordinate::new(min(self.x), min(self.y), min(self.z))
+    }
+
+    /// The largest chunk coordinate within this region (inclusive).
+    pub fn max_chunk(&self) -> ChunkCoordinate {
+        let max = Self::CHUNKS_PER_REGION - 1;
+        self.min_chunk().offset(max, max, max)
+    }
+
+    /// Checks to see if the given chunk is within this region.
+    pub fn contains(&self, chunk: ChunkCoordinate) -> bool {
+        chunk.region() == *self
+    }
+}
+
+impl Display for RegionCoordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}, {}", self.x, self.y, self.z)
+    }
+}
+
+impl super::Coordinate for RegionCoordinate {
+    type Value = i64;
+
+    fn x(&self) -> Self::Value {
+        self.x
+    }
+
+    fn y(&self) -> Self::Value {
+        self.y
+    }
+
+    fn z(&self) -> Self::Value {
+        self.z
+    }
+}
@@ -0,0 +1,524 @@
+//! # Edit
+//!
+//! Edits spanning many blocks, and possibly many chunks, at once.
+//!
+//! Operations are grouped into a `Transaction`. Applying one either changes
+//! every block it covers or, if any of its chunks aren't loaded, nothing at
+//! all. The result remembers what was there before, so the whole transaction
+//! can be rolled back later.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use thiserror::Error;
+
+use super::{
+    coordinates::{BoundingBox, ChunkCoordinate, GlobalCoordinate},
+    schematic::{Rotation, Schematic},
+    MacawWorld,
+};
+use crate::block::{entity::BlockEntity, Block, BlockType};
+
+/// A single edit within a `Transaction`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditOperation {
+    /// Sets every block in the box to the given block.
+    Fill {
+        bounds: BoundingBox<GlobalCoordinate>,
+        block: Block,
+    },
+    /// Swaps every block of the `matching` type in the box for the given
+    /// block. Other blocks are left alone.
+    Replace {
+        bounds: BoundingBox<GlobalCoordinate>,
+        matching: BlockType,
+        block: Block,
+    },
+    /// Copies the blocks in the `source` box, along with their block
+    /// entities, so its smallest corner lands on `destination`.
+    Copy {
+        source: BoundingBox<GlobalCoordinate>,
+        destination: GlobalCoordinate,
+    },
+    /// Sets every block in the box to air.
+    Clear {
+        bounds: BoundingBox<GlobalCoordinate>,
+    },
+    /// Pastes a schematic, turned by `rotation`, so its smallest corner lands
+    /// on `origin`. With `skip_air`, the schematic's air leaves the world's
+    /// blocks alone.
+    Paste {
+        schematic: Arc<Schematic>,
+        origin: GlobalCoordinate,
+        rotation: Rotation,
+        skip_air: bool,
+    },
+}
+
+impl EditOperation {
+    /// The blocks this operation reads from or writes to.
+    fn areas(&self) -> Vec<BoundingBox<GlobalCoordinate>> {
+        match self {
+            EditOperation::Fill { bounds, .. }
+            | EditOperation::Replace { bounds, .. }
+            | EditOperation::Clear { bounds } => vec![*bounds],
+            EditOperation::Copy {
+                source,
+                destination,
+            } => {
+                let (smaller, larger) = source.bounds();
+                let mut target = BoundingBox::new_point(*destination);
+                target.extend(GlobalCoordinate::new(
+                    destination.x + larger.x - smaller.x,
+                    destination.y + larger.y - smaller.y,
+                    destination.z + larger.z - smaller.z,
+                ));
+
+                vec![*source, target]
+            }
+            EditOperation::Paste {
+                schematic,
+                origin,
+                rotation,
+                ..
+            } => vec![schematic.bounds_at(*origin, *rotation)],
+        }
+    }
+}
+
+/// A group of edits that are applied together, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transaction {
+    operations: Vec<EditOperation>,
+}
+
+impl Transaction {
+    /// Creates a transaction without any edits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an operation to the end of the transaction.
+    pub fn push(&mut self, operation: EditOperation) -> &mut Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Fills the box with the given block.
+    pub fn fill(&mut self, bounds: BoundingBox<GlobalCoordinate>, block: Block) -> &mut Self {
+        self.push(EditOperation::Fill { bounds, block })
+    }
+
+    /// Swaps blocks of the `matching` type in the box for the given block.
+    pub fn replace(
+        &mut self,
+        bounds: BoundingBox<GlobalCoordinate>,
+        matching: BlockType,
+        block: Block,
+    ) -> &mut Self {
+        self.push(EditOperation::Replace {
+            bounds,
+            matching,
+            block,
+        })
+    }
+
+    /// Copies the `source` box so its smallest corner lands on `destination`.
+    pub fn copy(
+        &mut self,
+        source: BoundingBox<GlobalCoordinate>,
+        destination: GlobalCoordinate,
+    ) -> &mut Self {
+        self.push(EditOperation::Copy {
+            source,
+            destination,
+        })
+    }
+
+    /// Fills the box with air.
+    pub fn clear(&mut self, bounds: BoundingBox<GlobalCoordinate>) -> &mut Self {
+        self.push(EditOperation::Clear { bounds })
+    }
+
+    /// Pastes a schematic so its smallest corner lands on `origin`.
+    pub fn paste(
+        &mut self,
+        schematic: Arc<Schematic>,
+        origin: GlobalCoordinate,
+        rotation: Rotation,
+        skip_air: bool,
+    ) -> &mut Self {
+        self.push(EditOperation::Paste {
+            schematic,
+            origin,
+            rotation,
+            skip_air,
+        })
+    }
+
+    /// The operations in this transaction, in the order they're applied.
+    pub fn operations(&self) -> &[EditOperation] {
+        &self.operations
+    }
+
+    /// Every chunk this transaction needs to be loaded.
+    fn chunks(&self) -> BTreeSet<ChunkCoordinate> {
+        self.operations
+            .iter()
+            .flat_map(|op| op.areas())
+            .flat_map(|area| area.chunks().all_coordinates())
+            .collect()
+    }
+}
+
+/// A transaction that's been applied to the world.
+///
+/// Keep this around to roll the transaction back with
+/// `MacawWorld::rollback()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedTransaction {
+    /// The chunks with blocks that actually changed.
+    touched: BTreeSet<ChunkCoordinate>,
+    /// What every changed block looked like beforehand.
+    undo: BTreeMap<GlobalCoordinate, (Block, Option<BlockEntity>)>,
+}
+
+impl AppliedTransaction {
+    /// The chunks with blocks that changed.
+    pub fn touched_chunks(&self) -> &BTreeSet<ChunkCoordinate> {
+        &self.touched
+    }
+
+    /// How many blocks changed.
+    pub fn changed_blocks(&self) -> usize {
+        self.undo.len()
+    }
+}
+
+impl MacawWorld {
+    /// Applies every operation in a transaction, in order.
+    ///
+    /// If any chunk the transaction covers isn't loaded, this fails without
+    /// changing anything.
+    pub fn apply(&mut self, transaction: &Transaction) -> Result<AppliedTransaction, EditError> {
+        self.require_loaded(transaction.chunks())?;
+
+        // work out the final blocks first. later operations see the results of
+        // earlier ones, like copying a freshly filled area. only copies carry
+        // block entities, everything else gets whatever its block starts with
+        let mut pending = BTreeMap::new();
+        for operation in transaction.operations() {
+            let read = |coords: GlobalCoordinate| -> (Block, Option<BlockEntity>) {
+                pending.get(&coords).cloned().unwrap_or_else(|| {
+                    let chunk = self.chunks().get(&coords.chunk());
+                    (
+                        self.loaded_block(coords).unwrap_or_default(),
+                        chunk.and_then(|c| c.block_entity(&coords.local()).cloned()),
+                    )
+                })
+            };
+
+            let writes = match operation {
+                EditOperation::Fill { bounds, block } => bounds
+                    .all_coordinates()
+                    .into_iter()
+                    .map(|coords| (coords, (block.clone(), None)))
+                    .collect::<Vec<_>>(),
+                EditOperation::Replace {
+                    bounds,
+                    matching,
+                    block,
+                } => bounds
+                    .all_coordinates()
+                    .into_iter()
+                    .filter(|coords| read(*coords).0.block_type == *matching)
+                    .map(|coords| (coords, (block.clone(), None)))
+                    .collect(),
+                EditOperation::Copy {
+                    source,
+                    destination,
+                } => {
+                    let smaller = source.smaller();
+                    source
+                        .all_coordinates()
+                        .into_iter()
+                        .map(|coords| {
+                            let target = GlobalCoordinate::new(
+                                destination.x + coords.x - smaller.x,
+                                destination.y + coords.y - smaller.y,
+                                destination.z + coords.z - smaller.z,
+                            );
+                            (target, read(coords))
+                        })
+                        .collect()
+                }
+                EditOperation::Clear { bounds } => bounds
+                    .all_coordinates()
+                    .into_iter()
+                    .map(|coords| (coords, (Block::default(), None)))
+                    .collect(),
+                EditOperation::Paste {
+                    schematic,
+                    origin,
+                    rotation,
+                    skip_air,
+                } => schematic
+                    .rotated(*rotation)
+                    .blocks()
+                    .filter(|(_, block)| !(*skip_air && block.block_type == BlockType::AIR))
+                    .map(|((x, y, z), block)| {
+                        let target = GlobalCoordinate::new(
+                            origin.x + x as i64,
+                            origin.y + y as i64,
+                            origin.z + z as i64,
+                        );
+                        (target, (block.clone(), None))
+                    })
+                    .collect(),
+            };
+
+            pending.extend(writes);
+        }
+
+        let undo = self.write_blocks(pending);
+        Ok(AppliedTransaction {
+            touched: undo.keys().map(|coords| coords.chunk()).collect(),
+            undo,
+        })
+    }
+
+    /// Puts back every block changed by a transaction, along with any block
+    /// entities they had.
+    ///
+    /// Returns the chunks that were changed back. If any of them aren't
+    /// loaded anymore, this fails without changing anything.
+    pub fn rollback(
+        &mut self,
+        applied: AppliedTransaction,
+    ) -> Result<BTreeSet<ChunkCoordinate>, EditError> {
+        self.require_loaded(applied.touched.iter().copied())?;
+        let _ = self.write_blocks(applied.undo);
+        Ok(applied.touched)
+    }
+
+    /// Writes blocks into loaded chunks, then updates the heightmaps and
+    /// light around them. Blocks written with a block entity get that one
+    /// instead of a fresh one.
+    ///
+    /// Returns what was there before for every block that changed.
+    fn write_blocks(
+        &mut self,
+        blocks: BTreeMap<GlobalCoordinate, (Block, Option<BlockEntity>)>,
+    ) -> BTreeMap<GlobalCoordinate, (Block, Option<BlockEntity>)> {
+        let mut undo = BTreeMap::new();
+
+        for (coords, (block, entity)) in blocks {
+            let Some(chunk) = self.chunk(coords.chunk()) else {
+                continue;
+            };
+
+            let local = coords.local();
+            let Some(old) = chunk.block(&local) else {
+                continue;
+            };
+
+            // the same block can still hold different things, like two chests
+            let old_entity = chunk.block_entity(&local).cloned();
+            if old == block && (entity.is_none() || entity == old_entity) {
+                continue;
+            }
+
+            chunk.set_block(block, local);
+            if let Some(entity) = entity {
+                chunk
+                    .set_block_entity(local, entity)
+                    .expect("block entity matches the block it came with");
+            }
+            undo.insert(coords, (old, old_entity));
+        }
+
+        let changed = undo.keys().copied().collect::<Vec<_>>();
+        for coords in &changed {
+            self.update_heights(*coords);
+        }
+        self.relight(&changed);
+
+        undo
+    }
+
+    /// Makes sure all of the given chunks are loaded.
+    fn require_loaded(
+        &self,
+        chunks: impl IntoIterator<Item = ChunkCoordinate>,
+    ) -> Result<(), EditError> {
+        for coords in chunks {
+            if !self.chunks().contains_key(&coords) {
+                return Err(EditError::ChunkNotLoaded(coords));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum EditError {
+    #[error("Can't edit the chunk at `{0}`, as it isn't loaded.")]
+    ChunkNotLoaded(ChunkCoordinate),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditError, Transaction};
+    use crate::{
+        block::{
+            entity::{BlockEntity, Inventory, ItemStack},
+            Block, BlockType,
+        },
+        world::{
+            chunk::Chunk,
+            coordinates::{BoundingBox, ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
+            MacawWorld,
+        },
+    };
+
+    fn world_with_chunks(chunks: &[ChunkCoordinate]) -> MacawWorld {
+        let mut world = MacawWorld::default();
+        for coords in chunks {
+            world.set_chunk(Chunk::new(*coords), *coords);
+        }
+        world
+    }
+
+    #[test]
+    fn fill_across_chunks_and_roll_back() {
+        let (left, right) = (ChunkCoordinate::new(-1, 0, 0), ChunkCoordinate::ORIGIN);
+        let mut world = world_with_chunks(&[left, right]);
+        let stone = Block::new(BlockType::STONE, 0);
+
+        let mut transaction = Transaction::new();
+        transaction.fill(
+            BoundingBox::new(
+                GlobalCoordinate::new(-2, 0, 0),
+                GlobalCoordinate::new(1, 0, 0),
+            ),
+            stone.clone(),
+        );
+        let applied = world.apply(&transaction).unwrap();
+
+        assert_eq!(applied.changed_blocks(), 4);
+        assert_eq!(applied.touched_chunks().len(), 2);
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(-2, 0, 0)),
+            Some(stone)
+        );
+
+        let rolled_back = world.rollback(applied).unwrap();
+        assert_eq!(rolled_back.len(), 2);
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(-2, 0, 0)),
+            Some(Block::default())
+        );
+    }
+
+    #[test]
+    fn later_operations_see_earlier_ones() {
+        let mut world = world_with_chunks(&[ChunkCoordinate::ORIGIN]);
+        let (dirt, grass) = (
+            Block::new(BlockType::DIRT, 0),
+            Block::new(BlockType::GRASS, 0),
+        );
+
+        let area = BoundingBox::new(
+            GlobalCoordinate::new(0, 0, 0),
+            GlobalCoordinate::new(1, 1, 1),
+        );
+        let mut transaction = Transaction::new();
+        transaction
+            .fill(area, dirt)
+            .replace(area, BlockType::DIRT, grass.clone())
+            .copy(area, GlobalCoordinate::new(4, 0, 0));
+        world.apply(&transaction).unwrap();
+
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(5, 1, 1)),
+            Some(grass)
+        );
+    }
+
+    #[test]
+    fn copies_bring_block_entities_along() {
+        let mut world = world_with_chunks(&[ChunkCoordinate::ORIGIN]);
+        let chest = Block::new(BlockType::CHEST, 0);
+        let (source, target) = (
+            ChunkBlockCoordinate::new(0, 0, 0),
+            ChunkBlockCoordinate::new(2, 0, 0),
+        );
+
+        let mut inventory = Inventory::new(Inventory::CHEST_SIZE);
+        inventory.set(
+            3,
+            Some(ItemStack {
+                item: 264,
+                count: 5,
+                damage: 0,
+            }),
+        );
+        let filled = BlockEntity::Chest(inventory);
+
+        // an empty chest is already sitting where the copy lands
+        let chunk = world.chunk(ChunkCoordinate::ORIGIN).unwrap();
+        chunk.set_block(chest.clone(), source);
+        chunk.set_block_entity(source, filled.clone()).unwrap();
+        chunk.set_block(chest.clone(), target);
+        let empty = chunk.block_entity(&target).cloned();
+
+        let mut transaction = Transaction::new();
+        transaction.copy(
+            BoundingBox::new_point(GlobalCoordinate::ORIGIN),
+            GlobalCoordinate::new(2, 0, 0),
+        );
+        let applied = world.apply(&transaction).unwrap();
+
+        let entity = |world: &MacawWorld| {
+            world.chunks()[&ChunkCoordinate::ORIGIN]
+                .block_entity(&target)
+                .cloned()
+        };
+        assert_eq!(applied.changed_blocks(), 1);
+        assert_eq!(entity(&world), Some(filled));
+
+        // rolling back empties the chest again
+        world.rollback(applied).unwrap();
+        assert_eq!(entity(&world), empty);
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(2, 0, 0)),
+            Some(chest)
+        );
+    }
+
+    #[test]
+    fn unloaded_chunks_change_nothing() {
+        let mut world = world_with_chunks(&[ChunkCoordinate::ORIGIN]);
+
+        let mut transaction = Transaction::new();
+        transaction.fill(
+            BoundingBox::new(
+                GlobalCoordinate::new(0, 0, 0),
+                GlobalCoordinate::new(16, 0, 0),
+            ),
+            Block::new(BlockType::STONE, 0),
+        );
+
+        assert_eq!(
+            world.apply(&transaction),
+            Err(EditError::ChunkNotLoaded(ChunkCoordinate::new(1, 0, 0)))
+        );
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::ORIGIN),
+            Some(Block::default())
+        );
+    }
+}
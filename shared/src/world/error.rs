@@ -1,9 +1,15 @@
 use thiserror::Error;
 
-use super::coordinates::GlobalCoordinate;
+use super::coordinates::ChunkCoordinate;
+use crate::block::{entity::BlockEntityKind, BlockType};
 
 #[derive(Clone, Copy, Debug, Error)]
 pub enum WorldError {
     #[error("A chunk already exists at location: `{0}`")]
-    ChunkAlreadyExists(GlobalCoordinate),
+    ChunkAlreadyExists(ChunkCoordinate),
+    #[error("Blocks of type `{block_type}` can't hold a `{kind:?}` block entity.")]
+    BlockEntityMismatch {
+        block_type: BlockType,
+        kind: BlockEntityKind,
+    },
 }
@@ -59,26 +59,26 @@ impl BiomeBlockLayout {
     /// Creates a 'sandy' set of layers for biomes like beaches and deserts.
     pub fn sandy() -> Self {
         BiomeBlockLayout {
-            grass: BlockType::Sand,
-            dirt: BlockType::Sandstone,
-            stone: BlockType::Stone,
+            grass: BlockType::SAND,
+            dirt: BlockType::SANDSTONE,
+            stone: BlockType::STONE,
         }
     }
 
     /// Creates a 'wet' set of layers for biomes like oceans, lakes, and rivers.
     pub fn wet() -> Self {
         Self {
-            grass: BlockType::Water,
-            dirt: BlockType::Water,
-            stone: BlockType::Stone,
+            grass: BlockType::WATER,
+            dirt: BlockType::WATER,
+            stone: BlockType::STONE,
         }
     }
 
     pub fn cold_wet() -> Self {
         Self {
-            grass: BlockType::Ice,
-            dirt: BlockType::Water,
-            stone: BlockType::Stone,
+            grass: BlockType::ICE,
+            dirt: BlockType::WATER,
+            stone: BlockType::STONE,
         }
     }
 }
@@ -86,9 +86,9 @@ impl BiomeBlockLayout {
 impl Default for BiomeBlockLayout {
     fn default() -> Self {
         Self {
-            grass: BlockType::Grass,
-            dirt: BlockType::Dirt,
-            stone: BlockType::Stone,
+            grass: BlockType::GRASS,
+            dirt: BlockType::DIRT,
+            stone: BlockType::STONE,
         }
     }
 }
@@ -1,3 +1,21 @@
+use self::blank::BlankGenerator;
+use super::{Generator as _, GeneratorWrapper};
+use crate::world::metadata::GeneratorId;
+
 pub mod blank;
 pub mod default;
 pub mod fixed;
+
+/// Finds the generator a world was made with, set up with the world's seed.
+///
+/// Gives back `None` for generators that can't make chunks on demand,
+/// including ones this game doesn't know about.
+pub fn resolve(id: &GeneratorId, _seed: u64) -> Option<GeneratorWrapper> {
+    if *id == BlankGenerator.id() {
+        return Some(GeneratorWrapper::new(BlankGenerator));
+    }
+
+    // `DefaultGenerator` only fills whole worlds up front, so its worlds
+    // keep their holes until it learns to shape one chunk at a time
+    None
+}
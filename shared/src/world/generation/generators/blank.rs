@@ -5,7 +5,12 @@
 
 use crate::{
     util::built_info,
-    world::{metadata::GeneratorId, Generator, MacawWorld},
+    world::{
+        chunk::Chunk,
+        coordinates::{BoundingBox, ChunkCoordinate},
+        metadata::GeneratorId,
+        Generator, MacawWorld,
+    },
 };
 use async_trait::async_trait;
 
@@ -35,13 +40,9 @@ impl Generator for BlankGenerator {
         MacawWorld::default()
     }
 
-    async fn generate(
-        &mut self,
-        _world: &mut MacawWorld,
-        _chunks: (
-            crate::world::coordinates::ChunkBlockCoordinate,
-            crate::world::coordinates::ChunkBlockCoordinate,
-        ),
-    ) {
+    async fn generate(&mut self, _world: &mut MacawWorld, _chunks: BoundingBox<ChunkCoordinate>) {}
+
+    fn terrain(&self, coords: ChunkCoordinate) -> Option<Chunk> {
+        Some(Chunk::new(coords))
     }
 }
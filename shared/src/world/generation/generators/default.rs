@@ -7,6 +7,7 @@ use async_trait::async_trait;
 use crate::{
     util::built_info,
     world::{
+        coordinates::{BoundingBox, ChunkCoordinate},
         generation::{biomes::DefaultBiomeGenerator, Generator},
         metadata::GeneratorId,
         MacawWorld,
@@ -61,14 +62,7 @@ impl Generator for DefaultGenerator {
         todo!()
     }
 
-    async fn generate(
-        &mut self,
-        _world: &mut MacawWorld,
-        _chunks: (
-            crate::world::coordinates::ChunkBlockCoordinate,
-            crate::world::coordinates::ChunkBlockCoordinate,
-        ),
-    ) {
+    async fn generate(&mut self, _world: &mut MacawWorld, _chunks: BoundingBox<ChunkCoordinate>) {
         todo!()
     }
 }
@@ -13,10 +13,12 @@ use crate::{
     block::{Block, BlockType},
     world::{
         chunk::{Chunk, CHUNK_LENGTH},
-        coordinates::{ChunkBlockCoordinate, GlobalCoordinate},
+        coordinates::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
         generation::Generator as _,
         loader::WorldLoader,
         metadata::WorldMetadata,
+        state::WorldState,
+        storage::{MemoryStorage, Storage},
         MacawWorld,
     },
 };
@@ -26,14 +28,16 @@ use super::blank::BlankGenerator;
 pub struct Generate;
 
 impl Generate {
-    pub fn testing_world() -> MacawWorld {
+    /// A world with a chunk of random blocks and a couple of blocks to
+    /// stand on, kept in the given storage.
+    pub fn testing_world(storage: Storage) -> MacawWorld {
         let mut chunks = HashMap::new();
 
         // -----------------------
         // create the random chunk
         // -----------------------
         let mut rng = rand::thread_rng();
-        let rand_chunk_coord = GlobalCoordinate::new(4, 4, 4);
+        let rand_chunk_coord = ChunkCoordinate::new(4, 4, 4);
         let mut random_chunk = Chunk::new(rand_chunk_coord);
 
         for x in 0..CHUNK_LENGTH {
@@ -42,9 +46,9 @@ impl Generate {
                     let coord = ChunkBlockCoordinate::new(x, y, z);
 
                     let block_type = match Uniform::from(0..=2).sample(&mut rng) {
-                        0 => BlockType::Stone,
-                        1 => BlockType::Air,
-                        2 => BlockType::Grass,
+                        0 => BlockType::STONE,
+                        1 => BlockType::AIR,
+                        2 => BlockType::GRASS,
                         _ => {
                             unreachable!("rng only gens from 0 to 2")
                         }
@@ -61,14 +65,14 @@ impl Generate {
         // create two block testing area
         // -----------------------------
 
-        let tbc_coord = GlobalCoordinate::new(0, 0, 0);
+        let tbc_coord = ChunkCoordinate::new(0, 0, 0);
         let mut two_block_chunk = Chunk::new(tbc_coord);
         two_block_chunk.set_block(
-            Block::new(BlockType::Stone, 0),
+            Block::new(BlockType::STONE, 0),
             ChunkBlockCoordinate::new(0, 0, 0),
         );
         two_block_chunk.set_block(
-            Block::new(BlockType::Stone, 0),
+            Block::new(BlockType::STONE, 0),
             ChunkBlockCoordinate::new(1, 0, 0),
         );
 
@@ -80,21 +84,26 @@ impl Generate {
             BlankGenerator.id(),
         ));
 
-        let mut loader = WorldLoader::new(metadata.clone()).expect("failed to load testing world");
+        let mut loader =
+            WorldLoader::new(metadata.clone(), storage).expect("failed to load testing world");
         loader.chunks_mut().extend(chunks);
 
         MacawWorld {
-            loader,
+            generator: MacawWorld::generator_for(&metadata),
             metadata,
-            ..MacawWorld::default()
+            loader,
+            entities: HashSet::new(),
+            // stand on the two blocks
+            state: WorldState::new(GlobalCoordinate::new(0, 2, 0)),
+            players: HashMap::new(),
         }
     }
 
     pub fn one_test_block() -> MacawWorld {
-        let chunk_coordinate = GlobalCoordinate::new(0, 0, 0);
+        let chunk_coordinate = ChunkCoordinate::new(0, 0, 0);
         let mut chunk = Chunk::new(chunk_coordinate);
         chunk.set_block(
-            Block::new(BlockType::Grass, 0),
+            Block::new(BlockType::GRASS, 0),
             ChunkBlockCoordinate::new(0, 0, 0),
         );
 
@@ -104,26 +113,28 @@ impl Generate {
             BlankGenerator.id(),
         ));
 
-        let mut loader = WorldLoader::new(metadata.clone()).unwrap();
+        let mut loader =
+            WorldLoader::new(metadata.clone(), Arc::new(MemoryStorage::new())).unwrap();
         loader.chunks_mut().insert(chunk_coordinate, chunk);
 
         MacawWorld {
+            generator: MacawWorld::generator_for(&metadata),
             metadata,
             loader,
             entities: HashSet::new(),
-            spawn_location: GlobalCoordinate::ORIGIN,
+            state: WorldState::default(),
+            players: HashMap::new(),
         }
     }
 
     pub async fn generate_test_chunk() -> MacawWorld {
-        let mut chunk =
-            Chunk::new_filled(Block::new(BlockType::Stone, 0), GlobalCoordinate::ORIGIN);
+        let mut chunk = Chunk::new_filled(Block::new(BlockType::STONE, 0), ChunkCoordinate::ORIGIN);
 
         // set first layer as grass
         for x in 0..16 {
             for z in 0..16 {
                 chunk.set_block(
-                    Block::new(BlockType::Grass, 0),
+                    Block::new(BlockType::GRASS, 0),
                     ChunkBlockCoordinate::new(x, 15, z),
                 )
             }
@@ -134,7 +145,7 @@ impl Generate {
             for y in 12..=14 {
                 for z in 0..16 {
                     chunk.set_block(
-                        Block::new(BlockType::Dirt, 0),
+                        Block::new(BlockType::DIRT, 0),
                         ChunkBlockCoordinate::new(x, y, z),
                     )
                 }
@@ -147,15 +158,18 @@ impl Generate {
             BlankGenerator.id(),
         ));
 
-        let mut loader = WorldLoader::new(metadata.clone()).expect("failed to load testing world");
+        let mut loader = WorldLoader::new(metadata.clone(), Arc::new(MemoryStorage::new()))
+            .expect("failed to load testing world");
 
-        loader.chunks_mut().insert(GlobalCoordinate::ORIGIN, chunk);
+        loader.chunks_mut().insert(ChunkCoordinate::ORIGIN, chunk);
 
         MacawWorld {
+            generator: MacawWorld::generator_for(&metadata),
             metadata,
             loader,
             entities: HashSet::new(),
-            spawn_location: GlobalCoordinate::new(0, 18, 0),
+            state: WorldState::new(GlobalCoordinate::new(0, 18, 0)),
+            players: HashMap::new(),
         }
     }
 }
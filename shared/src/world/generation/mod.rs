@@ -1,9 +1,15 @@
-use super::{coordinates::ChunkBlockCoordinate, metadata::GeneratorId, MacawWorld};
+use super::{
+    chunk::Chunk,
+    coordinates::{BoundingBox, ChunkCoordinate},
+    metadata::GeneratorId,
+    MacawWorld,
+};
 use async_trait::async_trait;
 use std::sync::Arc;
 
 pub mod biomes;
 pub mod generators;
+pub mod status;
 
 /// The maximum generation height of the typical world.
 ///
@@ -12,7 +18,7 @@ pub mod generators;
 pub const MAX_GEN_HEIGHT: u32 = 256;
 
 #[async_trait]
-pub trait Generator {
+pub trait Generator: Send + Sync {
     /// The name of this generator.
     fn name(&self) -> &'static str;
 
@@ -25,12 +31,24 @@ pub trait Generator {
     /// Creates a new MacawWorld and returns it.
     async fn pre_generate(&mut self, seed: u64) -> MacawWorld;
 
-    /// Given an existing world and two chunk coordinates, generates the chunks in between.
-    async fn generate(
-        &mut self,
-        world: &mut MacawWorld,
-        chunks: (ChunkBlockCoordinate, ChunkBlockCoordinate),
-    );
+    /// Given an existing world and a box of chunk coordinates, generates the
+    /// chunks within.
+    async fn generate(&mut self, world: &mut MacawWorld, chunks: BoundingBox<ChunkCoordinate>);
+
+    /// Shapes the land in a chunk. This is the first step of generating a
+    /// chunk on demand, when it's missing from disk.
+    ///
+    /// Gives back `None` if this generator can't make chunks on demand. By
+    /// default, it can't, and missing chunks are left as holes.
+    fn terrain(&self, _coords: ChunkCoordinate) -> Option<Chunk> {
+        None
+    }
+
+    /// Carves caves and ravines out of a chunk's terrain.
+    fn carve(&self, _chunk: &mut Chunk) {}
+
+    /// Adds ores, trees, flowers and so on to a carved chunk.
+    fn decorate(&self, _chunk: &mut Chunk) {}
 }
 
 pub struct GeneratorWrapper(pub Arc<dyn Generator>);
@@ -0,0 +1,158 @@
+//! # Status
+//!
+//! Generating chunks on demand, one step at a time.
+//!
+//! Chunks that are missing from disk are made by the world's `Generator`.
+//! Each goes through a few steps: its terrain is shaped, then carved, then
+//! decorated, and finally lit once it's put in the world. Until then, it's
+//! kept aside, so a half-made chunk is never shown or saved as finished.
+
+use serde::{Deserialize, Serialize};
+
+use crate::world::{chunk::journal::JournalKind, coordinates::ChunkCoordinate, MacawWorld};
+
+/// How many chunks can start generating in one go. The rest wait for the
+/// next call, so generating a lot of chunks doesn't stall a frame.
+pub const GENERATION_BUDGET: usize = 32;
+
+/// The last step a chunk finished while being generated.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub enum GenerationStatus {
+    /// The land is shaped.
+    Terrain,
+    /// Caves and ravines are cut out.
+    Carved,
+    /// Ores, trees and so on are placed.
+    Decorated,
+    /// The chunk is lit and in the world. It's done!
+    Lit,
+}
+
+impl MacawWorld {
+    /// Moves every chunk being generated along by one step, then starts
+    /// generating the given chunks, in order, if they're known to be missing
+    /// from disk.
+    ///
+    /// Finished chunks are put in the world and marked to be saved. Gives
+    /// back the chunks that finished.
+    pub fn generate_chunks(
+        &mut self,
+        chunks: impl IntoIterator<Item = ChunkCoordinate>,
+    ) -> Vec<ChunkCoordinate> {
+        // worlds from generators that can't make chunks on demand keep their holes
+        let Some(generator) = self.generator.clone() else {
+            return Vec::new();
+        };
+
+        let mut finished = Vec::new();
+        for (coords, (chunk, status)) in self.loader.generating_mut().iter_mut() {
+            match status {
+                GenerationStatus::Terrain => {
+                    generator.0.carve(chunk);
+                    *status = GenerationStatus::Carved;
+                }
+                GenerationStatus::Carved => {
+                    generator.0.decorate(chunk);
+                    *status = GenerationStatus::Decorated;
+                }
+                GenerationStatus::Decorated | GenerationStatus::Lit => finished.push(*coords),
+            }
+        }
+
+        // lighting needs the chunk in the world, next to its neighbours
+        for coords in finished.iter() {
+            if let Some((mut chunk, _)) = self.loader.generating_mut().remove(coords) {
+                chunk.mark_dirty(JournalKind::Save);
+                self.set_chunk(chunk, *coords);
+            }
+        }
+
+        let starting = chunks
+            .into_iter()
+            .filter(|coords| self.loader.is_absent(*coords))
+            .take(GENERATION_BUDGET)
+            .collect::<Vec<_>>();
+        for coords in starting {
+            let Some(chunk) = generator.0.terrain(coords) else {
+                continue;
+            };
+            self.loader.start_generating(chunk);
+        }
+
+        finished
+    }
+
+    /// How far along a chunk is in being generated. See
+    /// `WorldLoader::generation_status()`.
+    pub fn generation_status(&self, chunk: ChunkCoordinate) -> Option<GenerationStatus> {
+        self.loader.generation_status(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GenerationStatus;
+    use crate::world::{
+        chunk::journal::JournalKind,
+        coordinates::ChunkCoordinate,
+        generation::{
+            generators::{self, default::DefaultGenerator},
+            Generator as _,
+        },
+        streaming::RenderDistance,
+        MacawWorld,
+    };
+
+    #[test]
+    fn missing_chunks_are_generated_step_by_step() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let centre = [ChunkCoordinate::ORIGIN];
+        let distance = RenderDistance::new(0, 0);
+
+        // first, the chunk turns out not to be saved anywhere
+        world.stream_around(&centre, distance);
+        world.loader.finish();
+        assert_eq!(world.generation_status(ChunkCoordinate::ORIGIN), None);
+
+        // then it's made, but kept out of sight until it's done
+        for step in [
+            GenerationStatus::Terrain,
+            GenerationStatus::Carved,
+            GenerationStatus::Decorated,
+        ] {
+            world.stream_around(&centre, distance);
+            assert_eq!(world.generation_status(ChunkCoordinate::ORIGIN), Some(step));
+            assert!(world.chunks().is_empty());
+        }
+
+        world.stream_around(&centre, distance);
+        assert_eq!(
+            world.generation_status(ChunkCoordinate::ORIGIN),
+            Some(GenerationStatus::Lit)
+        );
+        assert!(world.chunks()[&ChunkCoordinate::ORIGIN].is_dirty(JournalKind::Save));
+
+        // and it's saved like any other chunk
+        world.save()?;
+        let loaded = MacawWorld::load(world.metadata(), world.storage())?;
+        assert!(loaded.chunks().contains_key(&ChunkCoordinate::ORIGIN));
+
+        Ok(())
+    }
+
+    #[test]
+    fn generators_that_cant_make_chunks_leave_holes() {
+        let mut world = MacawWorld::default();
+        world.generator = generators::resolve(&DefaultGenerator::new(0).id(), 0);
+        assert!(world.generator.is_none());
+
+        let centre = [ChunkCoordinate::ORIGIN];
+        let distance = RenderDistance::new(0, 0);
+        world.stream_around(&centre, distance);
+        world.loader.finish();
+        world.stream_around(&centre, distance);
+
+        assert_eq!(world.generation_status(ChunkCoordinate::ORIGIN), None);
+        assert!(world.chunks().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! # Heightmap
+//!
+//! The highest blocks in each chunk column, kept up to date as blocks change.
+//!
+//! Chunks have no vertical limit, so finding the surface would otherwise mean
+//! scanning every loaded chunk in a column. Heights only account for loaded
+//! chunks, and stop at the first unloaded chunk below them.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chunk::{Chunk, CHUNK_LENGTH},
+    coordinates::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
+    MacawWorld,
+};
+use crate::block::{Block, BlockSide};
+
+/// The blocks that count towards a heightmap.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum HeightmapKind {
+    /// Blocks that entities can't move through.
+    MotionBlocking,
+    /// Blocks that dim light at all.
+    LightBlocking,
+}
+
+impl HeightmapKind {
+    /// Both kinds of heightmap.
+    pub const ALL: [HeightmapKind; 2] =
+        [HeightmapKind::MotionBlocking, HeightmapKind::LightBlocking];
+
+    /// Checks if a block counts towards this kind of heightmap.
+    pub fn counts(&self, block: &Block) -> bool {
+        match self {
+            HeightmapKind::MotionBlocking => block.is_solid(),
+            HeightmapKind::LightBlocking => block.light_opacity() > 0,
+        }
+    }
+}
+
+/// The heights of every block column in a chunk column.
+///
+/// A height is the `y` of the highest block counting towards the heightmap,
+/// or `None` when there isn't one.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Heightmap {
+    motion_blocking: Vec<Option<i64>>,
+    light_blocking: Vec<Option<i64>>,
+}
+
+impl Heightmap {
+    /// Creates a heightmap without any blocks in it.
+    pub fn new() -> Self {
+        let area = CHUNK_LENGTH as usize * CHUNK_LENGTH as usize;
+
+        Self {
+            motion_blocking: vec![None; area],
+            light_blocking: vec![None; area],
+        }
+    }
+
+    /// Gets the height at the given local `x` and `z`.
+    pub fn get(&self, kind: HeightmapKind, x: u8, z: u8) -> Option<i64> {
+        self.heights(kind)[Self::index(x, z)]
+    }
+
+    /// Sets the height at the given local `x` and `z`.
+    pub fn set(&mut self, kind: HeightmapKind, x: u8, z: u8, height: Option<i64>) {
+        let index = Self::index(x, z);
+
+        match kind {
+            HeightmapKind::MotionBlocking => self.motion_blocking[index] = height,
+            HeightmapKind::LightBlocking => self.light_blocking[index] = height,
+        }
+    }
+
+    fn heights(&self, kind: HeightmapKind) -> &[Option<i64>] {
+        match kind {
+            HeightmapKind::MotionBlocking => &self.motion_blocking,
+            HeightmapKind::LightBlocking => &self.light_blocking,
+        }
+    }
+
+    fn index(x: u8, z: u8) -> usize {
+        z as usize * CHUNK_LENGTH as usize + x as usize
+    }
+}
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MacawWorld {
+    /// The `y` of the highest block at the given `x` and `z` counting towards
+    /// this kind of heightmap, if there is one.
+    pub fn height(&self, kind: HeightmapKind, x: i64, z: i64) -> Option<i64> {
+        let block = GlobalCoordinate::new(x, 0, z);
+        let local = block.local();
+
+        self.loader
+            .heightmaps_ref()
+            .get(&block.column())?
+            .get(kind, local.x(), local.z())
+    }
+
+    /// Updates the heightmaps after a block has changed.
+    pub(crate) fn update_heights(&mut self, coords: GlobalCoordinate) {
+        let Some(block) = self.loaded_block(coords) else {
+            return;
+        };
+
+        for kind in HeightmapKind::ALL {
+            let current = self.height(kind, coords.x, coords.z);
+
+            if kind.counts(&block) {
+                if current.is_none_or(|h| coords.y > h) {
+                    self.set_height(kind, coords, Some(coords.y));
+                }
+            } else if current == Some(coords.y) {
+                // the top block is gone, so look for the next one down
+                let below = self.scan_height(kind, coords.next(&BlockSide::NegativeY));
+                self.set_height(kind, coords, below);
+            }
+        }
+    }
+
+    /// Updates the heightmaps after a whole chunk has been placed.
+    pub(crate) fn update_chunk_heights(&mut self, coords: ChunkCoordinate) {
+        let Some(chunk) = self.chunks().get(&coords) else {
+            return;
+        };
+
+        let (min_y, max_y) = (
+            coords.min_block().y,
+            coords.min_block().y + CHUNK_LENGTH as i64 - 1,
+        );
+
+        let mut changes = Vec::new();
+        for x in 0..CHUNK_LENGTH {
+            for z in 0..CHUNK_LENGTH {
+                let top = coords.block(ChunkBlockCoordinate::new(x, CHUNK_LENGTH - 1, z));
+
+                for kind in HeightmapKind::ALL {
+                    let current = self.height(kind, top.x, top.z);
+
+                    // anything above this chunk still wins
+                    if current.is_some_and(|h| h > max_y) {
+                        continue;
+                    }
+
+                    match highest_in_chunk(chunk, kind, x, z) {
+                        Some(local_y) => changes.push((kind, top, Some(min_y + local_y as i64))),
+                        None if current.is_some_and(|h| h >= min_y) => {
+                            changes.push((kind, top, None))
+                        }
+                        None => (),
+                    }
+                }
+            }
+        }
+
+        for (kind, top, height) in changes {
+            // an emptied chunk passes the search on to the chunks below it
+            let height = height
+                .or_else(|| self.scan_height(kind, GlobalCoordinate::new(top.x, min_y - 1, top.z)));
+            self.set_height(kind, top, height);
+        }
+    }
+
+    /// Searches downwards from `from` for the first block counting towards
+    /// the heightmap, stopping at unloaded chunks.
+    fn scan_height(&self, kind: HeightmapKind, from: GlobalCoordinate) -> Option<i64> {
+        let mut y = from.y;
+
+        loop {
+            let block = self.loaded_block(GlobalCoordinate::new(from.x, y, from.z))?;
+            if kind.counts(&block) {
+                return Some(y);
+            }
+
+            y -= 1;
+        }
+    }
+
+    fn set_height(&mut self, kind: HeightmapKind, coords: GlobalCoordinate, height: Option<i64>) {
+        // unchanged heights shouldn't get their column saved again
+        if self.height(kind, coords.x, coords.z) == height {
+            return;
+        }
+
+        let local = coords.local();
+
+        self.loader
+            .heightmap_mut(coords.column())
+            .set(kind, local.x(), local.z(), height);
+    }
+}
+
+/// Finds the local `y` of the highest block in a chunk's block column that
+/// counts towards the heightmap.
+fn highest_in_chunk(chunk: &Chunk, kind: HeightmapKind, x: u8, z: u8) -> Option<u8> {
+    if let Some(uniform) = chunk.uniform_block() {
+        return kind.counts(uniform).then_some(CHUNK_LENGTH - 1);
+    }
+
+    (0..CHUNK_LENGTH).rev().find(|&y| {
+        chunk
+            .block(&ChunkBlockCoordinate::new(x, y, z))
+            .is_some_and(|block| kind.counts(&block))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeightmapKind;
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::Chunk,
+            coordinates::{ChunkCoordinate, GlobalCoordinate},
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn heights_follow_blocks() {
+        let mut world = MacawWorld::default();
+        let stone = Block::new(BlockType::STONE, 0);
+
+        for y in [0, -1] {
+            let coords = ChunkCoordinate::new(0, y, 0);
+            world.set_chunk(Chunk::new(coords), coords);
+        }
+        assert_eq!(world.height(HeightmapKind::MotionBlocking, 3, 3), None);
+
+        // the ground is in the lower chunk
+        world.set_block(stone.clone(), GlobalCoordinate::new(3, -10, 3));
+        assert_eq!(world.height(HeightmapKind::MotionBlocking, 3, 3), Some(-10));
+
+        // water blocks light, but you can swim through it
+        world.set_block(
+            Block::new(BlockType::WATER, 0),
+            GlobalCoordinate::new(3, 2, 3),
+        );
+        assert_eq!(world.height(HeightmapKind::MotionBlocking, 3, 3), Some(-10));
+        assert_eq!(world.height(HeightmapKind::LightBlocking, 3, 3), Some(2));
+
+        // removing the top block falls back to the one below
+        world.set_block(Block::default(), GlobalCoordinate::new(3, 2, 3));
+        assert_eq!(world.height(HeightmapKind::LightBlocking, 3, 3), Some(-10));
+
+        // new chunks on top take over
+        let above = ChunkCoordinate::new(0, 1, 0);
+        world.set_chunk(Chunk::new_filled(stone, above), above);
+        assert_eq!(world.height(HeightmapKind::MotionBlocking, 3, 3), Some(31));
+    }
+}
@@ -0,0 +1,376 @@
+//! # Light
+//!
+//! Sky light and block light, spread across the world with a flood fill.
+//!
+//! Block light starts at blocks that give off light, like torches, while sky
+//! light starts at every block above the highest light-blocking block in its
+//! column, as the heightmaps see it. Each step away from a source
+//! costs one level, or the light opacity of the block being entered if
+//! that's higher. Full-strength sky light is special: it travels straight
+//! down through clear blocks without fading at all.
+//!
+//! Light only spreads through loaded chunks, crossing chunk borders freely.
+//! Chunks loaded from disk bring their light with them, so only the borders
+//! with their neighbors are relit when they arrive.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::{
+    chunk::light::{LightKind, MAX_LIGHT},
+    coordinates::{ChunkCoordinate, GlobalCoordinate},
+    heightmap::HeightmapKind,
+    MacawWorld,
+};
+use crate::{
+    block::{Block, BlockSide},
+    world::chunk::CHUNK_LENGTH,
+};
+
+impl MacawWorld {
+    /// Places a block at the given coordinates, then updates the heightmaps
+    /// and light around it.
+    ///
+    /// Returns the block that was there before, or `None` if its chunk isn't
+    /// loaded.
+    pub fn set_block(&mut self, block: Block, coords: GlobalCoordinate) -> Option<Block> {
+        let chunk = self.chunk_from_block_coords(coords)?;
+        let old = chunk.block(&coords.local())?;
+        chunk.set_block(block, coords.local());
+
+        self.update_heights(coords);
+        self.relight(&[coords]);
+        Some(old)
+    }
+
+    /// The light level of the given kind at some coordinates, if their
+    /// chunk is loaded.
+    pub fn light(&self, kind: LightKind, coords: GlobalCoordinate) -> Option<u8> {
+        let chunk = self.chunks().get(&coords.chunk())?;
+        Some(chunk.light(kind, &coords.local()))
+    }
+
+    /// How bright the block at some coordinates is: the brighter of its sky
+    /// light and block light.
+    pub fn light_level(&self, coords: GlobalCoordinate) -> Option<u8> {
+        let chunk = self.chunks().get(&coords.chunk())?;
+        Some(chunk.light_level(&coords.local()))
+    }
+
+    /// Lights up a chunk that was just placed into the world.
+    ///
+    /// The blocks bordering it in neighboring chunks are relit too, as the
+    /// new chunk might shade or light them.
+    pub(crate) fn light_chunk(&mut self, coords: ChunkCoordinate) {
+        let min = coords.min_block();
+        let range = -1..=CHUNK_LENGTH as i64;
+
+        let mut changed = Vec::new();
+        for x in range.clone() {
+            for y in range.clone() {
+                for z in range.clone() {
+                    changed.push(GlobalCoordinate::new(min.x + x, min.y + y, min.z + z));
+                }
+            }
+        }
+
+        self.relight(&changed);
+    }
+
+    /// Relights the borders between a chunk loaded from disk and the loaded
+    /// chunks around it.
+    ///
+    /// Its light was saved without knowing about neighbors loaded since, so
+    /// the two sides of each border might not agree.
+    pub(crate) fn light_chunk_borders(&mut self, coords: ChunkCoordinate) {
+        let min = coords.min_block();
+        let length = CHUNK_LENGTH as i64;
+        let edges = [0, length - 1];
+
+        let mut changed = HashSet::new();
+        for x in 0..length {
+            for y in 0..length {
+                for z in 0..length {
+                    if ![x, y, z].iter().any(|c| edges.contains(c)) {
+                        continue;
+                    }
+
+                    let block = GlobalCoordinate::new(min.x + x, min.y + y, min.z + z);
+                    for side in BlockSide::all_sides() {
+                        let outside = block.next(&side);
+                        if outside.chunk() != coords && self.chunks().contains_key(&outside.chunk())
+                        {
+                            changed.insert(block);
+                            changed.insert(outside);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.relight(&changed.into_iter().collect::<Vec<_>>());
+    }
+
+    /// Recomputes the light around blocks that have changed.
+    pub(crate) fn relight(&mut self, changed: &[GlobalCoordinate]) {
+        for kind in [LightKind::Sky, LightKind::Block] {
+            let sources = self.remove_light(kind, changed);
+            self.spread_light(kind, sources);
+        }
+    }
+
+    /// Darkens the given blocks, along with everything that was lit through
+    /// them.
+    ///
+    /// Returns the lit blocks around the darkened area. They need to spread
+    /// their light back in with `spread_light()`.
+    fn remove_light(
+        &mut self,
+        kind: LightKind,
+        changed: &[GlobalCoordinate],
+    ) -> VecDeque<GlobalCoordinate> {
+        let mut removals = VecDeque::new();
+        let mut sources = VecDeque::new();
+
+        for &coords in changed {
+            if let Some(level) = self.light(kind, coords) {
+                self.set_light(kind, coords, 0);
+                removals.push_back((coords, level));
+            }
+        }
+
+        while let Some((coords, level)) = removals.pop_front() {
+            // blocks that make their own light get it back right away
+            let own_light = self.own_light(kind, coords);
+            if own_light > 0 {
+                self.set_light(kind, coords, own_light);
+                sources.push_back(coords);
+            }
+
+            for side in BlockSide::all_sides() {
+                let neighbor = coords.next(&side);
+                let Some(neighbor_level) = self.light(kind, neighbor) else {
+                    continue;
+                };
+
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                // dimmer neighbors were lit through this block, so they go dark
+                // too. brighter ones are lit from elsewhere
+                let straight_down = is_sky_column(kind, side, level);
+                if neighbor_level < level || (straight_down && neighbor_level == MAX_LIGHT) {
+                    self.set_light(kind, neighbor, 0);
+                    removals.push_back((neighbor, neighbor_level));
+                } else {
+                    sources.push_back(neighbor);
+                }
+            }
+        }
+
+        sources
+    }
+
+    /// Spreads light outwards from the given blocks until it fades out.
+    fn spread_light(&mut self, kind: LightKind, mut queue: VecDeque<GlobalCoordinate>) {
+        while let Some(coords) = queue.pop_front() {
+            let Some(level) = self.light(kind, coords) else {
+                continue;
+            };
+
+            for side in BlockSide::all_sides() {
+                let neighbor = coords.next(&side);
+                let Some(block) = self.loaded_block(neighbor) else {
+                    continue;
+                };
+
+                let spread = light_through(kind, side, level, block.light_opacity());
+                if spread > self.light(kind, neighbor).unwrap_or(MAX_LIGHT) {
+                    self.set_light(kind, neighbor, spread);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// The light a block gets without any help from its neighbors.
+    ///
+    /// That's its own glow for block light, or the open sky above it for sky
+    /// light. A column nothing's blocked light in yet is open all the way
+    /// down.
+    fn own_light(&self, kind: LightKind, coords: GlobalCoordinate) -> u8 {
+        let Some(block) = self.loaded_block(coords) else {
+            return 0;
+        };
+
+        match kind {
+            LightKind::Block => block.light_emission(),
+            LightKind::Sky => {
+                let height = self.height(HeightmapKind::LightBlocking, coords.x, coords.z);
+                if height.is_some_and(|height| height >= coords.y) {
+                    0
+                } else {
+                    light_through(kind, BlockSide::NegativeY, MAX_LIGHT, block.light_opacity())
+                }
+            }
+        }
+    }
+
+    /// Sets a light level, if the block's chunk is loaded.
+    fn set_light(&mut self, kind: LightKind, coords: GlobalCoordinate, level: u8) {
+        if let Some(chunk) = self.loader.chunks_mut().get_mut(&coords.chunk()) {
+            chunk.set_light(kind, coords.local(), level);
+        }
+    }
+}
+
+/// How bright a block gets when light of `level` enters it from its
+/// neighbor on `side`.
+fn light_through(kind: LightKind, side: BlockSide, level: u8, opacity: u8) -> u8 {
+    if is_sky_column(kind, side, level) && opacity == 0 {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(opacity.max(1))
+    }
+}
+
+/// Whether this is full-strength sky light heading straight down, which
+/// doesn't fade through clear blocks.
+fn is_sky_column(kind: LightKind, side: BlockSide, level: u8) -> bool {
+    kind == LightKind::Sky && side == BlockSide::NegativeY && level == MAX_LIGHT
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::{journal::JournalKind, light::LightKind, Chunk},
+            coordinates::{ChunkCoordinate, GlobalCoordinate},
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn sky_light_shines_down_under_open_sky() {
+        let mut world = MacawWorld::default();
+        for y in [0, -1] {
+            let coords = ChunkCoordinate::new(0, y, 0);
+            world.set_chunk(Chunk::new(coords), coords);
+        }
+
+        // air all the way down, so nothing fades
+        let bottom = GlobalCoordinate::new(4, -16, 4);
+        assert_eq!(world.light(LightKind::Sky, bottom), Some(15));
+
+        // a roof casts a shadow below it, which fills in from the sides
+        world.set_block(
+            Block::new(BlockType::STONE, 0),
+            GlobalCoordinate::new(4, 10, 4),
+        );
+        assert_eq!(
+            world.light(LightKind::Sky, GlobalCoordinate::new(4, 9, 4)),
+            Some(14)
+        );
+        assert_eq!(world.light(LightKind::Sky, bottom), Some(14));
+
+        // taking it away lets the sky back in
+        world.set_block(Block::default(), GlobalCoordinate::new(4, 10, 4));
+        assert_eq!(world.light(LightKind::Sky, bottom), Some(15));
+    }
+
+    #[test]
+    fn sky_light_follows_the_heightmap() {
+        let mut world = MacawWorld::default();
+        let ground = ChunkCoordinate::new(0, 1, 0);
+        world.set_chunk(
+            Chunk::new_filled(Block::new(BlockType::STONE, 0), ground),
+            ground,
+        );
+
+        // the ground's still in the heightmap once it's gone, so a cave
+        // under it stays dark even though nothing above it is loaded
+        world.loader.chunks_mut().remove(&ground);
+        let cave = ChunkCoordinate::ORIGIN;
+        world.set_chunk(Chunk::new(cave), cave);
+
+        assert_eq!(
+            world.light(LightKind::Sky, GlobalCoordinate::new(4, 15, 4)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn block_light_crosses_chunks() {
+        let mut world = MacawWorld::default();
+        for x in [0, 1] {
+            let coords = ChunkCoordinate::new(x, 0, 0);
+            world.set_chunk(
+                Chunk::new_filled(Block::new(BlockType::STONE, 0), coords),
+                coords,
+            );
+        }
+
+        // carve a tunnel across the chunk border, then light one end
+        for x in 10..20 {
+            world.set_block(Block::default(), GlobalCoordinate::new(x, 8, 8));
+        }
+        world.set_block(
+            Block::new(BlockType::TORCH, 0),
+            GlobalCoordinate::new(10, 8, 8),
+        );
+
+        for x in 10..20 {
+            let light = world.light(LightKind::Block, GlobalCoordinate::new(x, 8, 8));
+            assert_eq!(light, Some(14 - (x - 10) as u8));
+        }
+
+        // stone doesn't let any through
+        assert_eq!(
+            world.light(LightKind::Block, GlobalCoordinate::new(11, 9, 8)),
+            Some(0)
+        );
+
+        // and it all goes away with the light source
+        world.set_block(Block::default(), GlobalCoordinate::new(10, 8, 8));
+        assert_eq!(
+            world.light(LightKind::Block, GlobalCoordinate::new(17, 8, 8)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn borders_are_relit_when_chunks_arrive() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let lit = ChunkCoordinate::ORIGIN;
+        let mut chunk = Chunk::new(lit);
+        chunk.set_block(
+            Block::new(BlockType::TORCH, 0),
+            GlobalCoordinate::new(15, 8, 8).local(),
+        );
+        world.set_chunk(chunk, lit);
+
+        // its neighbor is saved without ever having seen the torch
+        let dark = ChunkCoordinate::new(1, 0, 0);
+        let mut chunk = Chunk::new(dark);
+        chunk.mark_dirty(JournalKind::Save);
+        world.loader.chunks_mut().insert(dark, chunk);
+        world.save()?;
+
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([lit, dark]);
+
+        let mut arrived = Vec::new();
+        while arrived.len() < 2 {
+            arrived.extend(loaded.poll());
+            std::thread::yield_now();
+        }
+
+        assert_eq!(
+            loaded.light(LightKind::Block, GlobalCoordinate::new(16, 8, 8)),
+            Some(13)
+        );
+
+        Ok(())
+    }
+}
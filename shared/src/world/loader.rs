@@ -1,128 +1,1036 @@
 //! # Loader
 //!
 //! A module that saves/loads the world on disk.
+//!
+//! Loading and saving can either happen right away, blocking until they're
+//! done, or in the background on Bevy's task pools. Background work reports
+//! back through a completion queue, which is emptied by `poll()`.
 
+use self::ticket::{Ticket, TicketId, Tickets};
 use super::{
-    chunk::Chunk,
-    coordinates::{bounding_box::BoundingBox, GlobalCoordinate},
-    region::{Region, RegionError},
-    save::WorldSave,
+    chunk::{journal::JournalKind, Chunk, CHUNK_LENGTH},
+    coordinates::{bounding_box::BoundingBox, ChunkCoordinate, ColumnCoordinate, RegionCoordinate},
+    generation::status::GenerationStatus,
+    heightmap::{Heightmap, HeightmapKind},
+    region::{file::RegionFile, Region, RegionError},
+    save::{SaveFiles, WorldSave},
+    storage::{Storage, StorageError},
 };
 use crate::world::metadata::WorldMetadata;
 
-use std::{collections::HashMap, sync::Arc};
+use bevy::tasks::{AsyncComputeTaskPool, IoTaskPool, TaskPool};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+};
 use thiserror::Error;
 
+pub mod ticket;
+
 /// Manages the world's operations to/from disk.
 #[derive(Debug)]
 pub struct WorldLoader {
     /// All currently-loaded chunks in the world.
-    loaded: HashMap<GlobalCoordinate, Chunk>,
+    loaded: HashMap<ChunkCoordinate, Chunk>,
+    /// Heightmaps for every chunk column with loaded chunks.
+    heightmaps: HashMap<ColumnCoordinate, Heightmap>,
+    /// Columns with heightmaps that changed since the last save.
+    dirty_columns: HashSet<ColumnCoordinate>,
+    /// Reports of corrupted data that was quarantined while loading.
+    quarantined: Vec<WorldLoadingError>,
     /// The file the world is being saved into.
     save: WorldSave,
+    /// Handed to background tasks so they can report back when they're done.
+    completion_sender: Sender<Completion>,
+    /// Where background tasks report back to.
+    ///
+    /// Receivers can't be shared between threads, but worlds live in Bevy
+    /// resources, so this one sits behind a mutex.
+    completions: Mutex<Receiver<Completion>>,
+    /// How many loads are running in the background for each region.
+    loading: HashMap<RegionCoordinate, usize>,
+    /// Regions being written in the background.
+    saves_in_flight: HashSet<RegionCoordinate>,
+    /// How many saves are writing the world's metadata, state and players'
+    /// records in the background.
+    files_in_flight: usize,
+    /// Chunks asked for by `load_chunks_async()` that haven't arrived yet.
+    requested: HashSet<ChunkCoordinate>,
+    /// Chunks that were asked for, but aren't saved anywhere.
+    absent: HashSet<ChunkCoordinate>,
+    /// Regions that couldn't be read, and why. They might be from a newer
+    /// version of the game, or storage might be having a bad day. Either
+    /// way, their chunks aren't missing, so nothing is generated over them.
+    unreadable: HashMap<RegionCoordinate, WorldLoadingError>,
+    /// Reasons to keep chunks loaded, other than players being nearby.
+    tickets: Tickets,
+    /// Chunks partway through being generated, with the last step they
+    /// finished. They aren't loaded until they're done, so nothing can show
+    /// or save them early.
+    generating: HashMap<ChunkCoordinate, (Chunk, GenerationStatus)>,
+}
+
+/// Work that finished in the background, waiting to be picked up.
+#[derive(Debug)]
+enum Completion {
+    /// A region's chunks and heightmaps were read.
+    Loaded(RegionContents),
+    /// A region couldn't be read at all.
+    Unreadable {
+        region: RegionCoordinate,
+        requested: Vec<ChunkCoordinate>,
+        error: WorldLoadingError,
+    },
+    /// A region's changes were written, or failed to be.
+    ///
+    /// The copies that were written come back too, so nothing is lost if
+    /// the write failed after their chunks were unloaded.
+    Saved {
+        region: RegionCoordinate,
+        chunks: Vec<Chunk>,
+        heightmaps: Vec<(ColumnCoordinate, Heightmap)>,
+        result: Result<(), WorldLoadingError>,
+    },
+    /// A save's metadata, state and players' records were written, or
+    /// failed to be.
+    WroteFiles(Result<(), WorldLoadingError>),
+}
+
+/// A copy of a region's changed chunks and heightmaps, waiting to be
+/// written.
+#[derive(Debug)]
+struct RegionChanges {
+    region: RegionCoordinate,
+    chunks: Vec<Chunk>,
+    heightmaps: Vec<(ColumnCoordinate, Heightmap)>,
+}
+
+/// Everything read out of a region file.
+#[derive(Debug)]
+struct RegionContents {
+    region: RegionCoordinate,
+    /// The chunks that were asked for one by one, whether they were found or
+    /// not.
+    requested: Vec<ChunkCoordinate>,
+    chunks: Vec<Chunk>,
+    heightmaps: Vec<(ColumnCoordinate, Heightmap)>,
+    quarantined: Vec<WorldLoadingError>,
+}
+
+/// Which chunks to read out of a region file.
+#[derive(Clone, Debug)]
+enum ChunkSelection {
+    /// Every chunk within a bounding box.
+    Within(BoundingBox<ChunkCoordinate>),
+    /// Just these chunks.
+    Only(HashSet<ChunkCoordinate>),
+}
+
+impl ChunkSelection {
+    fn contains_chunk(&self, chunk: ChunkCoordinate) -> bool {
+        match self {
+            Self::Within(bounding_box) => bounding_box.contains(chunk),
+            Self::Only(chunks) => chunks.contains(&chunk),
+        }
+    }
+
+    fn contains_column(&self, column: ColumnCoordinate) -> bool {
+        match self {
+            Self::Within(bounding_box) => {
+                let (smaller, larger) = bounding_box.bounds();
+                (smaller.x..=larger.x).contains(&column.x)
+                    && (smaller.z..=larger.z).contains(&column.z)
+            }
+            Self::Only(chunks) => chunks.iter().any(|chunk| chunk.column() == column),
+        }
+    }
+
+    /// The chunks that were asked for one by one.
+    fn requested(&self) -> Vec<ChunkCoordinate> {
+        match self {
+            Self::Within(_) => Vec::new(),
+            Self::Only(chunks) => chunks.iter().copied().collect(),
+        }
+    }
 }
 
 impl WorldLoader {
-    /// Creates a new `WorldLoader` given a `WorldMetadata`.
-    pub fn new(world_metadata: Arc<WorldMetadata>) -> Result<Self, WorldLoadingError> {
-        Ok(Self {
-            loaded: HashMap::new(),
-            save: WorldSave::new(world_metadata)?,
-        })
+    /// Creates a new `WorldLoader` given a `WorldMetadata` and the storage
+    /// its save lives in.
+    pub fn new(
+        world_metadata: Arc<WorldMetadata>,
+        storage: Storage,
+    ) -> Result<Self, WorldLoadingError> {
+        Ok(Self::empty(WorldSave::new(world_metadata, storage)?))
     }
 
     /// Creates a new `WorldLoader` given a `WorldSave` and `WorldMetadata`.
     pub fn new_with_save(
         save: WorldSave,
-        bounding_box: BoundingBox<GlobalCoordinate>,
+        bounding_box: BoundingBox<ChunkCoordinate>,
     ) -> Result<Self, WorldLoadingError> {
-        let mut loader = Self {
-            save,
-            loaded: HashMap::new(),
-        };
-
+        let mut loader = Self::empty(save);
         loader.load_from_disk(bounding_box)?;
         Ok(loader)
     }
 
+    /// Creates a loader with nothing loaded yet.
+    fn empty(save: WorldSave) -> Self {
+        let (completion_sender, completions) = mpsc::channel();
+
+        Self {
+            save,
+            loaded: HashMap::new(),
+            heightmaps: HashMap::new(),
+            dirty_columns: HashSet::new(),
+            quarantined: Vec::new(),
+            completion_sender,
+            completions: Mutex::new(completions),
+            loading: HashMap::new(),
+            saves_in_flight: HashSet::new(),
+            files_in_flight: 0,
+            requested: HashSet::new(),
+            absent: HashSet::new(),
+            unreadable: HashMap::new(),
+            tickets: Tickets::default(),
+            generating: HashMap::new(),
+        }
+    }
+
     /// Loads the world from disk.
     ///
-    /// You'll need to provide a bounding box of chunks.
+    /// You'll need to provide a bounding box of chunks. Corrupted regions
+    /// and chunks are quarantined instead of stopping the load. Check
+    /// `take_quarantined()` to find out about them. Regions that can't be
+    /// read for any other reason stop the load.
     #[tracing::instrument(skip(self))]
     pub fn load_from_disk(
         &mut self,
-        bounding_box: BoundingBox<GlobalCoordinate>,
+        bounding_box: BoundingBox<ChunkCoordinate>,
     ) -> Result<(), WorldLoadingError> {
         // find all regions that intersect with the bounding box
-        let region_bb = bounding_box / 32;
-        let regions = region_bb.all_coordinates();
+        let regions = bounding_box.regions().all_coordinates();
 
         for region_coordinate in regions {
-            // load the region
-            let region = self.save.load_region(region_coordinate);
+            let Some(file) =
+                open_region_file(&self.save, region_coordinate, &mut self.quarantined)?
+            else {
+                continue;
+            };
 
-            if let Ok(region) = region {
-                // add all chunks in the region to the loaded chunks
-                for (chunk_coordinate, chunk) in region.chunks().clone() {
-                    self.loaded.insert(chunk_coordinate, chunk);
-                }
-            } else {
-                tracing::error!("Failed to load region at {:?}", region_coordinate);
+            let selection = ChunkSelection::Within(bounding_box);
+            let contents = read_region_file(file, &selection, &self.save)?;
+            self.loaded.extend(
+                contents
+                    .chunks
+                    .into_iter()
+                    .map(|chunk| (chunk.coords(), chunk)),
+            );
+            for (column, heightmap) in contents.heightmaps {
+                self.merge_heightmap(column, heightmap);
             }
+            self.quarantined.extend(contents.quarantined);
         }
 
         Ok(())
     }
 
+    /// Starts loading the world from disk in the background.
+    ///
+    /// Region files are read on the `IoTaskPool`, then their chunks are
+    /// decompressed and deserialized on the `AsyncComputeTaskPool`. Call
+    /// `poll()` to pick up the chunks once they're ready.
+    ///
+    /// Chunks that get loaded or created some other way before then are kept
+    /// as they are.
+    #[tracing::instrument(skip(self))]
+    pub fn load_from_disk_async(&mut self, bounding_box: BoundingBox<ChunkCoordinate>) {
+        for region in bounding_box.regions().all_coordinates() {
+            self.load_region_async(region, ChunkSelection::Within(bounding_box));
+        }
+    }
+
+    /// Starts loading some chunks from disk in the background, like
+    /// `load_from_disk_async()`.
+    ///
+    /// Regions are read in the order their chunks first show up, so put the
+    /// chunks you want soonest first. Chunks that are already loaded, on
+    /// their way, or known not to be saved are skipped. So are chunks in
+    /// regions being written right now, or with their column's heightmap
+    /// being written, which can be asked for again once the write is done,
+    /// and chunks in regions that couldn't be read.
+    pub fn load_chunks_async(&mut self, chunks: impl IntoIterator<Item = ChunkCoordinate>) {
+        let mut regions: Vec<(RegionCoordinate, HashSet<ChunkCoordinate>)> = Vec::new();
+
+        for chunk in chunks {
+            let region = chunk.region();
+            let skip = self.loaded.contains_key(&chunk)
+                || self.generating.contains_key(&chunk)
+                || self.requested.contains(&chunk)
+                || self.absent.contains(&chunk)
+                || self.saves_in_flight.contains(&region)
+                || self.saves_in_flight.contains(&chunk.column().region())
+                || self.unreadable.contains_key(&region);
+
+            if skip {
+                continue;
+            }
+
+            match regions.iter_mut().find(|(r, _)| *r == region) {
+                Some((_, wanted)) => {
+                    wanted.insert(chunk);
+                }
+                None => regions.push((region, HashSet::from([chunk]))),
+            }
+            self.requested.insert(chunk);
+        }
+
+        for (region, wanted) in regions {
+            self.load_region_async(region, ChunkSelection::Only(wanted));
+        }
+    }
+
+    /// Whether a chunk asked for by `load_chunks_async()` is still on its
+    /// way.
+    pub fn is_requested(&self, chunk: ChunkCoordinate) -> bool {
+        self.requested.contains(&chunk)
+    }
+
+    /// Whether a chunk was asked for by `load_chunks_async()`, but turned out
+    /// not to be saved.
+    pub fn is_absent(&self, chunk: ChunkCoordinate) -> bool {
+        self.absent.contains(&chunk)
+    }
+
+    /// Forgets which chunks turned out not to be saved, except for the ones
+    /// `keep` holds on to.
+    pub fn retain_absent(&mut self, keep: impl Fn(&ChunkCoordinate) -> bool) {
+        self.absent.retain(keep);
+    }
+
+    /// The regions that couldn't be read in the background, and why.
+    pub fn unreadable_regions(&self) -> &HashMap<RegionCoordinate, WorldLoadingError> {
+        &self.unreadable
+    }
+
+    /// Forgets which regions couldn't be read, so their chunks get asked for
+    /// again.
+    pub fn retry_unreadable(&mut self) {
+        self.unreadable.clear();
+    }
+
+    /// How far along a chunk is in being generated.
+    ///
+    /// Loaded chunks are done, so they're always `Lit`. Chunks that are
+    /// neither loaded nor being generated give back `None`.
+    pub fn generation_status(&self, chunk: ChunkCoordinate) -> Option<GenerationStatus> {
+        if self.loaded.contains_key(&chunk) {
+            return Some(GenerationStatus::Lit);
+        }
+
+        self.generating.get(&chunk).map(|(_, status)| *status)
+    }
+
+    /// Starts keeping track of a chunk that's being generated, now that its
+    /// terrain is done.
+    pub(crate) fn start_generating(&mut self, chunk: Chunk) {
+        self.absent.remove(&chunk.coords());
+        self.generating
+            .insert(chunk.coords(), (chunk, GenerationStatus::Terrain));
+    }
+
+    /// The chunks partway through being generated in a mutable form.
+    pub(crate) fn generating_mut(
+        &mut self,
+    ) -> &mut HashMap<ChunkCoordinate, (Chunk, GenerationStatus)> {
+        &mut self.generating
+    }
+
+    /// Reads the selected chunks of a region on the task pools, sending them
+    /// back through the completion queue.
+    fn load_region_async(&mut self, region: RegionCoordinate, selection: ChunkSelection) {
+        let io = IoTaskPool::get_or_init(TaskPool::new);
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+
+        let save = self.save.clone();
+        let sender = self.completion_sender.clone();
+
+        io.spawn(async move {
+            let mut quarantined = Vec::new();
+            let file = match open_region_file(&save, region, &mut quarantined) {
+                Ok(Some(file)) => file,
+                Ok(None) => {
+                    // the loader may be gone by now, and that's fine
+                    let _ = sender.send(Completion::Loaded(RegionContents {
+                        region,
+                        requested: selection.requested(),
+                        chunks: Vec::new(),
+                        heightmaps: Vec::new(),
+                        quarantined,
+                    }));
+                    return;
+                }
+                Err(error) => {
+                    let _ = sender.send(Completion::Unreadable {
+                        region,
+                        requested: selection.requested(),
+                        error,
+                    });
+                    return;
+                }
+            };
+
+            AsyncComputeTaskPool::get()
+                .spawn(async move {
+                    let requested = selection.requested();
+                    let completion = match read_region_file(file, &selection, &save) {
+                        Ok(contents) => Completion::Loaded(contents),
+                        Err(error) => Completion::Unreadable {
+                            region,
+                            requested,
+                            error,
+                        },
+                    };
+                    let _ = sender.send(completion);
+                })
+                .detach();
+        })
+        .detach();
+
+        *self.loading.entry(region).or_default() += 1;
+    }
+
+    /// Takes the reports of corrupted data quarantined while loading.
+    pub fn take_quarantined(&mut self) -> Vec<WorldLoadingError> {
+        std::mem::take(&mut self.quarantined)
+    }
+
     /// Writes chunks to disk.
+    ///
+    /// Only regions with changes since the last save are written. Any saves
+    /// still running in the background are waited on first.
     #[tracing::instrument(skip(self))]
-    pub fn write_chunks(&self) -> Result<(), WorldLoadingError> {
-        // write all to disk
-        for r in self.regions() {
-            tracing::debug!("Writing region at {:?}", r.coordinates());
-            r.write()?;
+    pub fn write_chunks(&mut self) -> Result<(), WorldLoadingError> {
+        self.finish();
+
+        for region_coordinates in self.dirty_regions() {
+            tracing::debug!("Writing region at {:?}", region_coordinates);
+
+            let mut file = self.save.writable_region_file(region_coordinates)?;
+
+            // only rewrite the chunks and heightmaps that changed
+            let mut written = Vec::new();
+            for chunk in self.loaded.values() {
+                if chunk.region() == region_coordinates && chunk.is_dirty(JournalKind::Save) {
+                    file.write_chunk(chunk)?;
+                    written.push(chunk.coords());
+                }
+            }
+
+            for column in self.dirty_columns.iter() {
+                if column.region() == region_coordinates {
+                    file.write_heightmap(*column, &self.heightmaps[column])?;
+                }
+            }
+
+            // nothing's stored until the commit, so chunks stay dirty until then
+            file.commit()?;
+            for coords in written {
+                if let Some(chunk) = self.loaded.get_mut(&coords) {
+                    chunk.mark_clean(JournalKind::Save);
+                }
+            }
         }
 
+        self.dirty_columns.clear();
         Ok(())
     }
 
+    /// Starts writing chunks to disk in the background, along with the rest
+    /// of the save's files.
+    ///
+    /// The chunks and heightmaps that changed since the last save are copied
+    /// right away, so the world can keep changing while they're written on
+    /// the `IoTaskPool`, followed by `files`. If a region fails to be
+    /// written, its chunks are marked as changed again and go out with the
+    /// next save. The other files are written whole every time anyway.
+    #[tracing::instrument(skip_all)]
+    pub fn write_async(&mut self, files: SaveFiles) {
+        let mut skipped = Vec::new();
+        let mut changes = Vec::new();
+
+        for region in self.dirty_regions() {
+            // region files are rewritten whole, so two writes at once would
+            // lose one of them. this region can wait for the next save
+            if self.saves_in_flight.contains(&region) {
+                skipped.push(region);
+                continue;
+            }
+
+            changes.push(self.copy_region_changes(region));
+        }
+
+        self.dirty_columns
+            .retain(|column| skipped.contains(&column.region()));
+        self.spawn_save(changes, Some(files));
+    }
+
+    /// Saves chunks in the background, then unloads them.
+    ///
+    /// Chunks held by a ticket stay loaded. So do chunks in regions that are
+    /// still being loaded or written, since their file could change
+    /// underneath that work. Try them again later! Gives back the chunks that
+    /// were unloaded.
+    #[tracing::instrument(skip_all)]
+    pub fn unload_chunks(
+        &mut self,
+        chunks: impl IntoIterator<Item = ChunkCoordinate>,
+    ) -> Vec<ChunkCoordinate> {
+        let unloading = chunks
+            .into_iter()
+            .filter(|chunk| {
+                if !self.loaded.contains_key(chunk) {
+                    return false;
+                }
+
+                if self.tickets.is_held(*chunk) {
+                    let held = self
+                        .tickets
+                        .holding(*chunk)
+                        .map(|(id, at, ticket)| format!("{ticket} {id} at {at}"))
+                        .collect::<Vec<_>>();
+                    tracing::debug!("Keeping {chunk} loaded for: {}", held.join(", "));
+                    return false;
+                }
+
+                // its column's heightmap might go too, which is kept in the
+                // region at y = 0
+                !self.is_region_busy(chunk.region())
+                    && !self.is_region_busy(chunk.column().region())
+            })
+            .collect::<Vec<_>>();
+
+        let regions = unloading
+            .iter()
+            .flat_map(|chunk| [chunk.region(), chunk.column().region()])
+            .collect::<HashSet<_>>();
+        for region in self.dirty_regions().intersection(&regions) {
+            self.save_region_async(*region);
+        }
+
+        for chunk in unloading.iter() {
+            self.loaded.remove(chunk);
+        }
+
+        // heightmaps go once nothing in their column is loaded
+        let columns = self
+            .loaded
+            .keys()
+            .map(ChunkCoordinate::column)
+            .collect::<HashSet<_>>();
+        for column in unloading.iter().map(ChunkCoordinate::column) {
+            if !columns.contains(&column) {
+                self.heightmaps.remove(&column);
+                self.dirty_columns.remove(&column);
+            }
+        }
+
+        unloading
+    }
+
+    /// Puts a ticket on the `at` chunk, keeping the chunks it holds loaded
+    /// until it's removed or runs out. Gives back the ticket's id.
+    pub fn add_ticket(&mut self, at: ChunkCoordinate, ticket: Ticket) -> TicketId {
+        let id = self.tickets.add(at, ticket);
+        tracing::debug!("Added {ticket} {id} at {at}");
+        id
+    }
+
+    /// Takes a ticket away. Its chunks can be unloaded once nothing else
+    /// holds them.
+    pub fn remove_ticket(&mut self, id: TicketId) -> Option<(ChunkCoordinate, Ticket)> {
+        let removed = self.tickets.remove(id);
+        if let Some((at, ticket)) = removed {
+            tracing::debug!("Removed {ticket} {id} at {at}");
+        }
+
+        removed
+    }
+
+    /// Counts every ticket down by one game tick, taking away the ones that
+    /// run out.
+    pub fn tick_tickets(&mut self) {
+        for (id, at, ticket) in self.tickets.tick() {
+            tracing::debug!("The {} ticket {id} at {at} ran out", ticket.kind);
+        }
+    }
+
+    /// The tickets keeping chunks loaded.
+    pub fn tickets(&self) -> &Tickets {
+        &self.tickets
+    }
+
+    /// Whether a region is being loaded or written in the background.
+    fn is_region_busy(&self, region: RegionCoordinate) -> bool {
+        self.loading.contains_key(&region) || self.saves_in_flight.contains(&region)
+    }
+
+    /// Copies a region's changed chunks and heightmaps, then writes them on
+    /// the `IoTaskPool`.
+    fn save_region_async(&mut self, region: RegionCoordinate) {
+        let changes = self.copy_region_changes(region);
+        self.spawn_save(vec![changes], None);
+    }
+
+    /// Copies a region's changed chunks and heightmaps, marking the region
+    /// as being written.
+    fn copy_region_changes(&mut self, region: RegionCoordinate) -> RegionChanges {
+        let heightmaps = self
+            .dirty_columns
+            .iter()
+            .filter(|column| column.region() == region)
+            .map(|column| (*column, self.heightmaps[column].clone()))
+            .collect::<Vec<_>>();
+
+        let chunks = self
+            .loaded
+            .values_mut()
+            .filter(|chunk| chunk.region() == region && chunk.is_dirty(JournalKind::Save))
+            .map(|chunk| {
+                let snapshot = chunk.clone();
+                chunk.mark_clean(JournalKind::Save);
+                snapshot
+            })
+            .collect::<Vec<_>>();
+
+        self.saves_in_flight.insert(region);
+        RegionChanges {
+            region,
+            chunks,
+            heightmaps,
+        }
+    }
+
+    /// Writes regions' changes, then the save's other files if there are
+    /// any, in one task on the `IoTaskPool`.
+    fn spawn_save(&mut self, changes: Vec<RegionChanges>, files: Option<SaveFiles>) {
+        if files.is_some() {
+            self.files_in_flight += 1;
+        }
+
+        let save = self.save.clone();
+        let sender = self.completion_sender.clone();
+
+        IoTaskPool::get_or_init(TaskPool::new)
+            .spawn(async move {
+                // the loader may be gone by now, and that's fine
+                for RegionChanges {
+                    region,
+                    chunks,
+                    heightmaps,
+                } in changes
+                {
+                    let result = write_region_file(&save, region, &chunks, &heightmaps);
+                    let _ = sender.send(Completion::Saved {
+                        region,
+                        chunks,
+                        heightmaps,
+                        result,
+                    });
+                }
+
+                if let Some(files) = files {
+                    let result = files.write(save.storage().as_ref());
+                    let _ = sender.send(Completion::WroteFiles(result));
+                }
+            })
+            .detach();
+    }
+
+    /// Picks up any background work that finished since the last poll.
+    ///
+    /// Gives back the coordinates of the chunks that were loaded.
+    pub fn poll(&mut self) -> Vec<ChunkCoordinate> {
+        let finished = self
+            .completions
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_iter()
+            .collect::<Vec<_>>();
+
+        finished
+            .into_iter()
+            .flat_map(|completion| self.complete(completion))
+            .collect()
+    }
+
+    /// Blocks until all background work is done, then picks it up.
+    ///
+    /// Gives back the coordinates of the chunks that were loaded.
+    pub fn finish(&mut self) -> Vec<ChunkCoordinate> {
+        let mut arrived = Vec::new();
+
+        while self.is_busy() {
+            let completion = self
+                .completions
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv()
+                .expect("the loader holds a sender, so the queue stays open");
+
+            arrived.extend(self.complete(completion));
+        }
+
+        arrived
+    }
+
+    /// Whether anything is still being loaded or saved in the background.
+    pub fn is_busy(&self) -> bool {
+        !self.loading.is_empty() || !self.saves_in_flight.is_empty() || self.files_in_flight > 0
+    }
+
+    /// Takes in a piece of finished background work, giving back the
+    /// coordinates of any chunks it loaded.
+    fn complete(&mut self, completion: Completion) -> Vec<ChunkCoordinate> {
+        match completion {
+            Completion::Loaded(contents) => {
+                self.finish_loading(contents.region);
+                self.quarantined.extend(contents.quarantined);
+
+                for (column, heightmap) in contents.heightmaps {
+                    self.merge_heightmap(column, heightmap);
+                }
+
+                // don't clobber chunks that showed up while we were loading
+                let mut arrived = Vec::new();
+                for chunk in contents.chunks {
+                    if let Entry::Vacant(entry) = self.loaded.entry(chunk.coords()) {
+                        arrived.push(chunk.coords());
+                        entry.insert(chunk);
+                    }
+                }
+
+                // remember what isn't saved, so it isn't asked for again
+                for chunk in contents.requested {
+                    self.requested.remove(&chunk);
+                    if !self.loaded.contains_key(&chunk) {
+                        self.absent.insert(chunk);
+                    }
+                }
+
+                arrived
+            }
+
+            Completion::Unreadable {
+                region,
+                requested,
+                error,
+            } => {
+                self.finish_loading(region);
+                tracing::error!("Failed to read region at {region}: {error}");
+
+                // these chunks aren't missing, so they can't be marked absent
+                for chunk in requested {
+                    self.requested.remove(&chunk);
+                }
+                self.unreadable.insert(region, error);
+
+                Vec::new()
+            }
+
+            Completion::Saved {
+                region,
+                chunks,
+                heightmaps,
+                result,
+            } => {
+                self.saves_in_flight.remove(&region);
+
+                if let Err(e) = result {
+                    tracing::error!("Failed to write region at {region} in the background: {e}");
+
+                    // bring back anything unloaded in the meantime, so it's
+                    // written with the next save
+                    for snapshot in chunks {
+                        match self.loaded.entry(snapshot.coords()) {
+                            Entry::Occupied(chunk) => {
+                                chunk.into_mut().mark_dirty(JournalKind::Save)
+                            }
+                            Entry::Vacant(entry) => {
+                                entry.insert(snapshot);
+                            }
+                        }
+                    }
+
+                    for (column, heightmap) in heightmaps {
+                        self.merge_heightmap(column, heightmap);
+                        self.dirty_columns.insert(column);
+                    }
+                }
+
+                Vec::new()
+            }
+            Completion::WroteFiles(result) => {
+                self.files_in_flight -= 1;
+
+                // these are written whole with every save, so the next one
+                // tries again
+                if let Err(e) = result {
+                    tracing::error!("Failed to write the world's files in the background: {e}");
+                }
+
+                Vec::new()
+            }
+        }
+    }
+
+    /// Counts down the loads running for a region.
+    fn finish_loading(&mut self, region: RegionCoordinate) {
+        if let Entry::Occupied(mut loads) = self.loading.entry(region) {
+            *loads.get_mut() -= 1;
+            if *loads.get() == 0 {
+                loads.remove();
+            }
+        }
+    }
+
+    /// Finds the regions that have changed since the last save.
+    ///
+    /// A region changes when any of its chunks do, or when a heightmap
+    /// stored in it does.
+    pub fn dirty_regions(&self) -> HashSet<RegionCoordinate> {
+        self.loaded
+            .values()
+            .filter(|chunk| chunk.is_dirty(JournalKind::Save))
+            .map(|chunk| chunk.region())
+            .chain(self.dirty_columns.iter().map(ColumnCoordinate::region))
+            .collect()
+    }
+
     /// Gets all regions for these loaded chunks.
     pub fn regions(&self) -> Vec<Region> {
-        let mut v = HashMap::new();
+        self.loaded
+            .values()
+            .map(|chunk| chunk.region())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|region_coordinates| self.region(region_coordinates))
+            .collect()
+    }
+
+    /// Builds a region from the loaded chunks and heightmaps within it.
+    pub fn region(&self, region_coordinates: RegionCoordinate) -> Region {
+        let mut region = Region::new(region_coordinates, self.save.clone());
 
         for (chunk_coordinates, chunk) in self.loaded.iter() {
-            // add region to hashmap with new chunk if it's not there.
-            // otherwise, just add the chunk to the existing region.
-            let region_coordinates = chunk.region(chunk.coords());
-
-            v.entry(region_coordinates)
-                .or_insert_with(|| {
-                    let mut region = Region::new(region_coordinates, self.save.metadata());
-                    region
-                        .add_chunk(*chunk_coordinates, chunk.clone())
-                        .expect("region contains chunk");
-                    region
-                })
-                .add_chunk(*chunk_coordinates, chunk.clone())
-                .expect("region contains chunk");
+            if region.can_contain_chunk(*chunk_coordinates) {
+                region
+                    .add_chunk(*chunk_coordinates, chunk.clone())
+                    .expect("region contains chunk");
+            }
         }
 
-        v.into_values().collect()
+        // give the region the heightmaps of the columns stored in it
+        for (column, heightmap) in self.heightmaps.iter() {
+            if column.region() == region_coordinates {
+                region
+                    .add_heightmap(*column, heightmap.clone())
+                    .expect("region contains column");
+            }
+        }
+
+        region
     }
 
     pub fn save(&self) -> Result<WorldSave, WorldLoadingError> {
         Ok(self.save.clone())
     }
 
+    /// The storage the world is being saved into.
+    pub fn storage(&self) -> Storage {
+        self.save.storage()
+    }
+
     /// The currently-loaded chunks in a mutable form.
-    pub(crate) fn chunks_mut(&mut self) -> &mut HashMap<GlobalCoordinate, Chunk> {
+    pub(crate) fn chunks_mut(&mut self) -> &mut HashMap<ChunkCoordinate, Chunk> {
         &mut self.loaded
     }
 
     /// The currently-loaded chunks in a referenced form.
-    pub(crate) fn chunks_ref(&self) -> &HashMap<GlobalCoordinate, Chunk> {
+    pub(crate) fn chunks_ref(&self) -> &HashMap<ChunkCoordinate, Chunk> {
         &self.loaded
     }
+
+    /// The heightmap of a chunk column in a mutable form. The column is
+    /// marked as changed, so its regions get saved.
+    pub(crate) fn heightmap_mut(&mut self, column: ColumnCoordinate) -> &mut Heightmap {
+        self.dirty_columns.insert(column);
+        self.heightmaps.entry(column).or_default()
+    }
+
+    /// Brings a heightmap from outside the loader, like one read from disk,
+    /// into the column's current one.
+    ///
+    /// Heights in loaded chunks are left alone, since those chunks know
+    /// better, and are worked out again as new chunks arrive. Other heights
+    /// are taken when they're above what the loader already knows of.
+    fn merge_heightmap(&mut self, column: ColumnCoordinate, other: Heightmap) {
+        let current = self.heightmaps.entry(column).or_default();
+
+        for kind in HeightmapKind::ALL {
+            for x in 0..CHUNK_LENGTH {
+                for z in 0..CHUNK_LENGTH {
+                    let Some(height) = other.get(kind, x, z) else {
+                        continue;
+                    };
+
+                    let y = height.div_euclid(CHUNK_LENGTH as i64);
+                    if self
+                        .loaded
+                        .contains_key(&ChunkCoordinate::new(column.x, y, column.z))
+                        || current.get(kind, x, z).is_some_and(|h| h >= height)
+                    {
+                        continue;
+                    }
+
+                    current.set(kind, x, z, Some(height));
+                }
+            }
+        }
+    }
+
+    /// The heightmaps of loaded chunk columns in a referenced form.
+    pub(crate) fn heightmaps_ref(&self) -> &HashMap<ColumnCoordinate, Heightmap> {
+        &self.heightmaps
+    }
+}
+
+/// Opens a region's file for reading, if it's saved.
+///
+/// Corrupted regions are quarantined, with a report pushed onto
+/// `quarantined`. Anything else that goes wrong, like a region from a newer
+/// version of the game or storage failing, is given back as an error, so
+/// the region stays right where it is.
+fn open_region_file(
+    save: &WorldSave,
+    region_coordinate: RegionCoordinate,
+    quarantined: &mut Vec<WorldLoadingError>,
+) -> Result<Option<RegionFile>, WorldLoadingError> {
+    match save.region_file(region_coordinate) {
+        Ok(file) => Ok(file),
+        Err(WorldLoadingError::RegionWriteFailed(e)) if e.is_corruption() => {
+            let report = Region::quarantine(region_coordinate, save, e.to_string());
+            tracing::error!("{report}");
+            quarantined.push(report);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the selected chunks and their columns' heightmaps from a region file.
+/// Nothing else in the file is read.
+///
+/// Corrupted chunks are quarantined. Chunks that can't be read for any other
+/// reason stop the read, leaving the whole region where it is.
+fn read_region_file(
+    mut file: RegionFile,
+    selection: &ChunkSelection,
+    save: &WorldSave,
+) -> Result<RegionContents, WorldLoadingError> {
+    let mut contents = RegionContents {
+        region: file.coordinates(),
+        requested: selection.requested(),
+        chunks: Vec::new(),
+        heightmaps: Vec::new(),
+        quarantined: Vec::new(),
+    };
+
+    let mut corrupted = Vec::new();
+    for chunk_coordinates in file.chunks() {
+        if !selection.contains_chunk(chunk_coordinates) {
+            continue;
+        }
+
+        match file.read_chunk(chunk_coordinates) {
+            Ok(Some(chunk)) => contents.chunks.push(chunk),
+            Ok(None) => (),
+            Err(e) if e.is_corruption() => corrupted.push((chunk_coordinates, e)),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // regions stacked in a column share one heightmap, kept in the region
+    // at y = 0. it might be this one, or it might need opening
+    let mut columns: BTreeMap<RegionCoordinate, BTreeSet<ColumnCoordinate>> = BTreeMap::new();
+    let wanted = file
+        .columns()
+        .into_iter()
+        .filter(|column| selection.contains_column(*column))
+        .chain(contents.chunks.iter().map(|chunk| chunk.coords().column()));
+    for column in wanted {
+        columns.entry(column.region()).or_default().insert(column);
+    }
+
+    for (region, columns) in columns {
+        if region == contents.region {
+            read_heightmaps(&mut file, &columns, &mut contents.heightmaps);
+            continue;
+        }
+
+        match save.region_file(region) {
+            Ok(Some(mut other)) => read_heightmaps(&mut other, &columns, &mut contents.heightmaps),
+            Ok(None) => (),
+            // that region gets quarantined or left alone once it's loaded
+            Err(e) => tracing::warn!("Skipping heightmaps in unreadable region at {region}: {e}"),
+        }
+    }
+
+    for report in Region::quarantine_chunks(file, save, corrupted) {
+        tracing::error!("{report}");
+        contents.quarantined.push(report);
+    }
+
+    Ok(contents)
+}
+
+/// Reads the heightmaps of some columns stored in a region file.
+fn read_heightmaps(
+    file: &mut RegionFile,
+    columns: &BTreeSet<ColumnCoordinate>,
+    heightmaps: &mut Vec<(ColumnCoordinate, Heightmap)>,
+) {
+    for column in columns {
+        match file.read_heightmap(*column) {
+            Ok(Some(heightmap)) => heightmaps.push((*column, heightmap)),
+            Ok(None) => (),
+            Err(e) => tracing::warn!("Skipping unreadable heightmap at {column}: {e}"),
+        }
+    }
+}
+
+/// Writes copies of changed chunks and heightmaps into a region's file.
+fn write_region_file(
+    save: &WorldSave,
+    region: RegionCoordinate,
+    chunks: &[Chunk],
+    heightmaps: &[(ColumnCoordinate, Heightmap)],
+) -> Result<(), WorldLoadingError> {
+    tracing::debug!("Writing region at {:?}", region);
+    let mut file = save.writable_region_file(region)?;
+
+    for chunk in chunks {
+        file.write_chunk(chunk)?;
+    }
+
+    for (column, heightmap) in heightmaps {
+        file.write_heightmap(*column, heightmap)?;
+    }
+
+    Ok(file.commit()?)
 }
 
 /// A world-loading error.
@@ -136,6 +1044,8 @@ pub enum WorldLoadingError {
     WorldDoesntExist,
     #[error("Error while writing metadata: `{0}`.")]
     MetadataWriteFailed(String),
+    #[error("Error while reading metadata: `{0}`.")]
+    MetadataReadFailed(String),
     #[error("Couldn't write chunks to `bincode`: `{0}`")]
     ChunkSerializationFailed(String),
     #[error("Failed to write chunks to region: `{0}`.")]
@@ -144,4 +1054,215 @@ pub enum WorldLoadingError {
     RegionReadFailed(String),
     #[error("World write failed: `{0}`.")]
     WorldWriteFailed(String),
+    #[error("World was saved with format version `{found}`, but this game only supports up to `{supported}`.")]
+    UnsupportedSaveVersion { found: u32, supported: u32 },
+    #[error("Failed to back up world before migrating it: `{0}`.")]
+    BackupFailed(String),
+    #[error("Failed to migrate world to the current save format: `{0}`.")]
+    MigrationFailed(String),
+    #[error("Error while writing world state: `{0}`.")]
+    StateWriteFailed(String),
+    #[error("Error while reading world state: `{0}`.")]
+    StateReadFailed(String),
+    #[error("Error while writing player data: `{0}`.")]
+    PlayerDataWriteFailed(String),
+    #[error("Error while reading player data: `{0}`.")]
+    PlayerDataReadFailed(String),
+    #[error("Corrupted {what} was quarantined to {moved_to}: `{reason}`.")]
+    Quarantined {
+        what: String,
+        moved_to: String,
+        reason: String,
+    },
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::{journal::JournalKind, Chunk},
+            coordinates::ChunkCoordinate,
+            heightmap::HeightmapKind,
+            region::{
+                file::{ENTRY_MAGIC, FORMAT_VERSION},
+                Region,
+            },
+            state::WorldState,
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn chunks_load_and_save_in_the_background() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let coords = ChunkCoordinate::new(3, 0, -2);
+        world.set_chunk(
+            Chunk::new_filled(Block::new(BlockType::STONE, 0), coords),
+            coords,
+        );
+
+        // the chunk is copied right away, so it's clean before it's written
+        world.save_async();
+        assert!(!world.chunks()[&coords].is_dirty(JournalKind::Save));
+        let state_path = world.metadata().save_path();
+        world.loader.finish();
+        assert!(!world.loader.is_busy());
+
+        // the world's state goes out in the background too
+        let state = WorldState::read(world.storage().as_ref(), &state_path)?;
+        assert_eq!(state.as_ref(), Some(world.state()));
+
+        // chunks show up once the loads are picked up
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([coords]);
+        assert!(loaded.chunks().is_empty());
+        assert_eq!(loaded.loader.finish(), vec![coords]);
+        assert_eq!(
+            loaded.chunks()[&coords].blocks(),
+            world.chunks()[&coords].blocks()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_regions_are_left_alone() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let coords = ChunkCoordinate::ORIGIN;
+        world.set_chunk(
+            Chunk::new_filled(Block::new(BlockType::STONE, 0), coords),
+            coords,
+        );
+        world.save()?;
+
+        // pretend a newer version of the game wrote the region
+        let path = Region::path_from_coordinates(&coords.region(), world.metadata());
+        let mut raw = world.storage().read(&path)?.unwrap();
+        raw[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        world.storage().write(&path, &raw)?;
+
+        assert!(MacawWorld::load(world.metadata(), world.storage()).is_err());
+
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([coords]);
+        loaded.loader.finish();
+
+        // it isn't missing, so nothing is made in its place, and it isn't
+        // corrupted, so it stays right where it is
+        assert!(!loaded.loader.is_absent(coords));
+        assert!(loaded
+            .loader
+            .unreadable_regions()
+            .contains_key(&coords.region()));
+        assert!(loaded.loader.take_quarantined().is_empty());
+        assert_eq!(loaded.storage().read(&path)?, Some(raw.clone()));
+
+        // asking for the region doesn't replace it with an empty one either
+        assert!(Region::get(coords.region(), loaded.loader.save.clone()).is_err());
+        assert_eq!(loaded.storage().read(&path)?, Some(raw.clone()));
+
+        // the same goes for chunks from a newer version in an older region
+        raw[4..6].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+        let entries = raw
+            .windows(4)
+            .enumerate()
+            .filter(|(_, bytes)| *bytes == ENTRY_MAGIC)
+            .map(|(start, _)| start + 4)
+            .collect::<Vec<_>>();
+        for start in entries {
+            raw[start..start + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        }
+        world.storage().write(&path, &raw)?;
+
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([coords]);
+        loaded.loader.finish();
+
+        assert!(loaded.chunks().is_empty());
+        assert!(loaded
+            .loader
+            .unreadable_regions()
+            .contains_key(&coords.region()));
+        assert!(loaded.loader.take_quarantined().is_empty());
+        assert_eq!(loaded.storage().read(&path)?, Some(raw));
+
+        Ok(())
+    }
+
+    #[test]
+    fn heightmaps_are_kept_in_one_region() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let (low, high) = (ChunkCoordinate::ORIGIN, ChunkCoordinate::new(0, 40, 0));
+        for coords in [low, high] {
+            world.set_chunk(
+                Chunk::new_filled(Block::new(BlockType::STONE, 0), coords),
+                coords,
+            );
+        }
+        world.save()?;
+
+        let column = high.column();
+        let heightmap = world.loader.heightmaps_ref().get(&column).cloned();
+        assert!(heightmap.is_some());
+
+        // loading just the high chunk still brings in its column's heightmap
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([high]);
+        loaded.loader.finish();
+        assert_eq!(
+            loaded.loader.heightmaps_ref().get(&column),
+            heightmap.as_ref()
+        );
+
+        // since it's only kept in the region at y = 0
+        let path = Region::path_from_coordinates(&low.region(), world.metadata());
+        world.storage().remove(&path)?;
+
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.loader.load_chunks_async([high]);
+        loaded.loader.finish();
+        assert!(loaded.chunks().contains_key(&high));
+        assert!(loaded.loader.heightmaps_ref().get(&column).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn stored_heightmaps_merge_with_loaded_ones() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let stone = Block::new(BlockType::STONE, 0);
+        let (low, high) = (ChunkCoordinate::ORIGIN, ChunkCoordinate::new(0, 2, 0));
+        for coords in [low, high] {
+            world.set_chunk(Chunk::new_filled(stone.clone(), coords), coords);
+        }
+        world.save()?;
+
+        // a chunk below is already here, so the column has heights of its own
+        let below = ChunkCoordinate::new(0, -1, 0);
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.set_chunk(Chunk::new_filled(stone.clone(), below), below);
+        assert_eq!(loaded.height(HeightmapKind::LightBlocking, 0, 0), Some(-1));
+
+        // the stored heights come from a chunk that isn't loaded, so they win
+        loaded.loader.load_chunks_async([low]);
+        loaded.loader.finish();
+        assert_eq!(loaded.height(HeightmapKind::LightBlocking, 0, 0), Some(47));
+
+        // but a loaded chunk knows better than what was stored about it
+        let mut loaded = MacawWorld::load_async(world.metadata(), world.storage())?;
+        loaded.set_chunk(Chunk::new(high), high);
+        loaded.loader.load_chunks_async([low]);
+        let mut arrived = Vec::new();
+        while loaded.loader.is_busy() {
+            arrived.extend(loaded.poll());
+            std::thread::yield_now();
+        }
+        assert_eq!(arrived, vec![low]);
+        assert_eq!(loaded.height(HeightmapKind::LightBlocking, 0, 0), Some(15));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,335 @@
+//! # Ticket
+//!
+//! Reasons for chunks to stay loaded, beyond players standing near them.
+//!
+//! A ticket sits on a chunk and holds it in memory, along with every chunk
+//! within its level. Chunks are only unloaded once no ticket holds them.
+//! Tickets last until they're removed, or run out after some game ticks.
+//!
+//! Like in Beta, the spawn area is held as whole columns, so the ground
+//! under the spawn location stays loaded however high up it is.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    ops::RangeInclusive,
+};
+
+use crate::world::{chunk::CHUNK_LENGTH, coordinates::ChunkCoordinate, generation::MAX_GEN_HEIGHT};
+
+/// How many columns out from the spawn location stay loaded.
+pub const SPAWN_TICKET_LEVEL: u8 = 2;
+
+/// The highest level a ticket can have. Anything higher holds the same
+/// chunks as this, so one ticket can't keep millions of chunks around.
+pub const MAX_TICKET_LEVEL: u8 = 16;
+
+/// Why a ticket is keeping chunks loaded.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub enum TicketKind {
+    /// The area around the world's spawn location.
+    Spawn,
+    /// Chunks with redstone or block updates still going.
+    BlockUpdates,
+    /// Chunks forced to stay loaded, like by a server command.
+    Forced,
+    /// Chunks a generator is still decorating.
+    Generation,
+}
+
+impl Display for TicketKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TicketKind::Spawn => "spawn",
+            TicketKind::BlockUpdates => "block updates",
+            TicketKind::Forced => "forced",
+            TicketKind::Generation => "generation",
+        })
+    }
+}
+
+/// How long a ticket lasts.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub enum TicketLifetime {
+    /// The ticket stays until it's removed.
+    UntilRemoved,
+    /// The ticket runs out after this many more game ticks.
+    Ticks(u32),
+}
+
+impl Display for TicketLifetime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TicketLifetime::UntilRemoved => f.write_str("until removed"),
+            TicketLifetime::Ticks(ticks) => write!(f, "for {ticks} more ticks"),
+        }
+    }
+}
+
+/// A reason to keep some chunks loaded.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub struct Ticket {
+    pub kind: TicketKind,
+    /// How many chunks out from the ticket's chunk are held too, in every
+    /// direction. Level 0 holds just the one chunk. This goes up to
+    /// `MAX_TICKET_LEVEL`.
+    pub level: u8,
+    pub lifetime: TicketLifetime,
+}
+
+impl Ticket {
+    /// Creates a `Ticket`. Its level is clamped to `MAX_TICKET_LEVEL`.
+    pub fn new(kind: TicketKind, level: u8, lifetime: TicketLifetime) -> Self {
+        Self {
+            kind,
+            level: level.min(MAX_TICKET_LEVEL),
+            lifetime,
+        }
+    }
+
+    /// The chunks this ticket holds on each axis, sitting on the `at` chunk.
+    ///
+    /// Spawn tickets hold whole columns, from the bottom of the world up to
+    /// the generation height.
+    pub fn area(&self, at: ChunkCoordinate) -> [RangeInclusive<i64>; 3] {
+        let level = self.level.min(MAX_TICKET_LEVEL) as i64;
+        let around = |centre: i64| centre.saturating_sub(level)..=centre.saturating_add(level);
+
+        let y = match self.kind {
+            TicketKind::Spawn => {
+                let top = (MAX_GEN_HEIGHT / CHUNK_LENGTH as u32) as i64 - 1;
+                at.y.min(0)..=at.y.max(top)
+            }
+            _ => around(at.y),
+        };
+
+        [around(at.x), y, around(at.z)]
+    }
+
+    /// Whether this ticket, sitting on the `at` chunk, holds `chunk`.
+    pub fn holds(&self, at: ChunkCoordinate, chunk: ChunkCoordinate) -> bool {
+        let [x, y, z] = self.area(at);
+        x.contains(&chunk.x) && y.contains(&chunk.y) && z.contains(&chunk.z)
+    }
+
+    /// Every chunk this ticket holds, sitting on the `at` chunk.
+    pub fn chunks(&self, at: ChunkCoordinate) -> Vec<ChunkCoordinate> {
+        let [xs, ys, zs] = self.area(at);
+
+        let mut chunks = Vec::new();
+        for x in xs {
+            for y in ys.clone() {
+                for z in zs.clone() {
+                    chunks.push(ChunkCoordinate::new(x, y, z));
+                }
+            }
+        }
+
+        chunks
+    }
+}
+
+impl Display for Ticket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ticket (level {}, {})",
+            self.kind, self.level, self.lifetime
+        )
+    }
+}
+
+/// Identifies a ticket, so it can be removed later.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub struct TicketId(u64);
+
+impl Display for TicketId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Every ticket in a world, with the chunks they sit on.
+#[derive(Clone, Debug, Default)]
+pub struct Tickets {
+    /// The id the next ticket gets.
+    next_id: u64,
+    tickets: BTreeMap<TicketId, (ChunkCoordinate, Ticket)>,
+    /// Every chunk held by a ticket. This only changes when tickets come
+    /// and go, so it's worked out then instead of every frame.
+    held: HashSet<ChunkCoordinate>,
+}
+
+impl Tickets {
+    /// Puts a ticket on the `at` chunk, giving back its id.
+    pub fn add(&mut self, at: ChunkCoordinate, ticket: Ticket) -> TicketId {
+        let id = TicketId(self.next_id);
+        self.next_id += 1;
+
+        self.held.extend(ticket.chunks(at));
+        self.tickets.insert(id, (at, ticket));
+        id
+    }
+
+    /// Takes a ticket away, giving back where it sat and what it was.
+    pub fn remove(&mut self, id: TicketId) -> Option<(ChunkCoordinate, Ticket)> {
+        let removed = self.tickets.remove(&id);
+        if removed.is_some() {
+            self.update_held();
+        }
+
+        removed
+    }
+
+    /// Gets a ticket, along with the chunk it sits on.
+    pub fn get(&self, id: TicketId) -> Option<&(ChunkCoordinate, Ticket)> {
+        self.tickets.get(&id)
+    }
+
+    /// Counts down every ticket with a limited lifetime by one game tick,
+    /// taking away and giving back the ones that ran out.
+    pub fn tick(&mut self) -> Vec<(TicketId, ChunkCoordinate, Ticket)> {
+        let mut expired = Vec::new();
+
+        for (id, (at, ticket)) in self.tickets.iter_mut() {
+            if let TicketLifetime::Ticks(ticks) = &mut ticket.lifetime {
+                *ticks = ticks.saturating_sub(1);
+                if *ticks == 0 {
+                    expired.push((*id, *at, *ticket));
+                }
+            }
+        }
+
+        for (id, _, _) in expired.iter() {
+            self.tickets.remove(id);
+        }
+        if !expired.is_empty() {
+            self.update_held();
+        }
+
+        expired
+    }
+
+    /// The tickets holding `chunk` in memory. If there are any, this is why
+    /// it's loaded!
+    pub fn holding(
+        &self,
+        chunk: ChunkCoordinate,
+    ) -> impl Iterator<Item = (TicketId, ChunkCoordinate, &Ticket)> {
+        self.tickets
+            .iter()
+            .filter(move |(_, (at, ticket))| ticket.holds(*at, chunk))
+            .map(|(id, (at, ticket))| (*id, *at, ticket))
+    }
+
+    /// Whether any ticket holds `chunk` in memory.
+    pub fn is_held(&self, chunk: ChunkCoordinate) -> bool {
+        self.held.contains(&chunk)
+    }
+
+    /// Every chunk held by a ticket.
+    pub fn held_chunks(&self) -> &HashSet<ChunkCoordinate> {
+        &self.held
+    }
+
+    /// Every ticket, with its id and the chunk it sits on.
+    pub fn iter(&self) -> impl Iterator<Item = (TicketId, ChunkCoordinate, &Ticket)> {
+        self.tickets
+            .iter()
+            .map(|(id, (at, ticket))| (*id, *at, ticket))
+    }
+
+    /// How many tickets there are.
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
+
+    /// Whether there are no tickets at all.
+    pub fn is_empty(&self) -> bool {
+        self.tickets.is_empty()
+    }
+
+    /// Works out which chunks are held again, after tickets were taken away.
+    fn update_held(&mut self) {
+        self.held = self
+            .tickets
+            .values()
+            .flat_map(|(at, ticket)| ticket.chunks(*at))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ticket, TicketKind, TicketLifetime, MAX_TICKET_LEVEL};
+    use crate::world::{chunk::Chunk, coordinates::ChunkCoordinate, MacawWorld};
+
+    #[test]
+    fn tickets_keep_chunks_loaded() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let (held, spare) = (ChunkCoordinate::new(0, 0, 1), ChunkCoordinate::new(5, 0, 0));
+        for coords in [held, spare] {
+            world.set_chunk(Chunk::new(coords), coords);
+        }
+
+        let forced = world.loader.add_ticket(
+            ChunkCoordinate::ORIGIN,
+            Ticket::new(TicketKind::Forced, 1, TicketLifetime::UntilRemoved),
+        );
+        world.loader.add_ticket(
+            held,
+            Ticket::new(TicketKind::BlockUpdates, 0, TicketLifetime::Ticks(2)),
+        );
+
+        // only the chunk without a ticket unloads
+        assert_eq!(world.loader.unload_chunks([held, spare]), vec![spare]);
+
+        // and the tickets say why the other one is still here
+        let reasons = world
+            .loader
+            .tickets()
+            .holding(held)
+            .map(|(_, _, ticket)| ticket.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                "forced ticket (level 1, until removed)",
+                "block updates ticket (level 0, for 2 more ticks)",
+            ]
+        );
+
+        // the block updates run out, but the chunk is still forced
+        world.loader.tick_tickets();
+        world.loader.tick_tickets();
+        assert_eq!(world.loader.tickets().len(), 1);
+        assert!(world.loader.unload_chunks([held]).is_empty());
+
+        // once nothing holds it, it can go
+        world.loader.remove_ticket(forced);
+        world.loader.finish();
+        assert_eq!(world.loader.unload_chunks([held]), vec![held]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tickets_hold_a_bounded_area() {
+        // huge levels are cut down to size
+        let forced = Ticket::new(TicketKind::Forced, u8::MAX, TicketLifetime::UntilRemoved);
+        assert_eq!(forced.level, MAX_TICKET_LEVEL);
+
+        let edge = MAX_TICKET_LEVEL as i64;
+        let at = ChunkCoordinate::new(i64::MAX, 0, 0);
+        assert!(forced.holds(at, ChunkCoordinate::new(i64::MAX - edge, edge, -edge)));
+        assert!(!forced.holds(at, ChunkCoordinate::new(i64::MAX - edge - 1, 0, 0)));
+
+        // the spawn area is held as whole columns
+        let spawn = Ticket::new(TicketKind::Spawn, 1, TicketLifetime::UntilRemoved);
+        let at = ChunkCoordinate::new(0, 4, 0);
+        assert!(spawn.holds(at, ChunkCoordinate::new(1, 0, -1)));
+        assert!(spawn.holds(at, ChunkCoordinate::new(-1, 15, 1)));
+        assert!(!spawn.holds(at, ChunkCoordinate::new(2, 4, 0)));
+        assert_eq!(spawn.chunks(at).len(), 3 * 16 * 3);
+    }
+}
@@ -0,0 +1,310 @@
+//! # McRegion
+//!
+//! Minecraft Beta 1.7.3's world format, for bringing old worlds into Macaw
+//! and sending Macaw worlds back out to Beta-era tools.
+//!
+//! A Beta world is a folder with a gzipped NBT `level.dat`, holding the
+//! seed, spawn point and time, and a `region` folder of `r.<x>.<z>.mcr`
+//! files. Each region file covers 32x32 chunk columns, laid out much like
+//! Macaw's own region files: a table of sector locations, a table of
+//! timestamps, then each column's zlib-compressed NBT.
+//!
+//! Columns are 16x128x16 blocks, stored as a byte array of block ids plus
+//! nibble arrays for block data and light. Macaw chunks are 16x16x16, so
+//! every column becomes eight chunks stacked from `y = 0`.
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use super::{
+    coordinates::GlobalCoordinate,
+    loader::WorldLoadingError,
+    nbt::{self, Compound, NbtError, Tag},
+    region::RegionError,
+    state::Weather,
+    storage::StorageError,
+};
+
+pub mod export;
+pub mod import;
+
+/// The name of the file holding a world's seed, spawn, and so on.
+pub const LEVEL_FILE: &str = "level.dat";
+
+/// The folder holding a world's region files.
+pub const REGION_DIRECTORY: &str = "region";
+
+/// The `version` Beta writes into `level.dat` for McRegion worlds.
+pub const VERSION: i32 = 19132;
+
+/// How tall a chunk column is, in blocks.
+pub const COLUMN_HEIGHT: usize = 128;
+
+/// The number of blocks in a chunk column.
+pub const COLUMN_VOLUME: usize = 16 * 16 * COLUMN_HEIGHT;
+
+/// How many chunk columns wide a region is.
+pub const REGION_COLUMNS: i32 = 32;
+
+/// The size of a sector in a region file, in bytes.
+const SECTOR_BYTES: usize = 4096;
+
+/// The world-wide settings found in `level.dat`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LevelData {
+    /// The world's name, as shown in Beta's world list.
+    pub name: String,
+    /// The seed the world was generated with.
+    pub seed: i64,
+    /// Where players first appear.
+    pub spawn: GlobalCoordinate,
+    /// The world's age, in ticks.
+    pub time: i64,
+    /// Whether it's raining or thundering, and for how much longer.
+    pub weather: Weather,
+    /// When the world was last played, in milliseconds since the Unix epoch.
+    pub last_played: i64,
+}
+
+impl LevelData {
+    /// Reads the contents of a `level.dat` file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, McRegionError> {
+        let (_, root) = nbt::from_gzip(bytes)?;
+        let data = root.compound("Data")?;
+
+        Ok(Self {
+            name: data.string("LevelName").unwrap_or_default().to_owned(),
+            seed: data.long("RandomSeed")?,
+            spawn: GlobalCoordinate::new(
+                data.int("SpawnX")? as i64,
+                data.int("SpawnY")? as i64,
+                data.int("SpawnZ")? as i64,
+            ),
+            time: data.long("Time").unwrap_or_default(),
+            // weather came in Beta 1.5, so older worlds don't have any
+            weather: Weather {
+                raining: data.byte("raining").unwrap_or_default() != 0,
+                rain_time: data.int("rainTime").unwrap_or_default().max(0) as u32,
+                thundering: data.byte("thundering").unwrap_or_default() != 0,
+                thunder_time: data.int("thunderTime").unwrap_or_default().max(0) as u32,
+            },
+            last_played: data.long("LastPlayed").unwrap_or_default(),
+        })
+    }
+
+    /// Writes these settings out as a `level.dat` file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, McRegionError> {
+        let data = Compound::new()
+            .with("LevelName", Tag::String(self.name.clone()))
+            .with("RandomSeed", Tag::Long(self.seed))
+            .with("SpawnX", Tag::Int(self.spawn.x as i32))
+            .with("SpawnY", Tag::Int(self.spawn.y as i32))
+            .with("SpawnZ", Tag::Int(self.spawn.z as i32))
+            .with("Time", Tag::Long(self.time))
+            .with("LastPlayed", Tag::Long(self.last_played))
+            .with("raining", Tag::Byte(self.weather.raining as i8))
+            .with(
+                "rainTime",
+                Tag::Int(self.weather.rain_time.min(i32::MAX as u32) as i32),
+            )
+            .with("thundering", Tag::Byte(self.weather.thundering as i8))
+            .with(
+                "thunderTime",
+                Tag::Int(self.weather.thunder_time.min(i32::MAX as u32) as i32),
+            )
+            .with("SizeOnDisk", Tag::Long(0))
+            .with("version", Tag::Int(VERSION));
+
+        Ok(nbt::to_gzip(
+            "",
+            &Compound::new().with("Data", Tag::Compound(data)),
+        )?)
+    }
+}
+
+/// A chunk column's index in a region file, with its NBT (or why it couldn't
+/// be read).
+pub type RegionColumn = (usize, Result<Compound, McRegionError>);
+
+/// Reads every chunk column out of a region file.
+pub fn read_region(bytes: &[u8]) -> Result<Vec<RegionColumn>, McRegionError> {
+    let columns = (REGION_COLUMNS * REGION_COLUMNS) as usize;
+    if bytes.len() < columns * 4 {
+        return Err(McRegionError::BadRegionFile(format!(
+            "too short to have a header ({} bytes)",
+            bytes.len()
+        )));
+    }
+
+    let mut found = Vec::new();
+    for index in 0..columns {
+        let location = u32::from_be_bytes([
+            bytes[index * 4],
+            bytes[index * 4 + 1],
+            bytes[index * 4 + 2],
+            bytes[index * 4 + 3],
+        ]);
+        if location == 0 {
+            continue;
+        }
+
+        let start = (location >> 8) as usize * SECTOR_BYTES;
+        found.push((index, read_column(bytes, start)));
+    }
+
+    Ok(found)
+}
+
+/// Reads a single column's NBT, starting at `start` in a region file.
+fn read_column(bytes: &[u8], start: usize) -> Result<Compound, McRegionError> {
+    let cut_off = || McRegionError::BadColumn(format!("data at byte {start} is cut off"));
+
+    let header = bytes.get(start..start + 5).ok_or_else(cut_off)?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+
+    // the length includes the compression byte
+    let data = bytes
+        .get(start + 5..(start + 4).saturating_add(length))
+        .ok_or_else(cut_off)?;
+
+    let (_, root) = match header[4] {
+        1 => nbt::from_gzip(data)?,
+        2 => nbt::from_zlib(data)?,
+        other => {
+            return Err(McRegionError::BadColumn(format!(
+                "unknown compression type `{other}`"
+            )))
+        }
+    };
+
+    Ok(root.compound("Level")?.clone())
+}
+
+/// Builds a region file out of chunk columns, each given as its index in the
+/// file and its `Level` compound.
+///
+/// Every column is stamped with the same `timestamp`, in seconds since the
+/// Unix epoch.
+pub fn write_region(
+    columns: &[(usize, Compound)],
+    timestamp: u32,
+) -> Result<Vec<u8>, McRegionError> {
+    let entries = (REGION_COLUMNS * REGION_COLUMNS) as usize;
+
+    // the location and timestamp tables take up the first two sectors
+    let mut bytes = vec![0; SECTOR_BYTES * 2];
+    for (index, level) in columns {
+        if *index >= entries {
+            return Err(McRegionError::BadRegionFile(format!(
+                "there's no column {index} in a region"
+            )));
+        }
+
+        let data = nbt::to_zlib(
+            "",
+            &Compound::new().with("Level", Tag::Compound(level.clone())),
+        )?;
+        let start = bytes.len();
+
+        // the length includes the compression byte
+        bytes.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+        bytes.push(2);
+        bytes.extend_from_slice(&data);
+        bytes.resize(bytes.len().div_ceil(SECTOR_BYTES) * SECTOR_BYTES, 0);
+
+        let sectors = (bytes.len() - start) / SECTOR_BYTES;
+        if sectors > u8::MAX as usize {
+            return Err(McRegionError::BadRegionFile(format!(
+                "column {index} is too big to fit ({sectors} sectors)"
+            )));
+        }
+
+        let location = (((start / SECTOR_BYTES) as u32) << 8) | sectors as u32;
+        bytes[index * 4..index * 4 + 4].copy_from_slice(&location.to_be_bytes());
+        bytes[SECTOR_BYTES + index * 4..SECTOR_BYTES + index * 4 + 4]
+            .copy_from_slice(&timestamp.to_be_bytes());
+    }
+
+    Ok(bytes)
+}
+
+/// The name of the region file holding the given region.
+pub fn region_file_name(x: i32, z: i32) -> String {
+    format!("r.{x}.{z}.mcr")
+}
+
+/// Reads a region's coordinates from a region file's name, like
+/// `r.-1.2.mcr`.
+pub fn region_from_file_name(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let (x, z) = name
+        .strip_prefix("r.")?
+        .strip_suffix(".mcr")?
+        .split_once('.')?;
+
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+
+/// Where a block lives in a column's arrays.
+pub fn column_index(x: usize, y: usize, z: usize) -> usize {
+    y + z * COLUMN_HEIGHT + x * COLUMN_HEIGHT * 16
+}
+
+/// Gets a 4-bit value out of a nibble array. Even indices are in the low
+/// bits of each byte.
+pub fn nibble(array: &[u8], index: usize) -> u8 {
+    let byte = array.get(index / 2).copied().unwrap_or_default();
+
+    if index.is_multiple_of(2) {
+        byte & 0xF
+    } else {
+        byte >> 4
+    }
+}
+
+/// Sets a 4-bit value in a nibble array. Even indices are in the low bits of
+/// each byte.
+pub fn set_nibble(array: &mut [u8], index: usize, value: u8) {
+    let byte = &mut array[index / 2];
+
+    if index.is_multiple_of(2) {
+        *byte = (*byte & 0xF0) | (value & 0xF);
+    } else {
+        *byte = (*byte & 0x0F) | ((value & 0xF) << 4);
+    }
+}
+
+/// An error from reading or writing McRegion worlds.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum McRegionError {
+    #[error("Filesystem error at `{path}`: `{error}`")]
+    Io { path: String, error: String },
+    #[error("`{0}` isn't a Beta world: it has no `level.dat`")]
+    NotAWorld(String),
+    #[error("A save for a world named `{0}` already exists")]
+    SaveAlreadyExists(String),
+    #[error("There's already a Beta world at `{0}`")]
+    WorldAlreadyExists(String),
+    #[error("Region file is broken: {0}")]
+    BadRegionFile(String),
+    #[error("Chunk column is broken: {0}")]
+    BadColumn(String),
+    #[error(transparent)]
+    Nbt(#[from] NbtError),
+    #[error(transparent)]
+    Region(#[from] RegionError),
+    #[error(transparent)]
+    Loading(#[from] WorldLoadingError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+impl McRegionError {
+    pub(crate) fn io(path: &Path, error: std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! # Export
+//!
+//! Writes a Macaw save (or a box of it) out as a Beta 1.7.3 world, so
+//! Beta-era map renderers and editors can work with it.
+//!
+//! Blocks are mapped with `block::legacy`. Anything Beta doesn't have
+//! becomes air, and Beta columns stop at `y = 127`, so chunks below or above
+//! that are left out. Both are counted in the `ExportSummary`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::Path,
+};
+
+use super::{
+    column_index, region_file_name, set_nibble, write_region, LevelData, McRegionError,
+    COLUMN_HEIGHT, COLUMN_VOLUME, LEVEL_FILE, REGION_COLUMNS, REGION_DIRECTORY,
+};
+use crate::{
+    block::{legacy, BlockType},
+    world::{
+        chunk::{light::LightKind, Chunk, CHUNK_LENGTH},
+        coordinates::{
+            bounding_box::BoundingBox, ChunkBlockCoordinate, ChunkCoordinate, ColumnCoordinate,
+            GlobalCoordinate,
+        },
+        loader::WorldLoader,
+        nbt::{Compound, Tag},
+        region::Region,
+        save::WorldSave,
+        state::WorldState,
+    },
+};
+
+/// How many Macaw chunks fit in a Beta column.
+const COLUMN_CHUNKS: i64 = (COLUMN_HEIGHT / CHUNK_LENGTH as usize) as i64;
+
+/// What happened while exporting a world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportSummary {
+    /// Everything written into the new `level.dat`.
+    level: LevelData,
+    /// How many chunk columns were written.
+    columns: usize,
+    /// How many Macaw chunks went into those columns.
+    chunks: usize,
+    /// How many chunks with blocks in them were outside Beta's height limits.
+    clipped_chunks: usize,
+    /// How many of each Macaw block type were replaced with air.
+    unknown_blocks: BTreeMap<BlockType, u64>,
+    /// Regions that couldn't be exported, and why.
+    skipped: Vec<(String, McRegionError)>,
+}
+
+impl ExportSummary {
+    /// Everything written into the new world's `level.dat`.
+    pub fn level(&self) -> &LevelData {
+        &self.level
+    }
+
+    /// How many chunk columns were written.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// How many Macaw chunks were exported.
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// How many chunks with blocks in them were left out for being below
+    /// `y = 0` or above `y = 127`.
+    pub fn clipped_chunks(&self) -> usize {
+        self.clipped_chunks
+    }
+
+    /// How many of each Macaw block type had no Beta equivalent and were
+    /// replaced with air.
+    pub fn unknown_blocks(&self) -> &BTreeMap<BlockType, u64> {
+        &self.unknown_blocks
+    }
+
+    /// The parts of the world that couldn't be exported, with the reason for
+    /// each.
+    pub fn skipped(&self) -> &[(String, McRegionError)] {
+        &self.skipped
+    }
+}
+
+impl Display for ExportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Exported `{}`: {} chunks into {} columns.",
+            self.level.name, self.chunks, self.columns
+        )?;
+
+        if self.clipped_chunks > 0 {
+            writeln!(
+                f,
+                "Left out {} chunks outside of y = 0 to {}.",
+                self.clipped_chunks,
+                COLUMN_HEIGHT - 1
+            )?;
+        }
+
+        if !self.unknown_blocks.is_empty() {
+            writeln!(f, "Blocks without a Beta equivalent (replaced with air):")?;
+            for (block_type, count) in self.unknown_blocks.iter() {
+                writeln!(f, "- {block_type} x{count}")?;
+            }
+        }
+
+        if !self.skipped.is_empty() {
+            writeln!(f, "Skipped:")?;
+            for (what, reason) in self.skipped.iter() {
+                writeln!(f, "- {what}: {reason}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Exports a Macaw save as a Beta world in the `destination` folder.
+///
+/// Only blocks inside `bounds` are exported, if it's given. Otherwise, the
+/// whole save is. This fails if there's already a Beta world in
+/// `destination`.
+pub fn export(
+    save: &WorldSave,
+    destination: &Path,
+    bounds: Option<BoundingBox<GlobalCoordinate>>,
+) -> Result<ExportSummary, McRegionError> {
+    let level_path = destination.join(LEVEL_FILE);
+    if level_path.exists() {
+        return Err(McRegionError::WorldAlreadyExists(
+            destination.to_string_lossy().to_string(),
+        ));
+    }
+
+    let metadata = save.metadata();
+    let state =
+        WorldState::read(save.storage().as_ref(), &metadata.save_path())?.unwrap_or_default();
+    let level = LevelData {
+        name: metadata.name().to_owned(),
+        // Beta seeds are signed, but they're the same bits
+        seed: metadata.seed() as i64,
+        spawn: state.spawn_location,
+        time: state.time.ticks() as i64,
+        weather: state.weather,
+        last_played: state.last_played.timestamp_millis(),
+    };
+
+    let region_folder = destination.join(REGION_DIRECTORY);
+    std::fs::create_dir_all(&region_folder).map_err(|e| McRegionError::io(&region_folder, e))?;
+
+    let mut summary = ExportSummary {
+        level,
+        columns: 0,
+        chunks: 0,
+        clipped_chunks: 0,
+        unknown_blocks: BTreeMap::new(),
+        skipped: Vec::new(),
+    };
+
+    for ((x, z), chunks) in region_stacks(save)? {
+        let chunks = match bounds {
+            Some(bounds) => match intersect(chunks, bounds.chunks()) {
+                Some(chunks) => chunks,
+                None => continue,
+            },
+            None => chunks,
+        };
+
+        let (Ok(beta_x), Ok(beta_z)) = (i32::try_from(x), i32::try_from(z)) else {
+            summary.skipped.push((
+                format!("regions at ({x}, {z})"),
+                McRegionError::BadRegionFile("it's too far out for Beta".into()),
+            ));
+            continue;
+        };
+
+        let path = region_folder.join(region_file_name(beta_x, beta_z));
+        if let Err(e) = export_region(save, &path, chunks, bounds, &mut summary) {
+            summary.skipped.push((format!("regions at ({x}, {z})"), e));
+        }
+    }
+
+    if summary.clipped_chunks > 0 {
+        tracing::warn!(
+            "Left out {} chunks that don't fit in Beta's height limits",
+            summary.clipped_chunks
+        );
+    }
+
+    // the world only counts as a world once it has a `level.dat`
+    let level_bytes = summary.level.to_bytes()?;
+    std::fs::write(&level_path, level_bytes).map_err(|e| McRegionError::io(&level_path, e))?;
+
+    tracing::info!("{summary}");
+    Ok(summary)
+}
+
+/// Finds every stack of saved regions, keyed by the regions' x and z, with
+/// the box of chunks they cover.
+///
+/// A Beta region is as wide as a Macaw one, so each stack becomes one Beta
+/// region file.
+fn region_stacks(
+    save: &WorldSave,
+) -> Result<BTreeMap<(i64, i64), BoundingBox<ChunkCoordinate>>, McRegionError> {
+    let save_path = save.metadata().save_path();
+    let mut stacks: BTreeMap<(i64, i64), BoundingBox<ChunkCoordinate>> = BTreeMap::new();
+
+    for path in save.storage().list(&save_path)? {
+        // only regions sit right inside the save, not in its other folders
+        if path.parent() != Some(save_path.as_path()) {
+            continue;
+        }
+        let Some(region) = Region::coordinates_from_filename(&path) else {
+            continue;
+        };
+
+        let (smaller, larger) = (Region::minimum_chunk(region), Region::maximum_chunk(region));
+        stacks
+            .entry((region.x, region.z))
+            .and_modify(|stack| {
+                let (low, high) = stack.bounds();
+                *stack = BoundingBox::new(
+                    ChunkCoordinate::new(low.x, low.y.min(smaller.y), low.z),
+                    ChunkCoordinate::new(high.x, high.y.max(larger.y), high.z),
+                );
+            })
+            .or_insert(BoundingBox::new(smaller, larger));
+    }
+
+    Ok(stacks)
+}
+
+/// The chunks in both boxes, if there are any.
+fn intersect(
+    a: BoundingBox<ChunkCoordinate>,
+    b: BoundingBox<ChunkCoordinate>,
+) -> Option<BoundingBox<ChunkCoordinate>> {
+    let ((a_low, a_high), (b_low, b_high)) = (a.bounds(), b.bounds());
+
+    let low = ChunkCoordinate::new(
+        a_low.x.max(b_low.x),
+        a_low.y.max(b_low.y),
+        a_low.z.max(b_low.z),
+    );
+    let high = ChunkCoordinate::new(
+        a_high.x.min(b_high.x),
+        a_high.y.min(b_high.y),
+        a_high.z.min(b_high.z),
+    );
+
+    (low.x <= high.x && low.y <= high.y && low.z <= high.z).then(|| BoundingBox::new(low, high))
+}
+
+/// Exports a stack of regions into a single Beta region file at `path`.
+fn export_region(
+    save: &WorldSave,
+    path: &Path,
+    chunks: BoundingBox<ChunkCoordinate>,
+    bounds: Option<BoundingBox<GlobalCoordinate>>,
+    summary: &mut ExportSummary,
+) -> Result<(), McRegionError> {
+    let mut loader = WorldLoader::new_with_save(save.clone(), chunks)?;
+    for report in loader.take_quarantined() {
+        summary.skipped.push((
+            format!("part of `{}`", path.to_string_lossy()),
+            report.into(),
+        ));
+    }
+
+    let mut columns: HashMap<ColumnCoordinate, BetaColumn> = HashMap::new();
+    for (coords, chunk) in loader.chunks_ref() {
+        if !(0..COLUMN_CHUNKS).contains(&coords.y) {
+            if !chunk.blocks().is_empty() {
+                summary.clipped_chunks += 1;
+            }
+            continue;
+        }
+
+        columns
+            .entry(coords.column())
+            .or_insert_with(BetaColumn::new)
+            .add_chunk(chunk, bounds, &mut summary.unknown_blocks);
+        summary.chunks += 1;
+    }
+
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let mut levels = columns
+        .into_iter()
+        .map(|(column, beta)| {
+            let index = column.x.rem_euclid(REGION_COLUMNS as i64)
+                + column.z.rem_euclid(REGION_COLUMNS as i64) * REGION_COLUMNS as i64;
+            (index as usize, beta.into_level(column))
+        })
+        .collect::<Vec<_>>();
+    levels.sort_by_key(|(index, _)| *index);
+
+    let bytes = write_region(&levels, chrono::Utc::now().timestamp() as u32)?;
+    std::fs::write(path, bytes).map_err(|e| McRegionError::io(path, e))?;
+
+    summary.columns += levels.len();
+    Ok(())
+}
+
+/// A Beta chunk column being filled in from Macaw chunks.
+struct BetaColumn {
+    blocks: Vec<u8>,
+    data: Vec<u8>,
+    sky_light: Vec<u8>,
+    block_light: Vec<u8>,
+    /// The lowest height that gets full sky light, for each `z * 16 + x`.
+    heights: Vec<u8>,
+}
+
+impl BetaColumn {
+    fn new() -> Self {
+        Self {
+            blocks: vec![0; COLUMN_VOLUME],
+            data: vec![0; COLUMN_VOLUME / 2],
+            // missing chunks are open sky
+            sky_light: vec![0xFF; COLUMN_VOLUME / 2],
+            block_light: vec![0; COLUMN_VOLUME / 2],
+            heights: vec![0; 16 * 16],
+        }
+    }
+
+    /// Copies a chunk's blocks and light into this column.
+    fn add_chunk(
+        &mut self,
+        chunk: &Chunk,
+        bounds: Option<BoundingBox<GlobalCoordinate>>,
+        unknown_blocks: &mut BTreeMap<BlockType, u64>,
+    ) {
+        let base = (chunk.coords().y * CHUNK_LENGTH as i64) as usize;
+        let index = |local: &ChunkBlockCoordinate| {
+            column_index(
+                local.x() as usize,
+                base + local.y() as usize,
+                local.z() as usize,
+            )
+        };
+
+        for local in Chunk::bounds().all_coordinates() {
+            let i = index(&local);
+            set_nibble(&mut self.sky_light, i, chunk.light(LightKind::Sky, &local));
+            set_nibble(
+                &mut self.block_light,
+                i,
+                chunk.light(LightKind::Block, &local),
+            );
+        }
+
+        for (local, block) in chunk.blocks() {
+            if let Some(bounds) = bounds {
+                if !bounds.contains(chunk.global_block_coord(local)) {
+                    continue;
+                }
+            }
+
+            let Some((id, data)) = legacy::to_legacy(&block) else {
+                *unknown_blocks.entry(block.block_type).or_default() += 1;
+                continue;
+            };
+
+            let i = index(&local);
+            self.blocks[i] = id;
+            set_nibble(&mut self.data, i, data);
+
+            if block.light_opacity() > 0 {
+                let height = &mut self.heights[local.z() as usize * 16 + local.x() as usize];
+                *height = (*height).max((base + local.y() as usize + 1) as u8);
+            }
+        }
+    }
+
+    /// Turns this column into the `Level` compound Beta expects.
+    fn into_level(self, column: ColumnCoordinate) -> Compound {
+        Compound::new()
+            .with("xPos", Tag::Int(column.x as i32))
+            .with("zPos", Tag::Int(column.z as i32))
+            .with("Blocks", Tag::ByteArray(self.blocks))
+            .with("Data", Tag::ByteArray(self.data))
+            .with("SkyLight", Tag::ByteArray(self.sky_light))
+            .with("BlockLight", Tag::ByteArray(self.block_light))
+            .with("HeightMap", Tag::ByteArray(self.heights))
+            .with("Entities", Tag::List(Vec::new()))
+            .with("TileEntities", Tag::List(Vec::new()))
+            .with("LastUpdate", Tag::Long(0))
+            .with("TerrainPopulated", Tag::Byte(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::export;
+    use crate::{
+        block::{
+            state::{Facing, PropertyValue},
+            Block, BlockType,
+        },
+        world::{
+            chunk::Chunk,
+            coordinates::{
+                bounding_box::BoundingBox, ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate,
+            },
+            mcregion::{import::import, McRegionError},
+            save::WorldSave,
+            storage::{MemoryStorage, Storage},
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn worlds_survive_a_trip_through_beta() -> anyhow::Result<()> {
+        let folder = std::env::temp_dir().join(format!("macaw-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+
+        let mut world = MacawWorld::default();
+        let mut chunk = Chunk::new(ChunkCoordinate::new(-1, 0, 2));
+        chunk.set_block(
+            Block::new(BlockType::STONE, 0),
+            ChunkBlockCoordinate::new(0, 0, 0),
+        );
+        let mut torch = Block::new(BlockType::TORCH, 0);
+        torch.set(PropertyValue::Facing(Facing::West))?;
+        chunk.set_block(torch.clone(), ChunkBlockCoordinate::new(3, 4, 5));
+        chunk.set_block(
+            Block::new(BlockType::new(999), 0),
+            ChunkBlockCoordinate::new(1, 1, 1),
+        );
+        world.set_chunk(chunk, ChunkCoordinate::new(-1, 0, 2));
+
+        // too high for Beta
+        let high = ChunkCoordinate::new(0, 8, 0);
+        world.set_chunk(
+            Chunk::new_filled(Block::new(BlockType::DIRT, 0), high),
+            high,
+        );
+        world.save()?;
+
+        let save = WorldSave::new(world.metadata(), world.storage())?;
+        let summary = export(&save, &folder, None)?;
+        assert_eq!(summary.clipped_chunks(), 1);
+        assert_eq!(summary.unknown_blocks().get(&BlockType::new(999)), Some(&1));
+        assert!(summary.skipped().is_empty());
+
+        // exporting over an existing world isn't allowed
+        assert!(matches!(
+            export(&save, &folder, None),
+            Err(McRegionError::WorldAlreadyExists(_))
+        ));
+
+        // importing it again gives back what Beta could hold
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let imported = import(&folder, "round trip".into(), storage.clone())?;
+        assert!(imported.unknown_blocks().is_empty());
+        let mut back = MacawWorld::load(imported.metadata(), storage)?;
+
+        let stone = back.block_from_coords(GlobalCoordinate::new(-16, 0, 32));
+        assert_eq!(stone.map(|b| b.block_type), Some(BlockType::STONE));
+        let exported_torch = back.block_from_coords(GlobalCoordinate::new(-13, 4, 37));
+        assert_eq!(exported_torch, Some(torch));
+        let unknown = back.block_from_coords(GlobalCoordinate::new(-15, 1, 33));
+        assert_eq!(unknown.map(|b| b.block_type), Some(BlockType::AIR));
+
+        // boxes leave everything else out
+        std::fs::remove_dir_all(&folder)?;
+        let bounds = BoundingBox::new(
+            GlobalCoordinate::new(-13, 4, 37),
+            GlobalCoordinate::new(-13, 4, 37),
+        );
+        let summary = export(&save, &folder, Some(bounds))?;
+        assert_eq!((summary.columns(), summary.clipped_chunks()), (1, 0));
+        assert!(summary.unknown_blocks().is_empty());
+
+        std::fs::remove_dir_all(folder)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! # Import
+//!
+//! Turns a Beta 1.7.3 world folder into a brand new Macaw save.
+//!
+//! Blocks are mapped with `block::legacy`. Anything without a Macaw
+//! equivalent becomes air, and is counted in the `ImportSummary` so nothing
+//! disappears without anyone knowing. Tile entities become block entities
+//! the same way: chests, furnaces, signs, note blocks and mob spawners come
+//! along, and the rest are counted. Broken columns are skipped and reported
+//! too.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{
+    column_index, nibble, read_region, region_from_file_name, LevelData, McRegionError,
+    COLUMN_HEIGHT, COLUMN_VOLUME, LEVEL_FILE, REGION_COLUMNS, REGION_DIRECTORY,
+};
+use crate::{
+    block::{
+        entity::{BlockEntity, Furnace, Inventory, ItemStack, MobSpawner, NoteBlock, Sign},
+        legacy, Block, BlockType,
+    },
+    time::GameTick,
+    world::{
+        chunk::{light::LightKind, Chunk, CHUNK_LENGTH},
+        coordinates::{ChunkBlockCoordinate, ChunkCoordinate, ColumnCoordinate, RegionCoordinate},
+        generation::{generators::blank::BlankGenerator, Generator as _},
+        heightmap::{Heightmap, HeightmapKind},
+        metadata::WorldMetadata,
+        nbt::{Compound, NbtError, Tag},
+        save::WorldSave,
+        state::WorldState,
+        storage::Storage,
+    },
+};
+
+/// What happened while importing a world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportSummary {
+    /// The metadata of the new save.
+    metadata: Arc<WorldMetadata>,
+    /// Everything in the world's `level.dat`.
+    level: LevelData,
+    /// How many chunk columns were read.
+    columns: usize,
+    /// How many Macaw chunks those columns became.
+    chunks: usize,
+    /// How many of each Beta block (id and data) were replaced with air.
+    unknown_blocks: BTreeMap<(u8, u8), u64>,
+    /// How many of each Beta tile entity, by id, were left behind.
+    unknown_tile_entities: BTreeMap<String, u64>,
+    /// Columns and region files that couldn't be read, and why.
+    skipped: Vec<(String, McRegionError)>,
+}
+
+impl ImportSummary {
+    /// The metadata of the new save. Load the world with this!
+    pub fn metadata(&self) -> Arc<WorldMetadata> {
+        self.metadata.clone()
+    }
+
+    /// Everything in the old world's `level.dat`.
+    pub fn level(&self) -> &LevelData {
+        &self.level
+    }
+
+    /// How many chunk columns were imported.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// How many Macaw chunks were written.
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// How many of each Beta block, by id and data, had no Macaw equivalent
+    /// and were replaced with air.
+    pub fn unknown_blocks(&self) -> &BTreeMap<(u8, u8), u64> {
+        &self.unknown_blocks
+    }
+
+    /// How many of each Beta tile entity, by id, were left behind. They
+    /// either have no Macaw equivalent, were broken, or weren't on a block
+    /// that could hold them.
+    pub fn unknown_tile_entities(&self) -> &BTreeMap<String, u64> {
+        &self.unknown_tile_entities
+    }
+
+    /// The parts of the world that couldn't be read, with the reason for
+    /// each.
+    pub fn skipped(&self) -> &[(String, McRegionError)] {
+        &self.skipped
+    }
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Imported `{}`: {} columns into {} chunks.",
+            self.metadata.name(),
+            self.columns,
+            self.chunks
+        )?;
+
+        if !self.unknown_blocks.is_empty() {
+            writeln!(f, "Blocks without a Macaw equivalent (replaced with air):")?;
+            for ((id, data), count) in self.unknown_blocks.iter() {
+                writeln!(f, "- {id}:{data} x{count}")?;
+            }
+        }
+
+        if !self.unknown_tile_entities.is_empty() {
+            writeln!(f, "Tile entities that were left behind:")?;
+            for (id, count) in self.unknown_tile_entities.iter() {
+                writeln!(f, "- {id} x{count}")?;
+            }
+        }
+
+        if !self.skipped.is_empty() {
+            writeln!(f, "Skipped:")?;
+            for (what, reason) in self.skipped.iter() {
+                writeln!(f, "- {what}: {reason}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Imports the Beta world in the `source` folder as a new Macaw world
+/// called `name`, saved into `storage`.
+///
+/// This fails if `storage` already has a save for a world with that name.
+pub fn import(
+    source: &Path,
+    name: String,
+    storage: Storage,
+) -> Result<ImportSummary, McRegionError> {
+    let level_path = source.join(LEVEL_FILE);
+    if !level_path.is_file() {
+        return Err(McRegionError::NotAWorld(
+            source.to_string_lossy().to_string(),
+        ));
+    }
+
+    let level_bytes = std::fs::read(&level_path).map_err(|e| McRegionError::io(&level_path, e))?;
+    let level = LevelData::from_bytes(&level_bytes)?;
+
+    // Beta seeds are signed, but they're the same bits
+    let metadata = WorldMetadata::new_now(name, level.seed as u64, BlankGenerator.id());
+    if storage.exists(&metadata.metadata_path())? {
+        return Err(McRegionError::SaveAlreadyExists(metadata.name().to_owned()));
+    }
+
+    let metadata = Arc::new(metadata);
+    let save = WorldSave::new(metadata.clone(), storage.clone())?;
+
+    let state = WorldState {
+        spawn_location: level.spawn,
+        time: GameTick::from_ticks(level.time.max(0) as u64),
+        weather: level.weather,
+        last_played: chrono::DateTime::from_timestamp_millis(level.last_played)
+            .unwrap_or_else(chrono::Utc::now),
+    };
+    state.write(storage.as_ref(), &metadata.save_path())?;
+
+    let mut summary = ImportSummary {
+        metadata,
+        level,
+        columns: 0,
+        chunks: 0,
+        unknown_blocks: BTreeMap::new(),
+        unknown_tile_entities: BTreeMap::new(),
+        skipped: Vec::new(),
+    };
+
+    for path in region_files(source)? {
+        let Some((x, z)) = region_from_file_name(&path) else {
+            continue;
+        };
+
+        if let Err(e) = import_region(&save, &path, x, z, &mut summary) {
+            summary
+                .skipped
+                .push((format!("region file `{}`", path.to_string_lossy()), e));
+        }
+    }
+
+    tracing::info!("{summary}");
+    Ok(summary)
+}
+
+/// Lists the region files in a Beta world, in name order.
+fn region_files(source: &Path) -> Result<Vec<PathBuf>, McRegionError> {
+    let folder = source.join(REGION_DIRECTORY);
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = std::fs::read_dir(&folder)
+        .map_err(|e| McRegionError::io(&folder, e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mcr"))
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Imports every column in a region file.
+///
+/// Beta regions are as wide as Macaw regions, and columns are shorter than
+/// Macaw regions are tall, so each one lands in exactly one Macaw region.
+fn import_region(
+    save: &WorldSave,
+    path: &Path,
+    x: i32,
+    z: i32,
+    summary: &mut ImportSummary,
+) -> Result<(), McRegionError> {
+    let bytes = std::fs::read(path).map_err(|e| McRegionError::io(path, e))?;
+    let region = RegionCoordinate::new(x as i64, 0, z as i64);
+    let mut file = save.writable_region_file(region)?;
+
+    for (index, level) in read_region(&bytes)? {
+        let column = ColumnCoordinate::new(
+            (x * REGION_COLUMNS) as i64 + (index as i32 % REGION_COLUMNS) as i64,
+            (z * REGION_COLUMNS) as i64 + (index as i32 / REGION_COLUMNS) as i64,
+        );
+
+        let imported = level.and_then(|level| import_column(&level, column, summary));
+        match imported {
+            Ok((chunks, heightmap)) => {
+                for chunk in chunks.iter() {
+                    file.write_chunk(chunk)?;
+                }
+                file.write_heightmap(column, &heightmap)?;
+
+                summary.columns += 1;
+                summary.chunks += chunks.len();
+            }
+            Err(e) => summary.skipped.push((format!("column at {column}"), e)),
+        }
+    }
+
+    file.commit()?;
+    Ok(())
+}
+
+/// Splits a Beta chunk column into Macaw chunks, along with the column's
+/// heightmap. Its tile entities are put on their blocks.
+fn import_column(
+    level: &Compound,
+    column: ColumnCoordinate,
+    summary: &mut ImportSummary,
+) -> Result<(Vec<Chunk>, Heightmap), McRegionError> {
+    let (x_pos, z_pos) = (level.int("xPos")?, level.int("zPos")?);
+    if (x_pos as i64, z_pos as i64) != (column.x, column.z) {
+        return Err(McRegionError::BadColumn(format!(
+            "it says it's at ({x_pos}, {z_pos}), but it's stored at {column}"
+        )));
+    }
+
+    let blocks = level.byte_array("Blocks")?;
+    let data = level.byte_array("Data")?;
+    if blocks.len() != COLUMN_VOLUME || data.len() != COLUMN_VOLUME / 2 {
+        return Err(McRegionError::BadColumn(format!(
+            "it has {} blocks and {} bytes of data",
+            blocks.len(),
+            data.len()
+        )));
+    }
+
+    // light is nice to have, but can be worked out again later
+    let sky_light = level.byte_array("SkyLight").ok();
+    let block_light = level.byte_array("BlockLight").ok();
+
+    let length = CHUNK_LENGTH as usize;
+    let mut chunks = (0..(COLUMN_HEIGHT / length) as i64)
+        .map(|y| Chunk::new(ChunkCoordinate::new(column.x, y, column.z)))
+        .collect::<Vec<_>>();
+    let mut heightmap = Heightmap::new();
+
+    for x in 0..length {
+        for z in 0..length {
+            for y in 0..COLUMN_HEIGHT {
+                let index = column_index(x, y, z);
+                let (id, block_data) = (blocks[index], nibble(data, index));
+
+                let block = legacy::from_legacy(id, block_data).unwrap_or_else(|| {
+                    *summary.unknown_blocks.entry((id, block_data)).or_default() += 1;
+                    Block::default()
+                });
+
+                for kind in HeightmapKind::ALL {
+                    if kind.counts(&block) {
+                        heightmap.set(kind, x as u8, z as u8, Some(y as i64));
+                    }
+                }
+
+                let chunk = &mut chunks[y / length];
+                let local = ChunkBlockCoordinate::new(x as u8, (y % length) as u8, z as u8);
+
+                if block.block_type != BlockType::AIR {
+                    chunk.set_block(block, local);
+                }
+                if let Some(sky_light) = sky_light {
+                    chunk.set_light(LightKind::Sky, local, nibble(sky_light, index));
+                }
+                if let Some(block_light) = block_light {
+                    chunk.set_light(LightKind::Block, local, nibble(block_light, index));
+                }
+            }
+        }
+    }
+
+    // a column without tile entities might leave the list out entirely
+    for tag in level.list("TileEntities").unwrap_or_default() {
+        import_tile_entity(tag, column, &mut chunks, summary);
+    }
+
+    Ok((chunks, heightmap))
+}
+
+/// Puts a Beta tile entity onto its block in an imported column, as a block
+/// entity. Anything that can't be is counted in the summary.
+fn import_tile_entity(
+    tag: &Tag,
+    column: ColumnCoordinate,
+    chunks: &mut [Chunk],
+    summary: &mut ImportSummary,
+) {
+    let Tag::Compound(tile) = tag else {
+        *summary
+            .unknown_tile_entities
+            .entry("(not a compound)".into())
+            .or_default() += 1;
+        return;
+    };
+
+    let placed = match block_entity(tile) {
+        Ok(Some(entity)) => place_block_entity(tile, entity, column, chunks),
+        Ok(None) | Err(_) => false,
+    };
+
+    if !placed {
+        let id = tile.string("id").unwrap_or("(no id)");
+        *summary
+            .unknown_tile_entities
+            .entry(id.to_owned())
+            .or_default() += 1;
+    }
+}
+
+/// Puts a block entity on the block its tile entity was on, if that block is
+/// in the column and can hold it. Gives back whether it could.
+fn place_block_entity(
+    tile: &Compound,
+    entity: BlockEntity,
+    column: ColumnCoordinate,
+    chunks: &mut [Chunk],
+) -> bool {
+    let (Ok(x), Ok(y), Ok(z)) = (tile.int("x"), tile.int("y"), tile.int("z")) else {
+        return false;
+    };
+
+    // it has to be in this column, or it's not ours to place
+    let length = CHUNK_LENGTH as i32;
+    let in_column =
+        (x.div_euclid(length) as i64, z.div_euclid(length) as i64) == (column.x, column.z);
+    if !in_column || !(0..COLUMN_HEIGHT as i32).contains(&y) {
+        return false;
+    }
+
+    let local = ChunkBlockCoordinate::new(
+        x.rem_euclid(length) as u8,
+        (y % length) as u8,
+        z.rem_euclid(length) as u8,
+    );
+    chunks[(y / length) as usize]
+        .set_block_entity(local, entity)
+        .is_ok()
+}
+
+/// Reads a Beta tile entity as a Macaw block entity.
+///
+/// Gives back `None` for tile entities Macaw doesn't have, like dispensers.
+fn block_entity(tile: &Compound) -> Result<Option<BlockEntity>, NbtError> {
+    let entity = match tile.string("id")? {
+        "Chest" => {
+            let mut inventory = Inventory::new(Inventory::CHEST_SIZE);
+            for (slot, stack) in items(tile)? {
+                inventory.set(slot, Some(stack));
+            }
+
+            BlockEntity::Chest(inventory)
+        }
+        "Furnace" => {
+            let mut furnace = Furnace {
+                burn_time: tile.short("BurnTime")?.max(0) as u16,
+                cook_time: tile.short("CookTime")?.max(0) as u16,
+                ..Furnace::default()
+            };
+            for (slot, stack) in items(tile)? {
+                match slot {
+                    0 => furnace.input = Some(stack),
+                    1 => furnace.fuel = Some(stack),
+                    2 => furnace.output = Some(stack),
+                    _ => (),
+                }
+            }
+
+            BlockEntity::Furnace(furnace)
+        }
+        "Sign" => BlockEntity::Sign(Sign {
+            lines: [
+                tile.string("Text1")?.to_owned(),
+                tile.string("Text2")?.to_owned(),
+                tile.string("Text3")?.to_owned(),
+                tile.string("Text4")?.to_owned(),
+            ],
+        }),
+        "Music" => BlockEntity::NoteBlock(NoteBlock {
+            note: (tile.byte("note")?.max(0) as u8).min(24),
+        }),
+        "MobSpawner" => BlockEntity::MobSpawner(MobSpawner {
+            mob: mob_name(tile.string("EntityId")?),
+            delay: tile.short("Delay")?.max(0) as u16,
+        }),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(entity))
+}
+
+/// Reads the `Items` of a Beta tile entity, with the slot each is in.
+fn items(tile: &Compound) -> Result<Vec<(usize, ItemStack)>, NbtError> {
+    let mut items = Vec::new();
+
+    for tag in tile.list("Items")? {
+        let Tag::Compound(item) = tag else {
+            continue;
+        };
+
+        let stack = ItemStack {
+            item: item.short("id")? as u16,
+            count: item.byte("Count")? as u8,
+            damage: item.short("Damage")? as u16,
+        };
+        items.push((item.byte("Slot")? as u8 as usize, stack));
+    }
+
+    Ok(items)
+}
+
+/// Turns a Beta mob id, like `PigZombie`, into a Macaw one, like
+/// `pig_zombie`.
+fn mob_name(id: &str) -> String {
+    let mut name = String::new();
+
+    for (i, c) in id.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use super::{import, McRegionError};
+    use crate::{
+        block::{
+            entity::{BlockEntity, ItemStack},
+            state::{Property, PropertyValue},
+            BlockType,
+        },
+        time::GameTick,
+        world::{
+            coordinates::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
+            mcregion::{column_index, region_file_name, COLUMN_VOLUME, LEVEL_FILE},
+            nbt::{self, Compound, Tag},
+            storage::{MemoryStorage, Storage},
+            MacawWorld,
+        },
+    };
+
+    /// Builds a tiny Beta world with a single column at (1, 0).
+    fn beta_world(folder: &std::path::Path) -> anyhow::Result<()> {
+        let level = Compound::new().with(
+            "Data",
+            Tag::Compound(
+                Compound::new()
+                    .with("LevelName", Tag::String("Old World".into()))
+                    .with("RandomSeed", Tag::Long(-42))
+                    .with("SpawnX", Tag::Int(20))
+                    .with("SpawnY", Tag::Int(65))
+                    .with("SpawnZ", Tag::Int(-3))
+                    .with("Time", Tag::Long(24000)),
+            ),
+        );
+        std::fs::create_dir_all(folder.join("region"))?;
+        std::fs::write(folder.join(LEVEL_FILE), nbt::to_gzip("", &level)?)?;
+
+        // stone at the bottom, a few water blocks, and some red wool on top
+        let mut blocks = vec![0; COLUMN_VOLUME];
+        let mut data = vec![0; COLUMN_VOLUME / 2];
+        for x in 0..16 {
+            for z in 0..16 {
+                blocks[column_index(x, 0, z)] = 1;
+            }
+        }
+        let water = column_index(2, 1, 3);
+        blocks[water] = 8;
+        data[water / 2] = 0x3 << (4 * (water % 2));
+        let wool = column_index(5, 100, 5);
+        blocks[wool] = 35;
+        data[wool / 2] |= 14 << (4 * (wool % 2));
+        blocks[column_index(4, 10, 4)] = 54;
+
+        // a chest with some dirt in it, a sign that lost its block, and a
+        // dispenser, which Macaw doesn't have
+        let tile = |id: &str, x: i32| {
+            Compound::new()
+                .with("id", Tag::String(id.into()))
+                .with("x", Tag::Int(x))
+                .with("y", Tag::Int(10))
+                .with("z", Tag::Int(4))
+        };
+        let dirt = Compound::new()
+            .with("Slot", Tag::Byte(3))
+            .with("id", Tag::Short(3))
+            .with("Count", Tag::Byte(12))
+            .with("Damage", Tag::Short(0));
+        let chest = tile("Chest", 20).with("Items", Tag::List(vec![Tag::Compound(dirt)]));
+        let mut sign = tile("Sign", 21);
+        for line in ["Text1", "Text2", "Text3", "Text4"] {
+            sign.insert(line, Tag::String("hi".into()));
+        }
+        let tile_entities = vec![
+            Tag::Compound(chest),
+            Tag::Compound(sign),
+            Tag::Compound(tile("Trap", 22)),
+        ];
+
+        let column = Compound::new().with(
+            "Level",
+            Tag::Compound(
+                Compound::new()
+                    .with("xPos", Tag::Int(1))
+                    .with("zPos", Tag::Int(0))
+                    .with("Blocks", Tag::ByteArray(blocks))
+                    .with("Data", Tag::ByteArray(data))
+                    .with("TileEntities", Tag::List(tile_entities)),
+            ),
+        );
+        let compressed = nbt::to_zlib("", &column)?;
+
+        // one column, in the second slot of the first region
+        let mut region = vec![0; 4096 * 2];
+        region[4..8].copy_from_slice(&(2 << 8 | 1u32).to_be_bytes());
+        region.extend_from_slice(&(compressed.len() as u32 + 1).to_be_bytes());
+        region.push(2);
+        region.extend_from_slice(&compressed);
+        region.resize(4096 * 4, 0);
+        std::fs::write(folder.join("region").join(region_file_name(0, 0)), region)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn beta_worlds_are_imported() -> anyhow::Result<()> {
+        let folder = std::env::temp_dir().join(format!("macaw-beta-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        beta_world(&folder)?;
+
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let summary = import(&folder, "imported".into(), storage.clone())?;
+
+        assert_eq!((summary.columns(), summary.chunks()), (1, 8));
+        assert_eq!(summary.unknown_blocks().get(&(35, 14)), Some(&1));
+        assert_eq!(
+            summary.unknown_tile_entities(),
+            &BTreeMap::from([("Sign".to_owned(), 1), ("Trap".to_owned(), 1)])
+        );
+        assert!(summary.skipped().is_empty());
+        assert_eq!(summary.metadata().seed(), -42i64 as u64);
+
+        // the new save loads like any other world
+        let mut world = MacawWorld::load(summary.metadata(), storage.clone())?;
+        assert_eq!(world.spawn_location(), GlobalCoordinate::new(20, 65, -3));
+        assert_eq!(world.state().time, GameTick::new(0, 1));
+
+        let stone = world
+            .block_from_coords(GlobalCoordinate::new(16, 0, 0))
+            .unwrap();
+        assert_eq!(stone.block_type, BlockType::STONE);
+        let water = world
+            .block_from_coords(GlobalCoordinate::new(18, 1, 3))
+            .unwrap();
+        assert_eq!(
+            water.get(Property::FluidLevel),
+            Some(PropertyValue::FluidLevel(3))
+        );
+        let wool = world
+            .block_from_coords(GlobalCoordinate::new(21, 100, 5))
+            .unwrap();
+        assert_eq!(wool.block_type, BlockType::AIR);
+
+        // the chest kept its dirt
+        let chest = world.chunk(ChunkCoordinate::new(1, 0, 0)).unwrap();
+        let Some(BlockEntity::Chest(inventory)) =
+            chest.block_entity(&ChunkBlockCoordinate::new(4, 10, 4))
+        else {
+            panic!("the chest should have come along");
+        };
+        assert_eq!(
+            inventory.get(3),
+            Some(&ItemStack {
+                item: 3,
+                count: 12,
+                damage: 0
+            })
+        );
+
+        // importing over an existing save isn't allowed
+        assert_eq!(
+            import(&folder, "imported".into(), storage),
+            Err(McRegionError::SaveAlreadyExists("imported".into()))
+        );
+
+        std::fs::remove_dir_all(folder)?;
+        Ok(())
+    }
+}
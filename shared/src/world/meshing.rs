@@ -18,6 +18,11 @@ impl Meshing for Chunk {
             return true;
         }
 
+        // inside a uniform chunk, every neighbor is the same block
+        if let Some(uniform) = self.uniform_block() {
+            return uniform.is_transparent();
+        }
+
         // show blocks with air around them
         for adj in self.adjacent_blocks(block) {
             if adj.is_transparent() {
@@ -3,7 +3,11 @@
 //! A module containing representations of the various kinds of `MacawWorld`
 //! metadata!
 
-use std::{fmt::Display, fs::File, io::Write as _, path::PathBuf, sync::Arc};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use bevy::utils::Uuid;
 use chrono::DateTime;
@@ -12,9 +16,14 @@ use serde::{Deserialize, Serialize};
 use super::{
     generation::{generators::blank::BlankGenerator, Generator},
     loader::WorldLoadingError,
-    save::get_saves_path,
+    migration::SAVE_FORMAT_VERSION,
+    save::SAVES_DIRECTORY,
+    storage::StorageBackend,
 };
 
+/// The name of the metadata file in every world save.
+pub const METADATA_FILE: &str = "save.toml";
+
 /// Metadata important to maintain a world's consistency.
 /// You should keep these in an `std::sync::Arc` for the most part.
 #[derive(Clone, Debug, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
@@ -22,11 +31,21 @@ pub struct WorldMetadata {
     /// A unique name for a world.
     name: String,
     /// A unique seed used during world generation.
+    ///
+    /// TOML only has signed integers, so it's stored as one with the same
+    /// bits.
+    #[serde(with = "signed_seed")]
     seed: u64,
     /// A representation of this world's generator.
     generator: GeneratorId,
     /// Date/time when the world was made. (i am god)
     creation_date: DateTime<chrono::Utc>,
+    /// The version of the save format this world was written with.
+    ///
+    /// Worlds from before saves were versioned don't have one, so they're
+    /// version 0.
+    #[serde(default)]
+    format_version: u32,
 }
 
 impl WorldMetadata {
@@ -42,6 +61,7 @@ impl WorldMetadata {
             seed,
             generator,
             creation_date,
+            format_version: SAVE_FORMAT_VERSION,
         }
     }
 
@@ -52,6 +72,7 @@ impl WorldMetadata {
             seed,
             generator,
             creation_date: chrono::Utc::now(),
+            format_version: SAVE_FORMAT_VERSION,
         }
     }
 
@@ -75,60 +96,81 @@ impl WorldMetadata {
         &self.creation_date
     }
 
-    /// The path to a world's save directory.
-    pub fn save_path(&self) -> Arc<PathBuf> {
-        let saves_folder = get_saves_path();
+    /// The version of the save format this world was written with.
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
 
-        let save = format!(
-            "{}/{}",
-            saves_folder.to_string_lossy(),
-            urlencoding::encode(self.name()) // urlencoded to discourage nonsense :3
-        );
+    /// A copy of this metadata for a world with a different name.
+    pub fn renamed(&self, name: String) -> Self {
+        Self {
+            name,
+            ..self.clone()
+        }
+    }
 
-        Arc::new(PathBuf::from(save))
+    /// The name of the world's folder in the saves directory.
+    pub fn folder_name(&self) -> String {
+        Self::folder_name_for(self.name())
     }
 
-    /// The path to a world save's metadata file.
+    /// The name of the folder a world with the given name is saved in.
+    pub fn folder_name_for(name: &str) -> String {
+        urlencoding::encode(name).into_owned() // urlencoded to discourage nonsense :3
+    }
+
+    /// The path to a world's save directory, relative to the root of its
+    /// storage.
+    pub fn save_path(&self) -> Arc<PathBuf> {
+        Arc::new(Path::new(SAVES_DIRECTORY).join(self.folder_name()))
+    }
+
+    /// The path to a world save's metadata file, relative to the root of its
+    /// storage.
     pub fn metadata_path(&self) -> Arc<PathBuf> {
-        Arc::new(self.save_path().join("save.toml"))
+        Arc::new(self.save_path().join(METADATA_FILE))
     }
 
-    /// Writes metadata to disk.
-    pub fn write_to_disk(&self) -> Result<(), WorldLoadingError> {
-        // serialize self to string
-        let s = toml::to_string_pretty(&self).map_err(|e| {
-            WorldLoadingError::MetadataWriteFailed(format!(
-                "failed to serialize metadata to toml: {}",
-                e
+    /// Reads the metadata of the world saved at `save_path` in some storage.
+    pub fn read(storage: &dyn StorageBackend, save_path: &Path) -> Result<Self, WorldLoadingError> {
+        let path = save_path.join(METADATA_FILE);
+
+        let bytes = storage.read(&path)?.ok_or_else(|| {
+            WorldLoadingError::MetadataReadFailed(format!(
+                "no metadata file at `{}`",
+                path.to_string_lossy()
             ))
         })?;
 
-        // make path if it doesn't already exist
-        std::fs::create_dir_all(self.save_path().as_ref()).map_err(|e| {
-            WorldLoadingError::SavePathCreationFailure(format!(
-                "error while creating path for metadata: `{}` at path: `{}`",
-                e,
-                self.save_path().to_string_lossy()
+        let s = String::from_utf8(bytes).map_err(|e| {
+            WorldLoadingError::MetadataReadFailed(format!(
+                "metadata file at `{}` isn't UTF-8: {}",
+                path.to_string_lossy(),
+                e
             ))
         })?;
 
-        // create a file
-        let mut file = File::create(self.metadata_path().as_ref()).map_err(|e| {
-            WorldLoadingError::MetadataWriteFailed(format!(
-                "failed to create metadata file at `{}`: {}",
-                self.metadata_path().to_string_lossy(),
+        toml::from_str(&s).map_err(|e| {
+            WorldLoadingError::MetadataReadFailed(format!(
+                "failed to parse metadata file at `{}`: {}",
+                path.to_string_lossy(),
                 e
             ))
-        })?;
+        })
+    }
 
-        // write the string'd self to that file
-        file.write_all(s.as_bytes()).map_err(|e| {
+    /// Writes metadata into its world's save in some storage.
+    pub fn write(&self, storage: &dyn StorageBackend) -> Result<(), WorldLoadingError> {
+        // serialize self to string
+        let s = toml::to_string_pretty(&self).map_err(|e| {
             WorldLoadingError::MetadataWriteFailed(format!(
-                "failed to write stringy metadata: {}",
+                "failed to serialize metadata to toml: {}",
                 e
             ))
         })?;
 
+        // write the string'd self to storage
+        storage.write(&self.metadata_path(), s.as_bytes())?;
         Ok(())
     }
 }
@@ -144,10 +186,24 @@ impl Default for WorldMetadata {
             seed: rand::random(),
             generator: BlankGenerator.id(),
             creation_date: chrono::DateTime::default(),
+            format_version: SAVE_FORMAT_VERSION,
         }
     }
 }
 
+/// Stores seeds as signed integers, so every seed fits in a TOML file.
+mod signed_seed {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        (*seed as i64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        i64::deserialize(deserializer).map(|seed| seed as u64)
+    }
+}
+
 /// The Java-style qualifier to uniquely identify a world's `Generator`.
 ///
 /// Looks like: `tld.organization.name.subname`, where the `subname` element
@@ -0,0 +1,474 @@
+//! # Migration
+//!
+//! Brings saves from older versions of the game up to date.
+//!
+//! Every save records the version of the save format it was written with in
+//! its metadata. When an older world is opened, each migration from that
+//! version onwards runs in order, and the recorded version is bumped after
+//! every step. The whole save is backed up before anything changes, so a
+//! failed migration never costs anyone their world.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{
+    chunk::Chunk,
+    coordinates::{ColumnCoordinate, RegionCoordinate},
+    heightmap::Heightmap,
+    loader::WorldLoadingError,
+    metadata::METADATA_FILE,
+    region::{
+        file::{RegionFile, REGION_MAGIC},
+        Region,
+    },
+    storage::{Storage, StorageBackend},
+    MacawWorld,
+};
+
+mod v0;
+
+/// The save format version written by this version of the game.
+pub const SAVE_FORMAT_VERSION: u32 = 1;
+
+/// The folder where backups go. It sits next to the saves folder.
+pub const BACKUPS_DIRECTORY: &str = "backups";
+
+/// A step that upgrades a save from one format version to the next.
+#[derive(Clone, Copy, Debug)]
+pub struct Migration {
+    /// The version this migration upgrades from. Saves end up at `from + 1`.
+    pub from: u32,
+    /// What this migration changes, for the logs.
+    pub description: &'static str,
+    /// Upgrades the save folder at the given path in storage.
+    pub run: fn(&Storage, &Path) -> Result<(), WorldLoadingError>,
+}
+
+/// Every migration, in order. There's exactly one for each version below
+/// `SAVE_FORMAT_VERSION`.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "split region blobs into region files with a versioned header",
+    run: region_files_from_blobs,
+}];
+
+/// Brings the save at `save_path` in storage up to `SAVE_FORMAT_VERSION`.
+///
+/// Returns where the save was backed up to, or `None` when it was already up
+/// to date. Saves from newer versions of the game are refused.
+pub fn migrate(storage: &Storage, save_path: &Path) -> Result<Option<PathBuf>, WorldLoadingError> {
+    let Some(mut version) = read_format_version(storage.as_ref(), save_path)? else {
+        // no metadata means there's nothing to migrate yet
+        return Ok(None);
+    };
+
+    if version > SAVE_FORMAT_VERSION {
+        return Err(WorldLoadingError::UnsupportedSaveVersion {
+            found: version,
+            supported: SAVE_FORMAT_VERSION,
+        });
+    }
+    if version == SAVE_FORMAT_VERSION {
+        return Ok(None);
+    }
+
+    let backup = backup(storage.as_ref(), save_path, version)?;
+    tracing::info!(
+        "Backed up save at `{}` to `{}` before migrating it",
+        save_path.to_string_lossy(),
+        backup.to_string_lossy()
+    );
+
+    let from = version;
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        tracing::info!(
+            "Migrating save at `{}` from version {} to {}: {}",
+            save_path.to_string_lossy(),
+            migration.from,
+            migration.from + 1,
+            migration.description
+        );
+
+        (migration.run)(storage, save_path)?;
+        version = migration.from + 1;
+        write_format_version(storage.as_ref(), save_path, version)?;
+    }
+
+    Ok(Some(backup))
+}
+
+/// Reads the format version out of a save's metadata, if it has any.
+///
+/// This doesn't deserialize the whole `WorldMetadata`, since older metadata
+/// might not match what this version of the game expects.
+fn read_format_version(
+    storage: &dyn StorageBackend,
+    save_path: &Path,
+) -> Result<Option<u32>, WorldLoadingError> {
+    let Some(table) = read_metadata_table(storage, save_path)? else {
+        return Ok(None);
+    };
+
+    match table.get("format_version") {
+        None => Ok(Some(0)),
+        Some(toml::Value::Integer(version)) => u32::try_from(*version).map(Some).map_err(|_| {
+            WorldLoadingError::MigrationFailed(format!(
+                "save has an impossible format version: `{version}`"
+            ))
+        }),
+        Some(other) => Err(WorldLoadingError::MigrationFailed(format!(
+            "save's format version isn't a number: `{other}`"
+        ))),
+    }
+}
+
+/// Records a new format version in a save's metadata, leaving everything
+/// else as it was.
+fn write_format_version(
+    storage: &dyn StorageBackend,
+    save_path: &Path,
+    version: u32,
+) -> Result<(), WorldLoadingError> {
+    let mut table = read_metadata_table(storage, save_path)?.unwrap_or_default();
+    table.insert(
+        "format_version".into(),
+        toml::Value::Integer(version.into()),
+    );
+
+    let s = toml::to_string_pretty(&table).map_err(|e| {
+        WorldLoadingError::MetadataWriteFailed(format!("failed to serialize metadata to toml: {e}"))
+    })?;
+
+    storage.write(&save_path.join(METADATA_FILE), s.as_bytes())?;
+    Ok(())
+}
+
+fn read_metadata_table(
+    storage: &dyn StorageBackend,
+    save_path: &Path,
+) -> Result<Option<toml::Table>, WorldLoadingError> {
+    let path = save_path.join(METADATA_FILE);
+    let Some(bytes) = storage.read(&path)? else {
+        return Ok(None);
+    };
+
+    let s = String::from_utf8(bytes).map_err(|e| {
+        WorldLoadingError::MigrationFailed(format!(
+            "couldn't read metadata at `{}`: {e}",
+            path.to_string_lossy()
+        ))
+    })?;
+
+    s.parse::<toml::Table>().map(Some).map_err(|e| {
+        WorldLoadingError::MigrationFailed(format!(
+            "couldn't parse metadata at `{}`: {e}",
+            path.to_string_lossy()
+        ))
+    })
+}
+
+/// Copies a whole save into the backups folder, returning the copy's path.
+fn backup(
+    storage: &dyn StorageBackend,
+    save_path: &Path,
+    version: u32,
+) -> Result<PathBuf, WorldLoadingError> {
+    let saves = save_path.parent().unwrap_or(save_path);
+    let name = save_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let destination = saves
+        .parent()
+        .unwrap_or(saves)
+        .join(BACKUPS_DIRECTORY)
+        .join(format!(
+            "{name}-v{version}-{}",
+            chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S")
+        ));
+
+    storage.copy_all(save_path, &destination).map_err(|e| {
+        WorldLoadingError::BackupFailed(format!(
+            "couldn't copy `{}` to `{}`: {e}",
+            save_path.to_string_lossy(),
+            destination.to_string_lossy()
+        ))
+    })?;
+
+    Ok(destination)
+}
+
+/// Version 0 -> 1.
+///
+/// Regions used to be stored as one `bincode` blob each, holding every chunk
+/// in the old format. Now each region is a `RegionFile`, with a versioned
+/// header and its own compressed entry for every chunk. Old regions were
+/// named a little differently, so chunks are sorted into files by their own
+/// coordinates instead of their old region's filename.
+///
+/// Old chunks had no light or heightmaps, so those are worked out for the
+/// whole world before it's written.
+fn region_files_from_blobs(storage: &Storage, save_path: &Path) -> Result<(), WorldLoadingError> {
+    let failed = |path: &Path, e: String| {
+        WorldLoadingError::MigrationFailed(format!(
+            "couldn't upgrade region file at `{}`: {e}",
+            path.to_string_lossy()
+        ))
+    };
+
+    // every old region is read before anything is written, since an old
+    // filename can belong to a different new region
+    let mut old_paths = Vec::new();
+    let mut upgraded = Vec::new();
+
+    for path in storage.list(save_path)? {
+        // only regions sit right inside the save, not in its other folders
+        if path.parent() != Some(save_path) || path.extension().is_none_or(|ext| ext != "region") {
+            continue;
+        }
+
+        let bytes = storage
+            .read(&path)?
+            .ok_or_else(|| failed(&path, "it disappeared".into()))?;
+
+        // already upgraded, maybe by an earlier attempt that got cut off
+        if bytes.starts_with(&REGION_MAGIC) {
+            continue;
+        }
+
+        let old: v0::Region = bincode::deserialize(&bytes)
+            .map_err(|e| failed(&path, format!("it isn't a version 0 region: {e}")))?;
+
+        for chunk in old.chunks.into_values() {
+            upgraded.push(chunk.upgrade().map_err(|e| failed(&path, e))?);
+        }
+        old_paths.push(path);
+    }
+
+    let mut regions: BTreeMap<RegionCoordinate, (Vec<Chunk>, Vec<_>)> = BTreeMap::new();
+    let (chunks, heightmaps) = light_up(upgraded);
+    for chunk in chunks {
+        regions.entry(chunk.region()).or_default().0.push(chunk);
+    }
+    for (column, heightmap) in heightmaps {
+        regions
+            .entry(column.region())
+            .or_default()
+            .1
+            .push((column, heightmap));
+    }
+
+    let mut new_paths = Vec::new();
+    for (coordinates, (chunks, heightmaps)) in regions {
+        let path = save_path.join(Region::filename(&coordinates));
+
+        // new files left by an earlier attempt are added to. old ones sitting
+        // in the way were already read, so they're replaced
+        let mut file = if old_paths.contains(&path) {
+            RegionFile::create(Arc::clone(storage), &path, coordinates)
+        } else {
+            RegionFile::open(Arc::clone(storage), &path, coordinates)?
+        };
+
+        for chunk in chunks.iter() {
+            file.write_chunk(chunk)?;
+        }
+
+        for (column, heightmap) in heightmaps.iter() {
+            file.write_heightmap(*column, heightmap)?;
+        }
+
+        file.commit()?;
+        new_paths.push(path);
+    }
+
+    // old regions only go once every chunk is safely in a new one
+    for path in old_paths.iter().filter(|path| !new_paths.contains(path)) {
+        storage.remove(path)?;
+    }
+
+    Ok(())
+}
+
+/// Works out the heightmaps and light of upgraded chunks.
+///
+/// They're all put down in a scratch world together, so light crosses from
+/// one into the next just like it does in the game.
+fn light_up(mut chunks: Vec<Chunk>) -> (Vec<Chunk>, Vec<(ColumnCoordinate, Heightmap)>) {
+    let mut world = MacawWorld::default();
+
+    // going from the top down, each column's heights are settled before the
+    // chunks under them are lit
+    chunks.sort_by_key(|chunk| std::cmp::Reverse(chunk.coords().y));
+    for chunk in chunks {
+        let coords = chunk.coords();
+        world.set_chunk(chunk, coords);
+    }
+
+    let heightmaps = world
+        .loader
+        .heightmaps_ref()
+        .iter()
+        .map(|(column, heightmap)| (*column, heightmap.clone()))
+        .collect();
+    let chunks = world
+        .loader
+        .chunks_mut()
+        .drain()
+        .map(|(_, chunk)| chunk)
+        .collect();
+
+    (chunks, heightmaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path, sync::Arc};
+
+    use super::{migrate, v0, MIGRATIONS, SAVE_FORMAT_VERSION};
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::light::LightKind,
+            coordinates::{
+                ChunkBlockCoordinate, ChunkCoordinate, ColumnCoordinate, RegionCoordinate,
+            },
+            heightmap::HeightmapKind,
+            metadata::METADATA_FILE,
+            region::file::RegionFile,
+            storage::{MemoryStorage, Storage},
+        },
+    };
+
+    #[test]
+    fn migrations_cover_every_version() {
+        assert_eq!(MIGRATIONS.len() as u32, SAVE_FORMAT_VERSION);
+
+        for (version, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.from, version as u32);
+        }
+    }
+
+    /// A region blob, just like the game used to write them.
+    fn old_region(coordinates: v0::Coordinate, chunk: v0::Coordinate) -> anyhow::Result<Vec<u8>> {
+        let mut blocks = (0..16 * 16 * 16)
+            .map(|_| v0::Block {
+                block_type: v0::BlockType::Air,
+                state: 0,
+            })
+            .collect::<Vec<_>>();
+        blocks[3 * 256 + 2 * 16 + 1].block_type = v0::BlockType::Stone;
+
+        // a state that doesn't mean anything for stone
+        blocks[3 * 256 + 3 * 16 + 1] = v0::Block {
+            block_type: v0::BlockType::Stone,
+            state: 7,
+        };
+
+        let region = v0::Region {
+            metadata: v0::WorldMetadata {
+                name: "old_world".into(),
+                seed: 0,
+                generator: v0::GeneratorId {
+                    tld: "org".into(),
+                    organization: "shared".into(),
+                    name: "generator".into(),
+                    subname: Some("blank".into()),
+                },
+                creation_date: chrono::Utc::now(),
+            },
+            coordinates,
+            chunks: HashMap::from([(
+                chunk,
+                v0::Chunk {
+                    blocks,
+                    coords: chunk,
+                },
+            )]),
+            modification_date: chrono::Utc::now(),
+        };
+
+        Ok(bincode::serialize(&region)?)
+    }
+
+    #[test]
+    fn unversioned_saves_are_upgraded() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let save = Path::new("saves/old_world");
+        storage.write(&save.join(METADATA_FILE), b"name = \"old_world\"\n")?;
+
+        // old regions were named after their first chunk, so the second one
+        // belongs in the new region at (1, 0, 0)
+        let origin = v0::Coordinate { x: 0, y: 0, z: 0 };
+        let over = v0::Coordinate { x: 33, y: 0, z: 0 };
+        let first = old_region(origin, origin)?;
+        storage.write(&save.join("0_0_0.region"), &first)?;
+        storage.write(
+            &save.join("32_0_0.region"),
+            &old_region(v0::Coordinate { x: 32, y: 0, z: 0 }, over)?,
+        )?;
+
+        let backup = migrate(&storage, save)?.expect("old saves should be migrated");
+        assert_eq!(storage.read(&backup.join("0_0_0.region"))?, Some(first));
+
+        let stone = Block::new(BlockType::STONE, 0);
+        let mut file = RegionFile::open(
+            storage.clone(),
+            &save.join("0_0_0.region"),
+            RegionCoordinate::ORIGIN,
+        )?;
+        let chunk = file
+            .read_chunk(ChunkCoordinate::ORIGIN)?
+            .expect("chunks should stay in their region");
+        assert_eq!(
+            chunk.block(&ChunkBlockCoordinate::new(1, 2, 3)),
+            Some(stone.clone())
+        );
+
+        // the nonsense state is reset instead of being carried over
+        assert_eq!(
+            chunk.block(&ChunkBlockCoordinate::new(1, 3, 3)),
+            Some(stone.clone())
+        );
+
+        // migrated chunks come with light and heightmaps
+        let heightmap = file
+            .read_heightmap(ColumnCoordinate::new(0, 0))?
+            .expect("columns should get heightmaps");
+        assert_eq!(heightmap.get(HeightmapKind::LightBlocking, 1, 3), Some(3));
+        assert_eq!(heightmap.get(HeightmapKind::LightBlocking, 0, 0), None);
+        assert_eq!(
+            chunk.light(LightKind::Sky, &ChunkBlockCoordinate::new(1, 4, 3)),
+            15
+        );
+        assert_eq!(
+            chunk.light(LightKind::Sky, &ChunkBlockCoordinate::new(1, 1, 3)),
+            14
+        );
+
+        let mut file = RegionFile::open(
+            storage.clone(),
+            &save.join("1_0_0.region"),
+            RegionCoordinate::new(1, 0, 0),
+        )?;
+        let moved = file
+            .read_chunk(ChunkCoordinate::new(33, 0, 0))?
+            .expect("chunks should move to their new region");
+        assert_eq!(
+            moved.block(&ChunkBlockCoordinate::new(1, 2, 3)),
+            Some(stone)
+        );
+        assert!(!storage.exists(&save.join("32_0_0.region"))?);
+
+        // only needs to happen once!
+        let metadata = String::from_utf8(storage.read(&save.join(METADATA_FILE))?.unwrap())?;
+        assert!(metadata.contains(&format!("format_version = {SAVE_FORMAT_VERSION}")));
+        assert_eq!(migrate(&storage, save)?, None);
+
+        Ok(())
+    }
+}
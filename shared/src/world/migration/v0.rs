@@ -0,0 +1,161 @@
+//! # Version 0
+//!
+//! The save format from before saves were versioned, frozen in place.
+//!
+//! Back then, each region was stored as one `bincode` blob: the world's
+//! metadata, the region's coordinates, every chunk in it, and when it was
+//! last changed. Chunks were a plain list of blocks, and block types were an
+//! enum. These types must never change, or old saves can't be read anymore!
+
+use std::collections::HashMap;
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block,
+    world::{
+        chunk,
+        coordinates::{ChunkBlockCoordinate, ChunkCoordinate},
+    },
+};
+
+/// The number of blocks in every chunk.
+const CHUNK_VOLUME: usize = 16 * 16 * 16;
+
+/// A whole region, as it was stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Region {
+    /// The world's metadata. Every region had a copy.
+    pub metadata: WorldMetadata,
+    /// The region's coordinates.
+    pub coordinates: Coordinate,
+    /// Every chunk in the region.
+    pub chunks: HashMap<Coordinate, Chunk>,
+    /// When the region was last changed.
+    pub modification_date: DateTime<chrono::Utc>,
+}
+
+/// A world's metadata, as it was stored in each region.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct WorldMetadata {
+    pub name: String,
+    pub seed: u64,
+    pub generator: GeneratorId,
+    pub creation_date: DateTime<chrono::Utc>,
+}
+
+/// A generator's id, as it was stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct GeneratorId {
+    pub tld: String,
+    pub organization: String,
+    pub name: String,
+    pub subname: Option<String>,
+}
+
+/// A coordinate, used for both chunks and regions.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Coordinate {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+/// A chunk, as it was stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Chunk {
+    /// Every block in the chunk, indexed by `z * 256 + y * 16 + x`.
+    pub blocks: Vec<Block>,
+    /// The chunk's coordinates.
+    pub coords: Coordinate,
+}
+
+/// A block, as it was stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Block {
+    pub block_type: BlockType,
+    /// Nothing used this back then, but it's carried over as long as it
+    /// still makes sense for the block's type.
+    pub state: u32,
+}
+
+/// The kinds of blocks there were, before the block registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum BlockType {
+    Air,
+    Water,
+    Stone,
+    Log,
+    Dirt,
+    Grass,
+    Sand,
+    Sandstone,
+    Ice,
+    Leaves,
+}
+
+impl BlockType {
+    /// The registry's block type for this one.
+    pub fn upgrade(self) -> block::BlockType {
+        match self {
+            BlockType::Air => block::BlockType::AIR,
+            BlockType::Water => block::BlockType::WATER,
+            BlockType::Stone => block::BlockType::STONE,
+            BlockType::Log => block::BlockType::LOG,
+            BlockType::Dirt => block::BlockType::DIRT,
+            BlockType::Grass => block::BlockType::GRASS,
+            BlockType::Sand => block::BlockType::SAND,
+            BlockType::Sandstone => block::BlockType::SANDSTONE,
+            BlockType::Ice => block::BlockType::ICE,
+            BlockType::Leaves => block::BlockType::LEAVES,
+        }
+    }
+}
+
+impl Chunk {
+    /// Rebuilds this chunk in the current format.
+    ///
+    /// Fails if it doesn't have exactly one block for every position. States
+    /// that don't decode for their block's type anymore are reset to its
+    /// default state, with a warning.
+    pub fn upgrade(self) -> Result<chunk::Chunk, String> {
+        let Coordinate { x, y, z } = self.coords;
+        let coords = ChunkCoordinate::new(x, y, z);
+
+        if self.blocks.len() != CHUNK_VOLUME {
+            return Err(format!(
+                "chunk at {coords} has {} blocks instead of {CHUNK_VOLUME}",
+                self.blocks.len()
+            ));
+        }
+
+        let mut chunk = chunk::Chunk::new(coords);
+        let mut reset = 0;
+        for (index, block) in self.blocks.into_iter().enumerate() {
+            if block.block_type == BlockType::Air && block.state == 0 {
+                continue;
+            }
+
+            let mut upgraded = block::Block::new(block.block_type.upgrade(), block.state);
+            if upgraded.state().is_err() {
+                upgraded = block::Block::new(upgraded.block_type, 0);
+                reset += 1;
+            }
+
+            let (x, y, z) = (index % 16, index / 16 % 16, index / 256);
+            chunk.set_block(
+                upgraded,
+                ChunkBlockCoordinate::new(x as u8, y as u8, z as u8),
+            );
+        }
+
+        if reset > 0 {
+            tracing::warn!(
+                "Reset {reset} block states in chunk at {coords} that don't fit their block types"
+            );
+        }
+
+        Ok(chunk)
+    }
+}
@@ -6,12 +6,21 @@ use std::{
 use bevy::utils::Uuid;
 
 use self::{
-    coordinates::{BoundingBox, GlobalCoordinate},
+    coordinates::{BoundingBox, ChunkCoordinate, GlobalCoordinate},
     error::WorldError,
-    generation::{generators::blank::BlankGenerator, Generator},
-    loader::{WorldLoader, WorldLoadingError},
+    generation::{
+        generators::{self, blank::BlankGenerator},
+        Generator, GeneratorWrapper,
+    },
+    loader::{
+        ticket::{Ticket, TicketKind, TicketLifetime, SPAWN_TICKET_LEVEL},
+        WorldLoader, WorldLoadingError,
+    },
     metadata::WorldMetadata,
-    save::WorldSave,
+    player::PlayerData,
+    save::{SaveFiles, WorldSave},
+    state::WorldState,
+    storage::{MemoryStorage, Storage},
 };
 
 use super::block::Block;
@@ -19,13 +28,24 @@ use crate::{block::BlockSide, world::chunk::Chunk};
 
 pub mod chunk;
 pub mod coordinates;
+pub mod edit;
 pub mod error;
 pub mod generation;
+pub mod heightmap;
+pub mod light;
 pub mod loader;
+pub mod mcregion;
 pub mod meshing;
 pub mod metadata;
+pub mod migration;
+pub mod nbt;
+pub mod player;
 pub mod region;
 pub mod save;
+pub mod schematic;
+pub mod state;
+pub mod storage;
+pub mod streaming;
 
 /// A representation of a game world. Holds game state and loaded chunks/entities.
 #[derive(Debug)]
@@ -37,8 +57,13 @@ pub struct MacawWorld {
     loader: WorldLoader,
     /// The entities currently inhabiting this world.
     entities: HashSet<()>,
-    /// Spawn location (for players).
-    spawn_location: GlobalCoordinate,
+    /// The time, weather, spawn location, and so on.
+    state: WorldState,
+    /// Records of the players who've joined since the world was loaded.
+    players: HashMap<Uuid, PlayerData>,
+    /// Makes the chunks that are missing from disk. Worlds made by unknown
+    /// generators don't have one.
+    generator: Option<GeneratorWrapper>,
 }
 
 impl MacawWorld {
@@ -52,48 +77,195 @@ impl MacawWorld {
         self.metadata.clone()
     }
 
+    /// The storage this world is saved in.
+    pub fn storage(&self) -> Storage {
+        self.loader.storage()
+    }
+
+    /// Where new players first appear.
+    pub fn spawn_location(&self) -> GlobalCoordinate {
+        self.state.spawn_location
+    }
+
+    /// The time, weather, spawn location, and so on.
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    /// The time, weather, spawn location, and so on, in a mutable form.
+    pub fn state_mut(&mut self) -> &mut WorldState {
+        &mut self.state
+    }
+
     /// Saves the world, like I did when I was born.
     pub fn save(&mut self) -> Result<(), WorldLoadingError> {
-        self.metadata.write_to_disk()?;
+        let files = self.save_files();
         self.loader.write_chunks()?;
-        // TODO: write mobs/other world factors..?
+        files.write(self.storage().as_ref())
+    }
 
-        Ok(())
+    /// Copies the world's metadata, state and players' records, ready to be
+    /// saved.
+    fn save_files(&mut self) -> SaveFiles {
+        self.state.last_played = chrono::Utc::now();
+
+        SaveFiles {
+            metadata: self.metadata.clone(),
+            state: self.state.clone(),
+            players: self.players.values().cloned().collect(),
+        }
+    }
+
+    /// Saves the world without waiting for anything to be written.
+    ///
+    /// Changed chunks, the world's state and its players' records are all
+    /// copied, then written in the background, so this is cheap enough to
+    /// call while the game is running. Use `poll()` to pick up the results.
+    pub fn save_async(&mut self) {
+        let files = self.save_files();
+        self.loader.write_async(files);
     }
 
-    /// Loads a world from disk.
-    pub fn load(metadata: Arc<WorldMetadata>) -> Result<Self, WorldLoadingError> {
-        let save = WorldSave::new(metadata.clone())?;
+    /// Loads a world from storage.
+    pub fn load(metadata: Arc<WorldMetadata>, storage: Storage) -> Result<Self, WorldLoadingError> {
+        let save = WorldSave::new(metadata.clone(), storage.clone())?;
+
+        // worlds from before state was kept start over at the origin
+        let state = WorldState::read(storage.as_ref(), &metadata.save_path())?.unwrap_or_default();
 
         // TODO: remove hardcoded bounding box when the player can actually generate things
         let loader = WorldLoader::new_with_save(
             save,
             BoundingBox::new(
-                GlobalCoordinate::new(-16, -16, -16),
-                GlobalCoordinate::new(16, 16, 16),
+                ChunkCoordinate::new(-16, -16, -16),
+                ChunkCoordinate::new(16, 16, 16),
             ),
         )?;
 
         Ok(MacawWorld {
+            generator: Self::generator_for(&metadata),
+            metadata,
+            loader,
+            entities: HashSet::new(),
+            state,
+            players: HashMap::new(),
+        })
+    }
+
+    /// Opens a world from storage without loading any chunks.
+    ///
+    /// The world starts out empty. Ask for chunks with `stream_around()`,
+    /// and they show up as `poll()` picks them up.
+    pub fn load_async(
+        metadata: Arc<WorldMetadata>,
+        storage: Storage,
+    ) -> Result<Self, WorldLoadingError> {
+        let state = WorldState::read(storage.as_ref(), &metadata.save_path())?.unwrap_or_default();
+
+        // the spawn area stays loaded, even with nobody around
+        let mut loader = WorldLoader::new(metadata.clone(), storage)?;
+        loader.add_ticket(
+            state.spawn_location.chunk(),
+            Ticket::new(
+                TicketKind::Spawn,
+                SPAWN_TICKET_LEVEL,
+                TicketLifetime::UntilRemoved,
+            ),
+        );
+
+        Ok(MacawWorld {
+            generator: Self::generator_for(&metadata),
             metadata,
             loader,
             entities: HashSet::new(),
-            spawn_location: GlobalCoordinate::ORIGIN,
+            state,
+            players: HashMap::new(),
         })
     }
 
+    /// Finds the generator a world was made with.
+    fn generator_for(metadata: &WorldMetadata) -> Option<GeneratorWrapper> {
+        let generator = generators::resolve(metadata.generator(), metadata.seed());
+        if generator.is_none() {
+            tracing::warn!(
+                "World `{}` was made by `{}`, which can't generate chunks on demand. Missing chunks won't be generated.",
+                metadata.name(),
+                metadata.generator()
+            );
+        }
+
+        generator
+    }
+
+    /// Advances the world by one game tick.
+    pub fn tick(&mut self) {
+        self.state.time += 1;
+        self.loader.tick_tickets();
+    }
+
+    /// Picks up chunks loaded and saves finished in the background since
+    /// the last poll.
+    ///
+    /// Gives back the coordinates of the chunks that were loaded.
+    pub fn poll(&mut self) -> Vec<ChunkCoordinate> {
+        let arrived = self.loader.poll();
+        for coords in arrived.iter() {
+            self.update_chunk_heights(*coords);
+            self.light_chunk_borders(*coords);
+        }
+
+        arrived
+    }
+
+    /// Brings a player into the world, giving back their record.
+    ///
+    /// Players who've been here before pick up where they left off. Anyone
+    /// else starts fresh at the world's spawn location.
+    pub fn join(&mut self, uuid: Uuid, username: String) -> Result<PlayerData, WorldLoadingError> {
+        // players rejoining this session haven't been saved yet
+        let known = match self.players.get(&uuid) {
+            Some(player) => Some(player.clone()),
+            None => PlayerData::read(self.storage().as_ref(), &self.metadata.save_path(), uuid)?,
+        };
+
+        let player = match known {
+            Some(player) => PlayerData { username, ..player },
+            None => PlayerData::new(uuid, username, self.state.spawn_location),
+        };
+
+        self.players.insert(uuid, player.clone());
+        Ok(player)
+    }
+
+    /// Gets the record of a player who's joined the world.
+    pub fn player(&self, uuid: Uuid) -> Option<&PlayerData> {
+        self.players.get(&uuid)
+    }
+
+    /// Replaces a player's record, so it's written on the next save.
+    pub fn update_player(&mut self, player: PlayerData) {
+        self.players.insert(player.uuid, player);
+    }
+
+    /// Saves a player's record and takes them out of the world.
+    pub fn leave(&mut self, uuid: Uuid) -> Result<(), WorldLoadingError> {
+        if let Some(player) = self.players.remove(&uuid) {
+            player.write(self.storage().as_ref(), &self.metadata.save_path())?;
+        }
+
+        Ok(())
+    }
+
     /// When given a coordinate, this method will return a mutable chunk
     /// if that chunk is currently loaded in the world.
-    ///
-    /// The `coords` are for a chunk, not a block.
-    pub fn chunk(&mut self, coords: GlobalCoordinate) -> Option<&mut Chunk> {
+    pub fn chunk(&mut self, coords: ChunkCoordinate) -> Option<&mut Chunk> {
         self.loader.chunks_mut().get_mut(&coords)
     }
 
     /// Tries to place down a chunk at a given location.
     ///
     /// This can fail if there's already a chunk there. Try using `set_chunk()` instead.
-    pub fn push_chunk(&mut self, chunk: Chunk, coords: GlobalCoordinate) -> Result<(), WorldError> {
+    pub fn push_chunk(&mut self, chunk: Chunk, coords: ChunkCoordinate) -> Result<(), WorldError> {
         // early return if we already have those coords stored
         if self.loader.chunks_mut().contains_key(&coords) {
             return Err(WorldError::ChunkAlreadyExists(coords));
@@ -104,42 +276,46 @@ impl MacawWorld {
     }
 
     /// Puts down a chunk at `coords`. This will overwrite anything currently there - be careful!
-    pub fn set_chunk(&mut self, chunk: Chunk, coords: GlobalCoordinate) {
+    ///
+    /// The chunk is lit up along with its surroundings, and its column's
+    /// heightmaps are updated.
+    pub fn set_chunk(&mut self, chunk: Chunk, coords: ChunkCoordinate) {
         self.loader.chunks_mut().insert(coords, chunk);
+        self.update_chunk_heights(coords);
+        self.light_chunk(coords);
     }
 
     /// Given a block's global coordinates, this will find the chunk it's
     /// located in.
     ///
     /// ```
-    /// # use macaw::{block::Block, world::{chunk::Chunk, coordinates::GlobalCoordinate, MacawWorld}};
+    /// # use shared::world::{coordinates::{ChunkCoordinate, GlobalCoordinate}, generation::generators::fixed::Generate};
     /// #
-    /// let mut world = MacawWorld::generate();
+    /// let mut world = Generate::one_test_block();
     /// let chunk = world.chunk_from_block_coords(GlobalCoordinate::new(0, 0, 0)).unwrap();
     ///
-    /// assert_eq!(chunk.coords(), GlobalCoordinate::new(0, 0, 0));
+    /// assert_eq!(chunk.coords(), ChunkCoordinate::new(0, 0, 0));
     /// ```
     pub fn chunk_from_block_coords(&mut self, coords: GlobalCoordinate) -> Option<&mut Chunk> {
-        let chunk_coords = GlobalCoordinate {
-            x: coords.x / 16,
-            y: coords.y / 16,
-            z: coords.z / 16,
-        };
-
-        self.chunk(chunk_coords)
+        self.chunk(coords.chunk())
     }
 
     /// Returns a reference to the internal chunks hashmap.
-    pub fn chunks(&self) -> &HashMap<GlobalCoordinate, Chunk> {
+    pub fn chunks(&self) -> &HashMap<ChunkCoordinate, Chunk> {
         self.loader.chunks_ref()
     }
 
     /// Given global coordinates, returns a block if there's one present.
-    pub fn block_from_coords(&mut self, coords: GlobalCoordinate) -> Option<&mut Block> {
+    pub fn block_from_coords(&mut self, coords: GlobalCoordinate) -> Option<Block> {
         let chunk = self.chunk_from_block_coords(coords)?;
         chunk.block_from_global_coords(coords)
     }
 
+    /// Gets a loaded block without needing mutable access to the world.
+    pub(crate) fn loaded_block(&self, coords: GlobalCoordinate) -> Option<Block> {
+        self.chunks().get(&coords.chunk())?.block(&coords.local())
+    }
+
     /// Checks if a block at the given coordinates has exposed sides. If it does,
     /// returns the sides.
     pub fn block_exposed_sides(&mut self, coords: GlobalCoordinate) -> Vec<BlockSide> {
@@ -178,7 +354,7 @@ impl MacawWorld {
             let (nx, ny, nz) = (coords.x + mx, coords.y + my, coords.z + mz);
 
             let neighbor = self.block_from_coords(GlobalCoordinate::new(nx, ny, nz));
-            blocks.push((block_side, neighbor.cloned()));
+            blocks.push((block_side, neighbor));
         }
 
         blocks
@@ -191,32 +367,63 @@ impl MacawWorld {
             BlankGenerator.id(),
         ));
 
-        let loader = WorldLoader::new(metadata.clone()).unwrap();
+        // throwaway worlds don't need to touch the disk
+        let loader = WorldLoader::new(metadata.clone(), Arc::new(MemoryStorage::new())).unwrap();
 
         MacawWorld {
+            generator: Self::generator_for(&metadata),
             metadata,
             loader,
             entities: HashSet::new(),
-            spawn_location: GlobalCoordinate::ORIGIN,
+            state: WorldState::default(),
+            players: HashMap::new(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::world::generation::{generators::default::DefaultGenerator, Generator};
-    use bevy::tasks::block_on;
+    use super::MacawWorld;
+    use crate::world::{
+        chunk::Chunk,
+        coordinates::{ChunkCoordinate, GlobalCoordinate},
+    };
 
     #[test]
     fn chunk_from_block_coords() -> anyhow::Result<()> {
-        use crate::world::coordinates::GlobalCoordinate;
+        let mut world = MacawWorld::default();
+        for coords in [
+            ChunkCoordinate::new(2, 1, 0),
+            ChunkCoordinate::new(-1, 0, -1),
+        ] {
+            world.set_chunk(Chunk::new(coords), coords);
+        }
 
-        let mut world = block_on(DefaultGenerator::new(0).pre_generate(0));
         let chunk = world
             .chunk_from_block_coords(GlobalCoordinate::new(47, 16, 15))
             .unwrap();
+        assert_eq!(chunk.coords(), ChunkCoordinate::new(2, 1, 0));
+
+        // negative blocks belong to negative chunks
+        let chunk = world
+            .chunk_from_block_coords(GlobalCoordinate::new(-1, 0, -16))
+            .unwrap();
+        assert_eq!(chunk.coords(), ChunkCoordinate::new(-1, 0, -1));
 
-        assert_eq!(chunk.coords(), GlobalCoordinate::new(3, 1, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn worlds_load_back_from_storage() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        let coords = ChunkCoordinate::new(1, 0, 0);
+        world.set_chunk(Chunk::new(coords), coords);
+        world.save()?;
+
+        // the same storage gives back the same world
+        let loaded = MacawWorld::load(world.metadata(), world.storage())?;
+        assert_eq!(loaded.metadata(), world.metadata());
+        assert!(loaded.chunks().contains_key(&coords));
 
         Ok(())
     }
@@ -0,0 +1,482 @@
+//! # NBT
+//!
+//! Minecraft's 'Named Binary Tag' format, used by older worlds and tools.
+//!
+//! Every NBT file is a single named compound tag, which can hold any number
+//! of other tags. Numbers are big-endian, and strings are prefixed with their
+//! length. Files are usually compressed with gzip or zlib.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read as _, Write as _},
+};
+
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use thiserror::Error;
+
+/// How deep tags can be nested before a file is considered broken.
+const MAX_DEPTH: usize = 512;
+
+/// A single NBT tag.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+}
+
+impl Tag {
+    /// The numeric id of this tag's type.
+    pub const fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+        }
+    }
+
+    /// The name of this tag's type, for error messages.
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Tag::Byte(_) => "byte",
+            Tag::Short(_) => "short",
+            Tag::Int(_) => "int",
+            Tag::Long(_) => "long",
+            Tag::Float(_) => "float",
+            Tag::Double(_) => "double",
+            Tag::ByteArray(_) => "byte array",
+            Tag::String(_) => "string",
+            Tag::List(_) => "list",
+            Tag::Compound(_) => "compound",
+            Tag::IntArray(_) => "int array",
+        }
+    }
+}
+
+/// A compound tag: a set of named tags.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound(BTreeMap<String, Tag>);
+
+impl Compound {
+    /// Creates an empty compound.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets a tag by name.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.0.get(name)
+    }
+
+    /// Adds a tag, replacing any other tag with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, tag: Tag) {
+        self.0.insert(name.into(), tag);
+    }
+
+    /// Adds a tag, giving back the compound. Handy for building files.
+    pub fn with(mut self, name: impl Into<String>, tag: Tag) -> Self {
+        self.insert(name, tag);
+        self
+    }
+
+    /// Iterates over every tag in the compound, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Tag)> {
+        self.0.iter()
+    }
+
+    pub fn byte(&self, name: &str) -> Result<i8, NbtError> {
+        match self.expect(name)? {
+            Tag::Byte(v) => Ok(*v),
+            other => Err(NbtError::wrong_type(name, "byte", other)),
+        }
+    }
+
+    pub fn short(&self, name: &str) -> Result<i16, NbtError> {
+        match self.expect(name)? {
+            Tag::Short(v) => Ok(*v),
+            other => Err(NbtError::wrong_type(name, "short", other)),
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Result<i32, NbtError> {
+        match self.expect(name)? {
+            Tag::Int(v) => Ok(*v),
+            other => Err(NbtError::wrong_type(name, "int", other)),
+        }
+    }
+
+    pub fn long(&self, name: &str) -> Result<i64, NbtError> {
+        match self.expect(name)? {
+            Tag::Long(v) => Ok(*v),
+            other => Err(NbtError::wrong_type(name, "long", other)),
+        }
+    }
+
+    pub fn byte_array(&self, name: &str) -> Result<&[u8], NbtError> {
+        match self.expect(name)? {
+            Tag::ByteArray(v) => Ok(v),
+            other => Err(NbtError::wrong_type(name, "byte array", other)),
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Result<&str, NbtError> {
+        match self.expect(name)? {
+            Tag::String(v) => Ok(v),
+            other => Err(NbtError::wrong_type(name, "string", other)),
+        }
+    }
+
+    pub fn list(&self, name: &str) -> Result<&[Tag], NbtError> {
+        match self.expect(name)? {
+            Tag::List(v) => Ok(v),
+            other => Err(NbtError::wrong_type(name, "list", other)),
+        }
+    }
+
+    pub fn compound(&self, name: &str) -> Result<&Compound, NbtError> {
+        match self.expect(name)? {
+            Tag::Compound(v) => Ok(v),
+            other => Err(NbtError::wrong_type(name, "compound", other)),
+        }
+    }
+
+    fn expect(&self, name: &str) -> Result<&Tag, NbtError> {
+        self.get(name)
+            .ok_or_else(|| NbtError::MissingTag(name.to_owned()))
+    }
+}
+
+/// Reads an uncompressed NBT file, giving back the root compound's name and
+/// the compound itself.
+pub fn from_bytes(bytes: &[u8]) -> Result<(String, Compound), NbtError> {
+    let mut reader = Reader { bytes, position: 0 };
+
+    let id = reader.u8()?;
+    if id != 10 {
+        return Err(NbtError::RootNotCompound(id));
+    }
+
+    let name = reader.string()?;
+    match reader.payload(id, 0)? {
+        Tag::Compound(root) => Ok((name, root)),
+        _ => unreachable!("compound ids always read compounds"),
+    }
+}
+
+/// Writes a root compound as an uncompressed NBT file.
+pub fn to_bytes(name: &str, root: &Compound) -> Result<Vec<u8>, NbtError> {
+    let mut out = vec![10];
+    write_string(&mut out, name)?;
+    write_compound(&mut out, root)?;
+    Ok(out)
+}
+
+/// Reads a gzipped NBT file, like `level.dat`.
+pub fn from_gzip(bytes: &[u8]) -> Result<(String, Compound), NbtError> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .map_err(|e| NbtError::DecompressionFailed(e.to_string()))?;
+
+    from_bytes(&data)
+}
+
+/// Writes a root compound as a gzipped NBT file.
+pub fn to_gzip(name: &str, root: &Compound) -> Result<Vec<u8>, NbtError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&to_bytes(name, root)?)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| NbtError::CompressionFailed(e.to_string()))
+}
+
+/// Reads a zlib-compressed NBT file, like a chunk in a region file.
+pub fn from_zlib(bytes: &[u8]) -> Result<(String, Compound), NbtError> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .map_err(|e| NbtError::DecompressionFailed(e.to_string()))?;
+
+    from_bytes(&data)
+}
+
+/// Writes a root compound as a zlib-compressed NBT file.
+pub fn to_zlib(name: &str, root: &Compound) -> Result<Vec<u8>, NbtError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&to_bytes(name, root)?)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| NbtError::CompressionFailed(e.to_string()))
+}
+
+/// Reads tags out of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], NbtError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(NbtError::UnexpectedEnd)?;
+
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NbtError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, NbtError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    /// Reads a length, which can't be negative.
+    fn length(&mut self) -> Result<usize, NbtError> {
+        let length = i32::from_be_bytes(self.array()?);
+        usize::try_from(length).map_err(|_| NbtError::NegativeLength(length))
+    }
+
+    fn string(&mut self) -> Result<String, NbtError> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Tag, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+
+        let tag = match id {
+            1 => Tag::Byte(i8::from_be_bytes(self.array()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let length = self.length()?;
+                Tag::ByteArray(self.take(length)?.to_vec())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element = self.u8()?;
+                let length = self.length()?;
+
+                // don't trust the length for allocations, it might be junk
+                let mut list = Vec::with_capacity(length.min(1024));
+                for _ in 0..length {
+                    list.push(self.payload(element, depth + 1)?);
+                }
+                Tag::List(list)
+            }
+            10 => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+
+                    let name = self.string()?;
+                    compound.insert(name, self.payload(id, depth + 1)?);
+                }
+                Tag::Compound(compound)
+            }
+            11 => {
+                let length = self.length()?;
+                let bytes = self.take(length.checked_mul(4).ok_or(NbtError::UnexpectedEnd)?)?;
+                Tag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                )
+            }
+            other => return Err(NbtError::UnknownTag(other)),
+        };
+
+        Ok(tag)
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) -> Result<(), NbtError> {
+    let length = u16::try_from(s.len()).map_err(|_| NbtError::TooLong(s.len()))?;
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn write_length(out: &mut Vec<u8>, length: usize) -> Result<(), NbtError> {
+    let length = i32::try_from(length).map_err(|_| NbtError::TooLong(length))?;
+    out.extend_from_slice(&length.to_be_bytes());
+    Ok(())
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) -> Result<(), NbtError> {
+    match tag {
+        Tag::Byte(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Short(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+        Tag::ByteArray(v) => {
+            write_length(out, v.len())?;
+            out.extend_from_slice(v);
+        }
+        Tag::String(v) => write_string(out, v)?,
+        Tag::List(list) => {
+            // empty lists are lists of 'end' tags
+            let element = list.first().map_or(0, |tag| tag.id());
+            if list.iter().any(|tag| tag.id() != element) {
+                return Err(NbtError::MixedList);
+            }
+
+            out.push(element);
+            write_length(out, list.len())?;
+            for tag in list {
+                write_payload(out, tag)?;
+            }
+        }
+        Tag::Compound(compound) => write_compound(out, compound)?,
+        Tag::IntArray(v) => {
+            write_length(out, v.len())?;
+            for int in v {
+                out.extend_from_slice(&int.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_compound(out: &mut Vec<u8>, compound: &Compound) -> Result<(), NbtError> {
+    for (name, tag) in compound.iter() {
+        out.push(tag.id());
+        write_string(out, name)?;
+        write_payload(out, tag)?;
+    }
+
+    // compounds end with an 'end' tag
+    out.push(0);
+    Ok(())
+}
+
+/// An error from reading or writing NBT.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum NbtError {
+    #[error("NBT data ended in the middle of a tag")]
+    UnexpectedEnd,
+    #[error("Unknown NBT tag type: `{0}`")]
+    UnknownTag(u8),
+    #[error("NBT files must start with a compound tag, but this one starts with type `{0}`")]
+    RootNotCompound(u8),
+    #[error("NBT data has a negative length: `{0}`")]
+    NegativeLength(i32),
+    #[error("NBT tags are nested too deeply")]
+    TooDeep,
+    #[error("Value is too long to fit in NBT: `{0}`")]
+    TooLong(usize),
+    #[error("NBT lists can only hold one type of tag")]
+    MixedList,
+    #[error("Missing NBT tag: `{0}`")]
+    MissingTag(String),
+    #[error("NBT tag `{name}` should be a {expected}, but it's a {found}")]
+    WrongType {
+        name: String,
+        expected: String,
+        found: String,
+    },
+    #[error("Failed to decompress NBT data: `{0}`")]
+    DecompressionFailed(String),
+    #[error("Failed to compress NBT data: `{0}`")]
+    CompressionFailed(String),
+}
+
+impl NbtError {
+    fn wrong_type(name: &str, expected: &str, found: &Tag) -> Self {
+        Self::WrongType {
+            name: name.to_owned(),
+            expected: expected.to_owned(),
+            found: found.type_name().to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes, from_gzip, to_bytes, to_gzip, Compound, NbtError, Tag};
+
+    #[test]
+    fn round_trip() -> anyhow::Result<()> {
+        let root = Compound::new()
+            .with("Byte", Tag::Byte(-3))
+            .with("Long", Tag::Long(1 << 40))
+            .with("Name", Tag::String("Beta 1.7.3".into()))
+            .with("Blocks", Tag::ByteArray(vec![1, 2, 3]))
+            .with(
+                "Pos",
+                Tag::List(vec![Tag::Double(0.5), Tag::Double(64.0), Tag::Double(-2.5)]),
+            )
+            .with("Empty", Tag::List(Vec::new()))
+            .with(
+                "Level",
+                Tag::Compound(Compound::new().with("xPos", Tag::Int(7))),
+            );
+
+        let bytes = to_gzip("", &root)?;
+        assert_eq!(from_gzip(&bytes)?, (String::new(), root.clone()));
+
+        // typed getters check what they find
+        assert_eq!(root.compound("Level")?.int("xPos")?, 7);
+        assert!(matches!(root.int("Long"), Err(NbtError::WrongType { .. })));
+        assert_eq!(
+            root.byte("Missing"),
+            Err(NbtError::MissingTag("Missing".into()))
+        );
+
+        // broken files are errors, not panics
+        let bytes = to_bytes("root", &root)?;
+        assert_eq!(
+            from_bytes(&bytes[..bytes.len() - 1]),
+            Err(NbtError::UnexpectedEnd)
+        );
+        assert_eq!(
+            to_bytes(
+                "",
+                &Compound::new().with("Mixed", Tag::List(vec![Tag::Byte(1), Tag::Int(1)]))
+            ),
+            Err(NbtError::MixedList)
+        );
+
+        Ok(())
+    }
+}
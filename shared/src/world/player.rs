@@ -0,0 +1,154 @@
+//! # Player
+//!
+//! What a world remembers about each player between sessions.
+//!
+//! Every player who's joined a world has a `playerdata/<uuid>` record in its
+//! save, holding where they were, which way they were looking, and what they
+//! were carrying. Records are written whenever the world is saved.
+
+use std::path::{Path, PathBuf};
+
+use bevy::utils::Uuid;
+use serde::{Deserialize, Serialize};
+
+use super::{coordinates::GlobalCoordinate, loader::WorldLoadingError, storage::StorageBackend};
+use crate::block::entity::Inventory;
+
+/// The folder in each save holding player records.
+pub const PLAYER_DATA_DIRECTORY: &str = "playerdata";
+
+/// How a player plays the game.
+#[derive(
+    Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize,
+)]
+pub enum GameMode {
+    /// Players can be hurt, and have to gather what they build with.
+    #[default]
+    Survival,
+    /// Players can fly, can't be hurt, and can build with anything.
+    Creative,
+}
+
+/// Everything saved about a player.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerData {
+    /// The player's unique id. Their record is named after it.
+    pub uuid: Uuid,
+    /// The name the player went by when they were last saved.
+    pub username: String,
+    /// Where the player is standing.
+    pub position: [f32; 3],
+    /// Which way the player is looking, as a quaternion.
+    pub rotation: [f32; 4],
+    pub game_mode: GameMode,
+    /// How many half-hearts the player has, up to `PlayerData::MAX_HEALTH`.
+    pub health: u8,
+    pub inventory: Inventory,
+}
+
+impl PlayerData {
+    /// The health of a player that hasn't been hurt at all.
+    pub const MAX_HEALTH: u8 = 20;
+
+    /// Creates the record of a player who's never been in the world before,
+    /// standing on the middle of the `spawn` block.
+    pub fn new(uuid: Uuid, username: String, spawn: GlobalCoordinate) -> Self {
+        Self {
+            uuid,
+            username,
+            position: [spawn.x as f32 + 0.5, spawn.y as f32, spawn.z as f32 + 0.5],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            game_mode: GameMode::default(),
+            health: Self::MAX_HEALTH,
+            inventory: Inventory::new(Inventory::PLAYER_SIZE),
+        }
+    }
+
+    /// The path of a player's record in the world saved at `save_path`.
+    pub fn path(save_path: &Path, uuid: Uuid) -> PathBuf {
+        save_path.join(PLAYER_DATA_DIRECTORY).join(uuid.to_string())
+    }
+
+    /// Reads a player's record from the world saved at `save_path`.
+    ///
+    /// Players who've never joined the world give back `None`.
+    pub fn read(
+        storage: &dyn StorageBackend,
+        save_path: &Path,
+        uuid: Uuid,
+    ) -> Result<Option<Self>, WorldLoadingError> {
+        let path = Self::path(save_path, uuid);
+        let Some(bytes) = storage.read(&path)? else {
+            return Ok(None);
+        };
+
+        bincode::deserialize(&bytes).map(Some).map_err(|e| {
+            WorldLoadingError::PlayerDataReadFailed(format!(
+                "failed to parse player record at `{}`: {e}",
+                path.to_string_lossy()
+            ))
+        })
+    }
+
+    /// Writes this record into the world saved at `save_path`.
+    pub fn write(
+        &self,
+        storage: &dyn StorageBackend,
+        save_path: &Path,
+    ) -> Result<(), WorldLoadingError> {
+        let bytes = bincode::serialize(self).map_err(|e| {
+            WorldLoadingError::PlayerDataWriteFailed(format!(
+                "failed to serialize player `{}`: {e}",
+                self.uuid
+            ))
+        })?;
+
+        storage.write(&Self::path(save_path, self.uuid), &bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Uuid;
+
+    use super::{GameMode, PlayerData};
+    use crate::{
+        block::entity::ItemStack,
+        world::{coordinates::GlobalCoordinate, MacawWorld},
+    };
+
+    #[test]
+    fn players_come_back_where_they_left() -> anyhow::Result<()> {
+        let uuid = Uuid::new_v4();
+        let mut world = MacawWorld::default();
+
+        // new players show up at spawn
+        let mut player = world.join(uuid, "barry".into())?;
+        assert_eq!(
+            player,
+            PlayerData::new(uuid, "barry".into(), GlobalCoordinate::ORIGIN)
+        );
+
+        player.position = [4.0, 70.5, -12.25];
+        player.rotation = [0.0, 0.6, 0.0, 0.8];
+        player.game_mode = GameMode::Creative;
+        player.health = 7;
+        player.inventory.set(
+            3,
+            Some(ItemStack {
+                item: 1,
+                count: 64,
+                damage: 0,
+            }),
+        );
+        world.update_player(player.clone());
+        world.save()?;
+
+        // everything is still there after loading the world again
+        let mut loaded = MacawWorld::load(world.metadata(), world.storage())?;
+        assert_eq!(loaded.join(uuid, "barry".into())?, player);
+
+        Ok(())
+    }
+}
@@ -3,170 +3,339 @@
 //! A collection of chunks in a 32x32x32 area. Inspired by [the wonderful `McRegion`
 //! format](https://tinyurl.com/mu3bfpkk)!
 
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use chrono::DateTime;
-use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use super::{chunk::Chunk, coordinates::GlobalCoordinate, metadata::WorldMetadata};
+use super::{
+    chunk::Chunk,
+    coordinates::{ChunkCoordinate, ColumnCoordinate, RegionCoordinate},
+    heightmap::Heightmap,
+    loader::WorldLoadingError,
+    metadata::WorldMetadata,
+    save::WorldSave,
+    storage::StorageError,
+};
+
+use self::file::RegionFile;
+
+pub mod compression;
+pub mod file;
+
+/// The folder in each save where corrupted data gets moved to.
+pub const CORRUPTED_DIRECTORY: &str = "corrupted";
 
 /// A 'region' of 32x32x32 surrounding a collection of chunks.
 /// Used to save these chunks to disk.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+///
+/// In storage, each region is a `RegionFile`, which can also read and write
+/// single chunks without loading the whole region.
+#[derive(Clone, Debug)]
 pub struct Region {
-    /// The world metadata.
-    metadata: Arc<WorldMetadata>,
+    /// The save this region belongs to.
+    save: WorldSave,
     /// The _region_ coordinate of this region. That's 32 times larger than
     /// chunks, and (16 * 32) times larger than blocks!
     ///
     /// Regions are named on disk according to their coordinates.
-    coordinates: GlobalCoordinate,
+    coordinates: RegionCoordinate,
     /// A list of chunks currently in this region. New chunks will be added.
     /// Empty chunks will be removed.
     ///
     /// Only chunks within the region's coordinates can be added.
-    chunks: HashMap<GlobalCoordinate, Chunk>,
+    chunks: HashMap<ChunkCoordinate, Chunk>,
+    /// Heightmaps for the chunk columns passing through this region.
+    ///
+    /// Columns can pass through many regions stacked on top of each other,
+    /// but only the region at `y = 0` stores their heightmaps.
+    heightmaps: HashMap<ColumnCoordinate, Heightmap>,
     /// The date/time when this region was last modified.
     /// Used to verify the world save.
     modification_date: DateTime<chrono::Utc>,
 }
 
 impl Region {
-    pub const CHUNKS_PER_REGION: u8 = 32;
+    pub const CHUNKS_PER_REGION: i64 = RegionCoordinate::CHUNKS_PER_REGION;
 
     /// Creates a new `Region`.
-    pub fn new(coordinates: GlobalCoordinate, metadata: Arc<WorldMetadata>) -> Self {
+    pub fn new(coordinates: RegionCoordinate, save: WorldSave) -> Self {
         Self {
-            metadata,
+            save,
             coordinates,
             chunks: HashMap::new(),
+            heightmaps: HashMap::new(),
             modification_date: chrono::Utc::now(),
         }
     }
 
-    /// Gets a region from disk, if it exists. Otherwise, it'll create a new one,
-    /// write it to disk, and return it.
-    pub fn get(
-        coordinates: GlobalCoordinate,
-        metadata: Arc<WorldMetadata>,
-    ) -> Result<Self, RegionError> {
-        if let Ok(region) = Self::load(coordinates, metadata.clone()) {
-            Ok(region)
-        } else {
-            // let's create one!
-            let region = Self::new(coordinates, metadata);
-            region.write()?;
-            Ok(region)
+    /// Gets a region from storage, if it exists. Otherwise, it'll create a
+    /// new one, write it to storage, and return it.
+    ///
+    /// Regions that exist but can't be loaded give back the error, leaving
+    /// what's stored alone.
+    pub fn get(coordinates: RegionCoordinate, save: WorldSave) -> Result<Self, RegionError> {
+        let path = Region::path_from_coordinates(&coordinates, save.metadata());
+        if save.storage().exists(&path)? {
+            return Self::load(coordinates, save);
         }
+
+        // let's create one!
+        let region = Self::new(coordinates, save);
+        region.write()?;
+        Ok(region)
     }
 
-    /// Loads a region from disk.
+    /// Loads a region from storage.
     ///
-    /// This can fail if the region isn't found on disk or is corrupted.
-    /// TODO: handle corruption!
-    pub fn load(
-        coordinates: GlobalCoordinate,
-        world_metadata: Arc<WorldMetadata>,
-    ) -> Result<Self, RegionError> {
+    /// This can fail if the region isn't found in storage or is corrupted.
+    /// Corrupted regions, along with any corrupted chunks in an otherwise
+    /// fine region, are quarantined. The rest of the region still loads.
+    /// Chunks that can't be read for any other reason, like coming from a
+    /// newer version of the game, fail the load instead.
+    pub fn load(coordinates: RegionCoordinate, save: WorldSave) -> Result<Self, RegionError> {
         // attempt to find region file
-        let path = Region::path_from_coordinates(&coordinates, world_metadata);
+        let path = Region::path_from_coordinates(&coordinates, save.metadata());
+        let storage = save.storage();
 
-        if !path.exists() {
+        if !storage.exists(&path)? {
             return Err(RegionError::RegionReadFailed(
                 path.to_string_lossy().to_string(),
             ));
         }
 
-        // read region into a buffer
-        let buf = std::fs::read(path.as_ref())
-            .map_err(|e| RegionError::RegionReadFailed(e.to_string()))?;
+        let mut file = match RegionFile::open(storage, &path, coordinates) {
+            Ok(file) => file,
+            Err(e) => {
+                if e.is_corruption() {
+                    let report = Self::quarantine(coordinates, &save, e.to_string());
+                    tracing::error!("{report}");
+                }
+                return Err(e);
+            }
+        };
+        let mut region = Self::new(coordinates, save.clone());
+
+        let mut corrupted = Vec::new();
+        for chunk_coordinates in file.chunks() {
+            match file.read_chunk(chunk_coordinates) {
+                Ok(Some(chunk)) => {
+                    region.chunks.insert(chunk_coordinates, chunk);
+                }
+                Ok(None) => (),
+                Err(e) if e.is_corruption() => corrupted.push((chunk_coordinates, e)),
+                Err(e) => return Err(e),
+            }
+        }
 
-        // deserialize that buffer into a region
-        let s: Region = bincode::deserialize(&buf).map_err(|e| {
-            RegionError::RegionWriteFailed(format!(
-                "failed to deserialize region from bincode: `{}`",
-                e
-            ))
-        })?;
+        for column in file.columns() {
+            match file.read_heightmap(column) {
+                Ok(Some(heightmap)) => {
+                    region.heightmaps.insert(column, heightmap);
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Skipping unreadable heightmap at {column}: {e}"),
+            }
+        }
+
+        for report in Self::quarantine_chunks(file, &save, corrupted) {
+            tracing::error!("{report}");
+        }
 
-        tracing::warn!("read region from disk: {:?}", &s.chunks.len());
-        tracing::error!("chunk: {:?}", &s.chunks.get(&GlobalCoordinate::ORIGIN));
+        tracing::debug!(
+            "read region from storage: {} ({} chunks)",
+            coordinates,
+            region.chunks.len()
+        );
 
-        Ok(s)
+        Ok(region)
     }
 
-    /// Writes this region to disk.
+    /// Writes this region to storage.
+    ///
+    /// Chunks already stored that aren't in this region are left alone. The
+    /// stored file is only replaced once everything has been written.
     pub fn write(&self) -> Result<(), RegionError> {
         tracing::debug!("Writing region at {:?}", self.coordinates());
 
-        // serialize this region into bincode
-        let s = bincode::serialize(&self)
-            .map_err(|e| RegionError::ChunkSerializationFailed(e.to_string()))?;
+        let mut file = self.open_file()?;
 
-        tracing::debug!("Serialized region to bincode: {:?}", self.coordinates());
+        for chunk in self.chunks.values() {
+            file.write_chunk(chunk)?;
+        }
 
-        // write to disk
-        let path = Region::path(self);
-        tracing::debug!("Writing region to disk: {:?}", &path);
+        for (column, heightmap) in self.heightmaps.iter() {
+            file.write_heightmap(*column, heightmap)?;
+        }
 
-        std::fs::write(path.as_ref(), s).map_err(|e| {
-            RegionError::RegionWriteFailed(format!(
-                "failed to actually write region to disk at `{}`: {}",
-                path.to_string_lossy(),
-                e
-            ))
-        })?;
+        file.commit()
+    }
 
-        Ok(())
+    /// Moves a region file that can't be read into the save's `corrupted`
+    /// folder, so a fresh one can take its place.
+    ///
+    /// Gives back a report of what happened.
+    pub fn quarantine(
+        coordinates: RegionCoordinate,
+        save: &WorldSave,
+        reason: String,
+    ) -> WorldLoadingError {
+        let path = Self::path_from_coordinates(&coordinates, save.metadata());
+        let (x, y, z) = coordinates.free();
+        let destination = Self::corrupted_path(save.metadata()).join(format!(
+            "{x}_{y}_{z}-{}.region",
+            chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S")
+        ));
+
+        let moved = save.storage().rename(&path, &destination);
+
+        WorldLoadingError::Quarantined {
+            what: format!("region at {coordinates}"),
+            moved_to: match moved {
+                Ok(()) => destination.to_string_lossy().to_string(),
+                Err(e) => format!("nowhere, since moving it failed: {e}"),
+            },
+            reason,
+        }
+    }
+
+    /// Copies corrupted chunks from a region file into the save's
+    /// `corrupted` folder, then removes them from the region.
+    ///
+    /// Gives back a report for each chunk.
+    pub fn quarantine_chunks(
+        mut file: RegionFile,
+        save: &WorldSave,
+        chunks: Vec<(ChunkCoordinate, RegionError)>,
+    ) -> Vec<WorldLoadingError> {
+        if chunks.is_empty() {
+            return Vec::new();
+        }
+
+        let coordinates = file.coordinates();
+        let storage = save.storage();
+        let folder = Self::corrupted_path(save.metadata());
+        let timestamp = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S");
+
+        let mut copied = Vec::new();
+        let mut reports = Vec::new();
+        for (chunk, reason) in chunks {
+            let (x, y, z) = (chunk.x, chunk.y, chunk.z);
+            let destination = folder.join(format!("{x}_{y}_{z}-{timestamp}.chunk"));
+
+            // only chunks that made it out safely are removed
+            let moved = file
+                .read_raw_chunk(chunk)
+                .and_then(|raw| Ok(storage.write(&destination, &raw.unwrap_or_default())?));
+
+            reports.push(WorldLoadingError::Quarantined {
+                what: format!("chunk at {chunk}"),
+                moved_to: match moved {
+                    Ok(()) => {
+                        copied.push(chunk);
+                        destination.to_string_lossy().to_string()
+                    }
+                    Err(e) => format!("nowhere, since copying it failed: {e}"),
+                },
+                reason: reason.to_string(),
+            });
+        }
+
+        let removed = copied
+            .into_iter()
+            .try_for_each(|chunk| file.remove_chunk(chunk))
+            .and_then(|_| file.commit());
+
+        if let Err(e) = removed {
+            tracing::error!("Failed to remove corrupted chunks from region at {coordinates}: {e}");
+        }
+
+        reports
+    }
+
+    /// The folder where a save's corrupted data goes, relative to the root of
+    /// its storage.
+    pub fn corrupted_path(world_metadata: Arc<WorldMetadata>) -> PathBuf {
+        world_metadata.save_path().join(CORRUPTED_DIRECTORY)
+    }
+
+    /// Opens this region's file in storage, creating it if it doesn't exist
+    /// yet.
+    pub fn open_file(&self) -> Result<RegionFile, RegionError> {
+        RegionFile::open(self.save.storage(), &self.path(), self.coordinates)
     }
 
     /// This region's filename.
-    pub fn filename(coordinates: &GlobalCoordinate) -> String {
+    pub fn filename(coordinates: &RegionCoordinate) -> String {
         let (x, y, z) = coordinates.free();
         format!("{}_{}_{}.region", x, y, z)
     }
 
-    /// Gets this region's path on disk, even if that path doesn't exist yet.
+    /// Reads a region's coordinates from its filename, like `1_-2_3.region`.
+    pub fn coordinates_from_filename(path: &Path) -> Option<RegionCoordinate> {
+        let stem = path.file_name()?.to_str()?.strip_suffix(".region")?;
+        let mut parts = stem.split('_').map(|part| part.parse::<i64>().ok());
+
+        let (x, y, z) = (parts.next()??, parts.next()??, parts.next()??);
+        parts
+            .next()
+            .is_none()
+            .then_some(RegionCoordinate::new(x, y, z))
+    }
+
+    /// Gets this region's path in storage, even if that path doesn't exist
+    /// yet.
     pub fn path(&self) -> Arc<PathBuf> {
-        Self::path_from_coordinates(&self.coordinates, self.metadata.clone())
+        Self::path_from_coordinates(&self.coordinates, self.save.metadata())
     }
 
-    /// Gets a region's path on disk from its coordinates.
+    /// Gets a region's path in storage from its coordinates.
     pub fn path_from_coordinates(
-        coordinates: &GlobalCoordinate,
+        coordinates: &RegionCoordinate,
         world_metadata: Arc<WorldMetadata>,
     ) -> Arc<PathBuf> {
         let p = world_metadata.save_path().join(Self::filename(coordinates));
         Arc::new(p)
     }
 
+    /// When this region was last modified in memory.
+    pub fn modification_date(&self) -> DateTime<chrono::Utc> {
+        self.modification_date
+    }
+
     /// Returns a copy of this region's coordinates.
-    pub fn coordinates(&self) -> GlobalCoordinate {
+    pub fn coordinates(&self) -> RegionCoordinate {
         self.coordinates
     }
 
-    pub fn chunks(&self) -> &HashMap<GlobalCoordinate, Chunk> {
+    pub fn chunks(&self) -> &HashMap<ChunkCoordinate, Chunk> {
         &self.chunks
     }
 
+    pub fn heightmaps(&self) -> &HashMap<ColumnCoordinate, Heightmap> {
+        &self.heightmaps
+    }
+
     /// Given a chunk's coordinates, finds the appropriate region coordinates.
     ///
     /// hint: This allows you to find regions on disk using chunks... :3
-    pub fn find_region_coordinates(chunk_coordinates: GlobalCoordinate) -> GlobalCoordinate {
-        chunk_coordinates * 16
+    pub fn find_region_coordinates(chunk_coordinates: ChunkCoordinate) -> RegionCoordinate {
+        chunk_coordinates.region()
     }
 
     /// Finds the minimum allowed chunk coordinate in a region, given its
     /// coordinates.
-    pub fn minimum_chunk(rc: GlobalCoordinate) -> GlobalCoordinate {
-        rc * 32
+    pub fn minimum_chunk(rc: RegionCoordinate) -> ChunkCoordinate {
+        rc.min_chunk()
     }
 
     /// Finds the max. chunk coordinate in a region, given its coordinates.
-    pub fn maximum_chunk(rc: GlobalCoordinate) -> GlobalCoordinate {
-        // add minimum to be non-inclusive
-        (rc * 32) + (Region::CHUNKS_PER_REGION - 1).into()
+    pub fn maximum_chunk(rc: RegionCoordinate) -> ChunkCoordinate {
+        rc.max_chunk()
     }
 
     /// Tries to add a new chunk to the internal `chunks` list.
@@ -174,7 +343,7 @@ impl Region {
     /// This can fail if the chunk already exists or is out of bounds for this region.
     pub fn add_chunk(
         &mut self,
-        coordinates: GlobalCoordinate,
+        coordinates: ChunkCoordinate,
         chunk: Chunk,
     ) -> Result<(), RegionError> {
         // check if chunk is out-of-bounds
@@ -194,17 +363,27 @@ impl Region {
         Ok(())
     }
 
-    /// Checks to see if this `Region` should hold the given chunk.
-    pub fn can_contain_chunk(&self, cc: GlobalCoordinate) -> bool {
-        let min = self.coordinates() * 32;
-        let max = min + 31;
+    /// Adds or replaces the heightmap of a column stored in this region.
+    pub fn add_heightmap(
+        &mut self,
+        column: ColumnCoordinate,
+        heightmap: Heightmap,
+    ) -> Result<(), RegionError> {
+        if column.region() != self.coordinates {
+            return Err(RegionError::WrongColumn {
+                column,
+                region: self.coordinates(),
+            });
+        }
+
+        self.heightmaps.insert(column, heightmap);
+        self.modify();
+        Ok(())
+    }
 
-        cc.x >= min.x
-            && cc.x <= max.x
-            && cc.y >= min.y
-            && cc.y <= max.y
-            && cc.z >= min.z
-            && cc.z <= max.z
+    /// Checks to see if this `Region` should hold the given chunk.
+    pub fn can_contain_chunk(&self, cc: ChunkCoordinate) -> bool {
+        self.coordinates.contains(cc)
     }
 
     /// Changes the modification date of this region to the current time.
@@ -219,8 +398,13 @@ impl Region {
 pub enum RegionError {
     #[error("Chunk at `{chunk}` is out of bounds for region at {region}")]
     WrongRegion {
-        chunk: GlobalCoordinate,
-        region: GlobalCoordinate,
+        chunk: ChunkCoordinate,
+        region: RegionCoordinate,
+    },
+    #[error("Column at `{column}` isn't stored in region at {region}")]
+    WrongColumn {
+        column: ColumnCoordinate,
+        region: RegionCoordinate,
     },
     #[error("Failed to serialize chunks to `bincode`: `{0}`")]
     ChunkSerializationFailed(String),
@@ -228,4 +412,34 @@ pub enum RegionError {
     RegionWriteFailed(String),
     #[error("Failed to read region from disk: `{0}`")]
     RegionReadFailed(String),
+    #[error("Chunk data is too large to fit in a region: `{0}` bytes")]
+    ChunkTooLarge(usize),
+    #[error("Region data doesn't start with a known magic number: `{0:?}`")]
+    BadMagic([u8; 4]),
+    #[error("Region data has format version `{found}`, but only versions up to `{supported}` are supported")]
+    UnsupportedVersion { found: u16, supported: u16 },
+    #[error("Region data uses an unknown compression id: `{0}`")]
+    UnknownCompression(u8),
+    #[error("Chunk data doesn't match its checksum (expected `{expected:#010x}`, found `{found:#010x}`)")]
+    ChecksumMismatch { expected: u32, found: u32 },
+    #[error("Region data is damaged: `{0}`")]
+    Damaged(String),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+impl RegionError {
+    /// Whether this error means the stored data itself is broken, so it's
+    /// worth quarantining.
+    ///
+    /// Data from newer versions of the game and storage that can't be read
+    /// right now are fine, so they're left where they are.
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            RegionError::BadMagic(_)
+                | RegionError::ChecksumMismatch { .. }
+                | RegionError::Damaged(_)
+        )
+    }
 }
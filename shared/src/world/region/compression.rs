@@ -0,0 +1,92 @@
+//! # Compression
+//!
+//! The ways an entry in a region file can be compressed.
+//!
+//! Each entry records the id of its compression, so files can hold a mix of
+//! them, and switching the default never breaks older entries.
+
+use std::io::{Read, Write};
+
+use super::RegionError;
+
+/// A compression method for region file entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub enum Compression {
+    /// Stored as-is.
+    None,
+    /// Compressed with DEFLATE. Chunks full of the same few blocks shrink a
+    /// lot with this!
+    #[default]
+    Deflate,
+}
+
+impl Compression {
+    /// The id stored on disk for this compression.
+    pub const fn id(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    /// Finds the compression with the given id.
+    pub fn from_id(id: u8) -> Result<Self, RegionError> {
+        match id {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            other => Err(RegionError::UnknownCompression(other)),
+        }
+    }
+
+    /// Compresses some data.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, RegionError> {
+        let failed = |e: std::io::Error| {
+            RegionError::ChunkSerializationFailed(format!("failed to compress: `{e}`"))
+        };
+
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).map_err(failed)?;
+                encoder.finish().map_err(failed)
+            }
+        }
+    }
+
+    /// Decompresses data that was compressed this way.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, RegionError> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Deflate => {
+                let mut out = Vec::new();
+                flate2::read::DeflateDecoder::new(data)
+                    .read_to_end(&mut out)
+                    .map_err(|e| {
+                        RegionError::RegionReadFailed(format!("failed to decompress: `{e}`"))
+                    })?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compression;
+
+    #[test]
+    fn round_trip() {
+        let data = vec![7; 4096];
+
+        for compression in [Compression::None, Compression::Deflate] {
+            let compressed = compression.compress(&data).unwrap();
+            assert_eq!(compression.decompress(&compressed).unwrap(), data);
+            assert_eq!(Compression::from_id(compression.id()), Ok(compression));
+        }
+
+        // repetitive data should get much smaller
+        assert!(Compression::Deflate.compress(&data).unwrap().len() < 100);
+    }
+}
@@ -0,0 +1,960 @@
+//! # File
+//!
+//! The stored layout of a region, built so single chunks can be read and
+//! rewritten without decoding the rest of the region.
+//!
+//! Files are split into 4 KiB sectors. The first sector starts with a magic
+//! number, the file's format version, and where the file's table is. The
+//! table only lists the entries actually in the file: the chunks in the
+//! region, then the chunk columns passing through it. Only regions at
+//! `y = 0` hold column entries, with each column's heightmap. Like McRegion,
+//! each entry has a 'location' packing the first sector of its data with how
+//! many sectors that data spans, along with the time it was last written.
+//!
+//! An entry's data starts with its length in bytes, then its own magic
+//! number, format version, compression id, and a CRC32 checksum, then the
+//! (maybe compressed) data itself. Sectors left behind by shrunk or removed
+//! entries are reused by later writes.
+//!
+//! An open region file only reads the header, the table, and the
+//! entries asked for.
+//! Written entries are held on to until the file is committed, which
+//! replaces the stored file with one holding them in one go.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use super::{compression::Compression, RegionError};
+use crate::world::{
+    chunk::Chunk,
+    coordinates::{ChunkCoordinate, ColumnCoordinate, RegionCoordinate},
+    heightmap::Heightmap,
+    storage::{Storage, StorageError},
+};
+
+/// The magic number at the start of every region file.
+pub const REGION_MAGIC: [u8; 4] = *b"MCWR";
+
+/// The magic number at the start of every entry's data.
+pub const ENTRY_MAGIC: [u8; 4] = *b"MCWC";
+
+/// The newest format version this game can read and write.
+///
+/// - Version 1 added the magic numbers and compression.
+/// - Version 2 added a checksum to each entry.
+/// - Version 3 swapped the header's fixed tables for one only listing the
+///   entries in the file.
+pub const FORMAT_VERSION: u16 = 3;
+
+/// The size of a sector, in bytes.
+pub const SECTOR_BYTES: usize = 4096;
+
+/// The most sectors a single entry can span.
+const MAX_ENTRY_SECTORS: usize = u8::MAX as usize;
+
+const PER_REGION: usize = RegionCoordinate::CHUNKS_PER_REGION as usize;
+
+/// One entry for every chunk, then one for every chunk column.
+const ENTRIES: usize = PER_REGION * PER_REGION * PER_REGION + PER_REGION * PER_REGION;
+
+/// The sectors at the start of the file, holding the magic number, version,
+/// and the table's location.
+const HEADER_SECTORS: usize = 1;
+
+/// The bytes each entry takes up in the table: its index, location, and
+/// timestamp.
+const TABLE_ENTRY_BYTES: usize = 2 + 4 + 4;
+
+/// The sectors taken up by each of the fixed tables in files before version
+/// 3. They had a location and timestamp for every possible entry, right
+/// after the first sector.
+const FIXED_TABLE_SECTORS: usize = (ENTRIES * 4).div_ceil(SECTOR_BYTES);
+
+/// Something stored in a region file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entry {
+    Chunk(ChunkCoordinate),
+    Column(ColumnCoordinate),
+}
+
+/// An open region file.
+#[derive(Debug)]
+pub struct RegionFile {
+    /// Where the file is stored.
+    storage: Storage,
+    /// The file's path in storage.
+    path: PathBuf,
+    /// The sectors of entries written since the file was opened, by their
+    /// first sector. They aren't in storage until the file is committed.
+    written: BTreeMap<usize, Vec<u8>>,
+    /// Whether committing replaces everything stored, rather than just what
+    /// was written.
+    fresh: bool,
+    /// Where the stored table is, packed like an entry's location. Zero when
+    /// there isn't one.
+    table: u32,
+    /// The region stored in this file.
+    coordinates: RegionCoordinate,
+    /// Where each entry's data lives: `(first sector << 8) | sector count`.
+    /// Missing entries are zero.
+    locations: Vec<u32>,
+    /// When each entry was last written, in seconds since the Unix epoch.
+    timestamps: Vec<u32>,
+    /// Which sectors are holding data.
+    used: Vec<bool>,
+    /// How newly written entries get compressed.
+    compression: Compression,
+}
+
+impl RegionFile {
+    /// Opens the region file at `path` in storage. If it doesn't exist yet,
+    /// an empty one is made, which is stored once it's committed.
+    pub fn open(
+        storage: Storage,
+        path: &Path,
+        coordinates: RegionCoordinate,
+    ) -> Result<Self, RegionError> {
+        let damaged =
+            |what: String| RegionError::Damaged(format!("`{}` {what}", path.to_string_lossy()));
+
+        let (Some(size), Some(first)) =
+            (storage.size(path)?, storage.read_at(path, 0, SECTOR_BYTES)?)
+        else {
+            return Ok(Self::create(storage, path, coordinates));
+        };
+
+        let version = check_version(first.get(..6).unwrap_or(&[0; 6]), REGION_MAGIC)?;
+        let total_sectors = (size as usize).div_ceil(SECTOR_BYTES);
+        let mut file = Self::create(storage, path, coordinates);
+        file.fresh = false;
+        file.used = vec![false; total_sectors.max(HEADER_SECTORS)];
+        file.used[..HEADER_SECTORS].fill(true);
+
+        if version < 3 {
+            // the old tables are read, but the next commit writes a new one,
+            // so their sectors are free to reuse
+            let bytes = FIXED_TABLE_SECTORS * 2 * SECTOR_BYTES;
+            let tables = file
+                .storage
+                .read_at(path, SECTOR_BYTES as u64, bytes)?
+                .unwrap_or_default();
+            if tables.len() < bytes {
+                return Err(damaged(format!(
+                    "is too short to have a header ({size} bytes)"
+                )));
+            }
+
+            let table = |start: usize| {
+                tables[start..start + ENTRIES * 4]
+                    .chunks_exact(4)
+                    .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect::<Vec<_>>()
+            };
+            file.locations = table(0);
+            file.timestamps = table(FIXED_TABLE_SECTORS * SECTOR_BYTES);
+            file.used[..1 + FIXED_TABLE_SECTORS * 2].fill(true);
+        } else {
+            let Some(&[a, b, c, d]) = first.get(6..10) else {
+                return Err(damaged(format!(
+                    "is too short to have a header ({size} bytes)"
+                )));
+            };
+            file.table = u32::from_be_bytes([a, b, c, d]);
+            file.read_table(total_sectors).map_err(damaged)?;
+        }
+
+        // mark the sectors that are in use
+        for index in 0..ENTRIES {
+            let location = file.locations[index];
+            if location == 0 {
+                continue;
+            }
+
+            let (offset, count) = split_location(location);
+            if count == 0 || offset < HEADER_SECTORS || offset + count > total_sectors {
+                return Err(damaged(format!(
+                    "has an entry outside of the file, at sectors {offset}..{}",
+                    offset + count
+                )));
+            }
+
+            // two entries sharing sectors would clobber each other on write
+            if file.used[offset..offset + count].contains(&true) {
+                return Err(damaged(format!(
+                    "has entries overlapping at sectors {offset}..{}",
+                    offset + count
+                )));
+            }
+
+            file.used[offset..offset + count].fill(true);
+        }
+
+        // older tables only go away once the file is rewritten
+        if version < 3 {
+            file.used[HEADER_SECTORS..1 + FIXED_TABLE_SECTORS * 2].fill(false);
+        }
+
+        Ok(file)
+    }
+
+    /// Starts an empty region file at `path` in storage, ignoring anything
+    /// already stored there. Committing it replaces the stored file.
+    pub fn create(storage: Storage, path: &Path, coordinates: RegionCoordinate) -> Self {
+        Self {
+            storage,
+            path: path.to_path_buf(),
+            written: BTreeMap::new(),
+            fresh: true,
+            table: 0,
+            coordinates,
+            locations: vec![0; ENTRIES],
+            timestamps: vec![0; ENTRIES],
+            used: vec![true; HEADER_SECTORS],
+            compression: Compression::default(),
+        }
+    }
+
+    /// Stores everything written to this file, replacing the old file all at
+    /// once.
+    pub fn commit(mut self) -> Result<(), RegionError> {
+        let mut table = Vec::new();
+        for (index, (location, timestamp)) in
+            self.locations.iter().zip(&self.timestamps).enumerate()
+        {
+            if *location != 0 {
+                table.extend_from_slice(&(index as u16).to_be_bytes());
+                table.extend_from_slice(&location.to_be_bytes());
+                table.extend_from_slice(&timestamp.to_be_bytes());
+            }
+        }
+
+        // the table gets a new home every time, like any other entry
+        if self.table != 0 {
+            let (offset, count) = split_location(self.table);
+            self.used[offset..offset + count].fill(false);
+        }
+        let table_sectors = table.len().div_ceil(SECTOR_BYTES);
+        let table_offset = if table.is_empty() {
+            0
+        } else {
+            self.allocate(table_sectors)
+        };
+        table.resize(table_sectors * SECTOR_BYTES, 0);
+
+        let mut first = vec![0; SECTOR_BYTES];
+        first[..4].copy_from_slice(&REGION_MAGIC);
+        first[4..6].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+        first[6..10].copy_from_slice(&pack_location(table_offset, table_sectors).to_be_bytes());
+
+        let runs = [(0, first.as_slice()), (table_offset, table.as_slice())]
+            .into_iter()
+            .chain(
+                self.written
+                    .iter()
+                    .map(|(offset, bytes)| (*offset, bytes.as_slice())),
+            )
+            .map(|(offset, bytes)| ((offset * SECTOR_BYTES) as u64, bytes))
+            .collect::<Vec<_>>();
+
+        // space left at the end by removed entries is given back
+        let sectors = self.used.iter().rposition(|used| *used).unwrap_or(0) + 1;
+        let length = sectors * SECTOR_BYTES;
+
+        if !self.fresh {
+            self.storage.write_at(&self.path, &runs, length as u64)?;
+            return Ok(());
+        }
+
+        // nothing stored is kept, so it's all put together here
+        let mut data = vec![0; length];
+        for (offset, bytes) in runs {
+            let offset = offset as usize;
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        self.storage.write(&self.path, &data)?;
+        Ok(())
+    }
+
+    /// How newly written entries get compressed.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Changes how newly written entries get compressed.
+    ///
+    /// Entries already in the file keep their old compression until they're
+    /// written again.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// The region stored in this file.
+    pub fn coordinates(&self) -> RegionCoordinate {
+        self.coordinates
+    }
+
+    /// Checks if the file holds the given chunk.
+    pub fn contains_chunk(&self, chunk: ChunkCoordinate) -> bool {
+        self.index(Entry::Chunk(chunk))
+            .is_ok_and(|index| self.locations[index] != 0)
+    }
+
+    /// All chunks stored in this file.
+    pub fn chunks(&self) -> Vec<ChunkCoordinate> {
+        let min = self.coordinates.min_chunk();
+
+        (0..PER_REGION.pow(3))
+            .filter(|index| self.locations[*index] != 0)
+            .map(|index| {
+                let (x, z, y) = (
+                    index % PER_REGION,
+                    index / PER_REGION % PER_REGION,
+                    index / (PER_REGION * PER_REGION),
+                );
+                min.offset(x as i64, y as i64, z as i64)
+            })
+            .collect()
+    }
+
+    /// All chunk columns with a heightmap stored in this file.
+    pub fn columns(&self) -> Vec<ColumnCoordinate> {
+        let min = self.coordinates.min_chunk();
+
+        (0..PER_REGION * PER_REGION)
+            .filter(|index| self.locations[PER_REGION.pow(3) + index] != 0)
+            .map(|index| {
+                let (x, z) = (index % PER_REGION, index / PER_REGION);
+                ColumnCoordinate::new(min.x + x as i64, min.z + z as i64)
+            })
+            .filter(|column| column.region() == self.coordinates)
+            .collect()
+    }
+
+    /// When the given chunk was last written, if it's in the file.
+    pub fn chunk_timestamp(&self, chunk: ChunkCoordinate) -> Option<DateTime<Utc>> {
+        let index = self.index(Entry::Chunk(chunk)).ok()?;
+
+        if self.locations[index] == 0 {
+            return None;
+        }
+        DateTime::from_timestamp(self.timestamps[index] as i64, 0)
+    }
+
+    /// Reads a single chunk from the file, if it's there.
+    pub fn read_chunk(&mut self, chunk: ChunkCoordinate) -> Result<Option<Chunk>, RegionError> {
+        let index = self.index(Entry::Chunk(chunk))?;
+
+        self.read_entry(index)?
+            .map(|bytes| {
+                bincode::deserialize(&bytes).map_err(|e| {
+                    RegionError::RegionReadFailed(format!(
+                        "failed to deserialize chunk at `{chunk}` from bincode: `{e}`"
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Writes a single chunk into the file, replacing any older copy.
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), RegionError> {
+        let index = self.index(Entry::Chunk(chunk.coords()))?;
+        let bytes = bincode::serialize(chunk)
+            .map_err(|e| RegionError::ChunkSerializationFailed(e.to_string()))?;
+
+        self.write_entry(index, &bytes)
+    }
+
+    /// Reads every sector of a chunk's entry exactly as stored, without
+    /// checking any of it. Useful for saving what's left of corrupted chunks.
+    pub fn read_raw_chunk(
+        &mut self,
+        chunk: ChunkCoordinate,
+    ) -> Result<Option<Vec<u8>>, RegionError> {
+        let index = self.index(Entry::Chunk(chunk))?;
+        if self.locations[index] == 0 {
+            return Ok(None);
+        }
+
+        let (offset, count) = split_location(self.locations[index]);
+        self.sectors(offset, count).map(Some)
+    }
+
+    /// Removes a chunk from the file, freeing up its sectors.
+    pub fn remove_chunk(&mut self, chunk: ChunkCoordinate) -> Result<(), RegionError> {
+        let index = self.index(Entry::Chunk(chunk))?;
+        self.free(index);
+        self.set_location(index, 0, 0);
+        Ok(())
+    }
+
+    /// Reads the heightmap of a chunk column, if it's in the file.
+    pub fn read_heightmap(
+        &mut self,
+        column: ColumnCoordinate,
+    ) -> Result<Option<Heightmap>, RegionError> {
+        let index = self.index(Entry::Column(column))?;
+
+        self.read_entry(index)?
+            .map(|bytes| {
+                bincode::deserialize(&bytes).map_err(|e| {
+                    RegionError::RegionReadFailed(format!(
+                        "failed to deserialize heightmap at `{column}` from bincode: `{e}`"
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Writes the heightmap of a chunk column into the file.
+    pub fn write_heightmap(
+        &mut self,
+        column: ColumnCoordinate,
+        heightmap: &Heightmap,
+    ) -> Result<(), RegionError> {
+        let index = self.index(Entry::Column(column))?;
+        let bytes = bincode::serialize(heightmap)
+            .map_err(|e| RegionError::ChunkSerializationFailed(e.to_string()))?;
+
+        self.write_entry(index, &bytes)
+    }
+
+    /// Finds an entry's position in the table.
+    fn index(&self, entry: Entry) -> Result<usize, RegionError> {
+        let min = self.coordinates.min_chunk();
+        let per_region = PER_REGION as i64;
+
+        match entry {
+            Entry::Chunk(chunk) => {
+                if !self.coordinates.contains(chunk) {
+                    return Err(RegionError::WrongRegion {
+                        chunk,
+                        region: self.coordinates,
+                    });
+                }
+
+                let (x, y, z) = (chunk.x - min.x, chunk.y - min.y, chunk.z - min.z);
+                Ok((x + z * per_region + y * per_region * per_region) as usize)
+            }
+            Entry::Column(column) => {
+                if column.region() != self.coordinates {
+                    return Err(RegionError::WrongColumn {
+                        column,
+                        region: self.coordinates,
+                    });
+                }
+
+                let (x, z) = (column.x - min.x, column.z - min.z);
+                Ok(PER_REGION.pow(3) + (x + z * per_region) as usize)
+            }
+        }
+    }
+
+    /// Reads an entry's data, if it has any, decompressing it.
+    fn read_entry(&mut self, index: usize) -> Result<Option<Vec<u8>>, RegionError> {
+        let location = self.locations[index];
+        if location == 0 {
+            return Ok(None);
+        }
+
+        let (offset, count) = split_location(location);
+        let sectors = self.sectors(offset, count)?;
+
+        let Some(&[a, b, c, d]) = sectors.get(..4) else {
+            return Err(RegionError::RegionReadFailed(format!(
+                "entry at sector {offset} has no room for its length"
+            )));
+        };
+        let length = u32::from_be_bytes([a, b, c, d]) as usize;
+        if length + 4 > sectors.len() {
+            return Err(RegionError::RegionReadFailed(format!(
+                "entry claims to be {length} bytes, but only has {count} sectors"
+            )));
+        }
+
+        decode_entry(&sectors[4..4 + length]).map(Some)
+    }
+
+    /// Compresses and writes an entry's data, reusing its old sectors if it
+    /// still fits.
+    fn write_entry(&mut self, index: usize, data: &[u8]) -> Result<(), RegionError> {
+        let data = self.compression.compress(data)?;
+        let length = entry_header_bytes(FORMAT_VERSION) + data.len();
+
+        let mut bytes = Vec::with_capacity(length + 4);
+        bytes.extend_from_slice(&(length as u32).to_be_bytes());
+        bytes.extend_from_slice(&ENTRY_MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.push(self.compression.id());
+        bytes.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+        bytes.extend_from_slice(&data);
+
+        let count = bytes.len().div_ceil(SECTOR_BYTES);
+        if count > MAX_ENTRY_SECTORS {
+            return Err(RegionError::ChunkTooLarge(bytes.len()));
+        }
+        bytes.resize(count * SECTOR_BYTES, 0);
+
+        let (old_offset, old_count) = split_location(self.locations[index]);
+        let offset = if self.locations[index] != 0 && count <= old_count {
+            // it fits! give back whatever's left over
+            self.used[old_offset + count..old_offset + old_count].fill(false);
+            old_offset
+        } else {
+            self.free(index);
+            self.allocate(count)
+        };
+
+        self.written.insert(offset, bytes);
+
+        self.set_location(index, offset, count);
+        Ok(())
+    }
+
+    /// Updates an entry in the table.
+    fn set_location(&mut self, index: usize, offset: usize, count: usize) {
+        self.locations[index] = pack_location(offset, count);
+        self.timestamps[index] = Utc::now().timestamp() as u32;
+    }
+
+    /// Reads the stored table into the locations and timestamps, marking its
+    /// own sectors as used. Gives back what's wrong with it if it's damaged.
+    fn read_table(&mut self, total_sectors: usize) -> Result<(), String> {
+        if self.table == 0 {
+            return Ok(());
+        }
+
+        let (offset, count) = split_location(self.table);
+        if count == 0 || offset < HEADER_SECTORS || offset + count > total_sectors {
+            return Err(format!(
+                "has a table outside of the file, at sectors {offset}..{}",
+                offset + count
+            ));
+        }
+
+        let table = self.sectors(offset, count).map_err(|e| e.to_string())?;
+        self.used[offset..offset + count].fill(true);
+
+        for entry in table.chunks_exact(TABLE_ENTRY_BYTES) {
+            let index = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+            let location = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+            let timestamp = u32::from_be_bytes([entry[6], entry[7], entry[8], entry[9]]);
+
+            // the table is padded out with empty entries
+            if location == 0 {
+                break;
+            }
+
+            if index >= ENTRIES || self.locations[index] != 0 {
+                return Err(format!("has a bad or repeated table entry for {index}"));
+            }
+            self.locations[index] = location;
+            self.timestamps[index] = timestamp;
+        }
+
+        Ok(())
+    }
+
+    /// The bytes in an entry's run of sectors, from what's been written
+    /// since opening or from storage.
+    fn sectors(&self, offset: usize, count: usize) -> Result<Vec<u8>, RegionError> {
+        if let Some(bytes) = self.written.get(&offset) {
+            return Ok(bytes.clone());
+        }
+
+        let mut bytes = self
+            .storage
+            .read_at(
+                &self.path,
+                (offset * SECTOR_BYTES) as u64,
+                count * SECTOR_BYTES,
+            )?
+            .ok_or_else(|| StorageError::NotFound(self.path.to_string_lossy().to_string()))?;
+
+        // the last sector in a file might not be padded out
+        bytes.resize(count * SECTOR_BYTES, 0);
+        Ok(bytes)
+    }
+
+    /// Marks an entry's sectors as free.
+    fn free(&mut self, index: usize) {
+        if self.locations[index] != 0 {
+            let (offset, count) = split_location(self.locations[index]);
+            self.used[offset..offset + count].fill(false);
+            self.written.remove(&offset);
+        }
+    }
+
+    /// Finds room for `count` sectors, marking them as used.
+    ///
+    /// Gaps left by other entries are used first. Otherwise, the file grows.
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut start = HEADER_SECTORS;
+        let mut run = 0;
+
+        for sector in HEADER_SECTORS..self.used.len() {
+            if self.used[sector] {
+                start = sector + 1;
+                run = 0;
+            } else {
+                run += 1;
+                if run == count {
+                    break;
+                }
+            }
+        }
+
+        // `start` is either a big enough gap or the free space at the end
+        let end = start + count;
+        if end > self.used.len() {
+            self.used.resize(end, false);
+        }
+        self.used[start..end].fill(true);
+
+        start
+    }
+}
+
+/// The bytes before an entry's data, which depend on its version.
+const fn entry_header_bytes(version: u16) -> usize {
+    // magic, version, and compression id
+    let header = ENTRY_MAGIC.len() + 2 + 1;
+
+    // then a checksum, since version 2
+    if version >= 2 {
+        header + 4
+    } else {
+        header
+    }
+}
+
+/// Checks an entry's header and checksum, then decompresses its data.
+fn decode_entry(stored: &[u8]) -> Result<Vec<u8>, RegionError> {
+    let too_short = || {
+        RegionError::RegionReadFailed(format!(
+            "entry is too short to have a header ({} bytes)",
+            stored.len()
+        ))
+    };
+
+    let version = check_version(stored.get(..6).ok_or_else(too_short)?, ENTRY_MAGIC)?;
+    let header_bytes = entry_header_bytes(version);
+    if stored.len() < header_bytes {
+        return Err(too_short());
+    }
+
+    let compression = Compression::from_id(stored[6])?;
+    let data = &stored[header_bytes..];
+
+    if version >= 2 {
+        let expected = u32::from_be_bytes([stored[7], stored[8], stored[9], stored[10]]);
+        let found = crc32fast::hash(data);
+
+        if expected != found {
+            return Err(RegionError::ChecksumMismatch { expected, found });
+        }
+    }
+
+    compression.decompress(data)
+}
+
+/// Checks that `bytes` start with the expected magic number, followed by a
+/// format version this game can read. Gives back that version.
+fn check_version(bytes: &[u8], magic: [u8; 4]) -> Result<u16, RegionError> {
+    let found = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if found != magic {
+        return Err(RegionError::BadMagic(found));
+    }
+
+    let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(RegionError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+
+    Ok(version)
+}
+
+/// Packs an entry's first sector and sector count into a location. Empty
+/// entries are zero.
+fn pack_location(offset: usize, count: usize) -> u32 {
+    if count == 0 {
+        0
+    } else {
+        (offset as u32) << 8 | count as u32
+    }
+}
+
+/// Splits a location into its first sector and sector count.
+fn split_location(location: u32) -> (usize, usize) {
+    ((location >> 8) as usize, (location & 0xFF) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use super::{
+        split_location, RegionError, RegionFile, FIXED_TABLE_SECTORS, FORMAT_VERSION,
+        HEADER_SECTORS, REGION_MAGIC, SECTOR_BYTES, TABLE_ENTRY_BYTES,
+    };
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::Chunk,
+            coordinates::{ChunkBlockCoordinate, ChunkCoordinate, RegionCoordinate},
+            storage::{MemoryStorage, Storage, StorageBackend, StorageError},
+        },
+    };
+
+    /// Storage that refuses to read whole blobs, so only ranged reads work.
+    #[derive(Debug, Default)]
+    struct RangedOnly(MemoryStorage);
+
+    impl StorageBackend for RangedOnly {
+        fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, StorageError> {
+            Err(StorageError::read_failed(
+                path,
+                "whole reads aren't allowed",
+            ))
+        }
+
+        fn read_at(
+            &self,
+            path: &Path,
+            offset: u64,
+            length: usize,
+        ) -> Result<Option<Vec<u8>>, StorageError> {
+            self.0.read_at(path, offset, length)
+        }
+
+        fn size(&self, path: &Path) -> Result<Option<u64>, StorageError> {
+            self.0.size(path)
+        }
+
+        fn write(&self, path: &Path, data: &[u8]) -> Result<(), StorageError> {
+            self.0.write(path, data)
+        }
+
+        fn write_at(
+            &self,
+            path: &Path,
+            runs: &[(u64, &[u8])],
+            length: u64,
+        ) -> Result<(), StorageError> {
+            self.0.write_at(path, runs, length)
+        }
+
+        fn remove(&self, path: &Path) -> Result<(), StorageError> {
+            self.0.remove(path)
+        }
+
+        fn list(&self, prefix: &Path) -> Result<Vec<PathBuf>, StorageError> {
+            self.0.list(prefix)
+        }
+    }
+
+    #[test]
+    fn chunks_are_read_back_individually() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(RangedOnly::default());
+        let path = Path::new("0_0_0.region");
+
+        let mut first = Chunk::new(ChunkCoordinate::new(1, 2, 3));
+        first.set_block(
+            Block::new(BlockType::STONE, 0),
+            ChunkBlockCoordinate::new(4, 5, 6),
+        );
+        let second = Chunk::new_filled(Block::new(BlockType::DIRT, 0), ChunkCoordinate::ORIGIN);
+
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.write_chunk(&first)?;
+        file.write_chunk(&second)?;
+        let first_sector = file.locations[file.index(super::Entry::Chunk(first.coords()))?] >> 8;
+        file.commit()?;
+
+        // reopening only reads the chunks we ask for
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        assert_eq!(file.chunks().len(), 2);
+        assert_eq!(file.read_chunk(first.coords())?, Some(first.clone()));
+        assert_eq!(file.read_chunk(ChunkCoordinate::new(9, 9, 9))?, None);
+
+        // removed chunks leave space for new ones
+        file.remove_chunk(first.coords())?;
+        file.write_chunk(&Chunk::new(ChunkCoordinate::new(5, 5, 5)))?;
+        let reused =
+            file.locations[file.index(super::Entry::Chunk(ChunkCoordinate::new(5, 5, 5)))?] >> 8;
+        assert_eq!(reused, first_sector);
+        assert_eq!(first_sector as usize, HEADER_SECTORS);
+
+        // chunks from other regions don't belong here
+        assert!(file
+            .write_chunk(&Chunk::new(ChunkCoordinate::new(32, 0, 0)))
+            .is_err());
+
+        // committing only changes what was written
+        file.commit()?;
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        assert_eq!(file.read_chunk(second.coords())?, Some(second));
+        assert_eq!(file.read_chunk(first.coords())?, None);
+        assert!(file.contains_chunk(ChunkCoordinate::new(5, 5, 5)));
+
+        // files from the future are refused instead of misread
+        let size = storage.size(path)?.unwrap();
+        storage.write_at(path, &[(4, &(FORMAT_VERSION + 1).to_be_bytes())], size)?;
+        assert_eq!(
+            RegionFile::open(storage, path, RegionCoordinate::ORIGIN).unwrap_err(),
+            RegionError::UnsupportedVersion {
+                found: FORMAT_VERSION + 1,
+                supported: FORMAT_VERSION
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn commits_and_checksums() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let path = Path::new("0_0_0.region");
+
+        let chunk = Chunk::new_filled(Block::new(BlockType::STONE, 0), ChunkCoordinate::ORIGIN);
+
+        // nothing is stored until the file is committed
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.write_chunk(&chunk)?;
+        assert!(!storage.exists(path)?);
+        file.commit()?;
+
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        assert_eq!(file.read_chunk(chunk.coords())?, Some(chunk.clone()));
+
+        // flip a byte at the end of the chunk's data
+        let start = split_location(file.locations[0]).0 * SECTOR_BYTES;
+        let mut raw = storage.read(path)?.unwrap();
+        let length =
+            u32::from_be_bytes([raw[start], raw[start + 1], raw[start + 2], raw[start + 3]]);
+        raw[start + 3 + length as usize] ^= 0xFF;
+        storage.write(path, &raw)?;
+
+        let mut file = RegionFile::open(storage, path, RegionCoordinate::ORIGIN)?;
+        assert!(matches!(
+            file.read_chunk(chunk.coords()),
+            Err(RegionError::ChecksumMismatch { .. })
+        ));
+
+        // the raw bytes are still there for whoever wants to dig through them
+        assert!(file.read_raw_chunk(chunk.coords())?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_headers_are_refused() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let path = Path::new("0_0_0.region");
+
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.write_chunk(&Chunk::new(ChunkCoordinate::ORIGIN))?;
+        file.write_chunk(&Chunk::new(ChunkCoordinate::new(1, 0, 0)))?;
+        file.commit()?;
+        let good = storage.read(path)?.unwrap();
+
+        let table = u32::from_be_bytes([good[6], good[7], good[8], good[9]]);
+        let damage = |index: usize, location: u32| -> anyhow::Result<()> {
+            let mut raw = good.clone();
+            let start = split_location(table).0 * SECTOR_BYTES + index * TABLE_ENTRY_BYTES + 2;
+            raw[start..start + 4].copy_from_slice(&location.to_be_bytes());
+            storage.write(path, &raw)?;
+            Ok(())
+        };
+
+        // an entry that takes up no sectors at all
+        damage(0, (HEADER_SECTORS as u32) << 8)?;
+        assert!(matches!(
+            RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN),
+            Err(RegionError::Damaged(_))
+        ));
+
+        // two entries in the same place
+        damage(1, (HEADER_SECTORS as u32) << 8 | 1)?;
+        assert!(matches!(
+            RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN),
+            Err(RegionError::Damaged(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn small_regions_make_small_files() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let path = Path::new("0_0_0.region");
+
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.write_chunk(&Chunk::new(ChunkCoordinate::ORIGIN))?;
+        file.commit()?;
+
+        // the header, the chunk, and the table
+        assert_eq!(storage.size(path)?, Some(3 * SECTOR_BYTES as u64));
+
+        // removing the chunk gives its space back
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.remove_chunk(ChunkCoordinate::ORIGIN)?;
+        file.commit()?;
+        assert_eq!(storage.size(path)?, Some(SECTOR_BYTES as u64));
+        let file = RegionFile::open(storage, path, RegionCoordinate::ORIGIN)?;
+        assert!(file.chunks().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_tables_are_still_read() -> anyhow::Result<()> {
+        let storage: Storage = Arc::new(MemoryStorage::new());
+        let path = Path::new("0_0_0.region");
+
+        let chunk = Chunk::new_filled(Block::new(BlockType::STONE, 0), ChunkCoordinate::ORIGIN);
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        file.write_chunk(&chunk)?;
+        let (offset, count) = split_location(file.locations[0]);
+        file.commit()?;
+        let new = storage.read(path)?.unwrap();
+        let entry = &new[offset * SECTOR_BYTES..(offset + count) * SECTOR_BYTES];
+
+        // lay the same chunk out like a version 2 file would
+        let first = 1 + FIXED_TABLE_SECTORS * 2;
+        let mut raw = vec![0; (first + count) * SECTOR_BYTES];
+        raw[..4].copy_from_slice(&REGION_MAGIC);
+        raw[4..6].copy_from_slice(&2u16.to_be_bytes());
+        let location = ((first as u32) << 8 | count as u32).to_be_bytes();
+        raw[SECTOR_BYTES..SECTOR_BYTES + 4].copy_from_slice(&location);
+        raw[first * SECTOR_BYTES..].copy_from_slice(entry);
+        storage.write(path, &raw)?;
+
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        assert_eq!(file.read_chunk(chunk.coords())?, Some(chunk.clone()));
+
+        // the old tables are dropped once it's written again
+        let other = Chunk::new(ChunkCoordinate::new(1, 0, 0));
+        file.write_chunk(&other)?;
+        file.commit()?;
+        let mut file = RegionFile::open(storage.clone(), path, RegionCoordinate::ORIGIN)?;
+        assert_eq!(file.read_chunk(chunk.coords())?, Some(chunk));
+        assert_eq!(file.read_chunk(other.coords())?, Some(other));
+        assert_eq!(
+            storage.read_at(path, 4, 2)?,
+            Some(FORMAT_VERSION.to_be_bytes().to_vec())
+        );
+
+        Ok(())
+    }
+}
@@ -7,58 +7,84 @@
 // and i'll leave the option open for anyone who wants to give it
 // a try!
 
-use std::{
-    path::{Path, PathBuf},
-    sync::Arc,
-};
-
-use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
 
 use super::{
-    coordinates::GlobalCoordinate,
+    coordinates::RegionCoordinate,
     loader::WorldLoadingError,
     metadata::WorldMetadata,
-    region::{Region, RegionError},
+    migration,
+    player::PlayerData,
+    region::{file::RegionFile, Region},
+    state::WorldState,
+    storage::{Storage, StorageBackend},
 };
 
+mod archive;
+pub mod manager;
+
 pub const GAME_DIRECTORY: &str = "macaw";
 pub const SAVES_DIRECTORY: &str = "saves";
 
+/// A copy of a save's files besides its regions: the world's metadata, its
+/// state, and its players' records. It's taken in one go, so it can be
+/// written in the background while the world keeps changing.
+#[derive(Clone, Debug)]
+pub struct SaveFiles {
+    pub metadata: Arc<WorldMetadata>,
+    pub state: WorldState,
+    pub players: Vec<PlayerData>,
+}
+
+impl SaveFiles {
+    /// Writes every file to storage.
+    pub fn write(&self, storage: &dyn StorageBackend) -> Result<(), WorldLoadingError> {
+        let save_path = self.metadata.save_path();
+        self.metadata.write(storage)?;
+        self.state.write(storage, &save_path)?;
+
+        for player in self.players.iter() {
+            player.write(storage, &save_path)?;
+        }
+        // TODO: write mobs..?
+
+        Ok(())
+    }
+}
+
 /// A representation of the world's actual save files.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
 pub struct WorldSave {
     /// The name of the world being saved. Used to find paths.
     metadata: Arc<WorldMetadata>,
+    /// Where the save's files are kept.
+    storage: Storage,
 }
 
 impl WorldSave {
     /// Loads an existing save, if it exists, or attempts to create a new save.
-    pub fn new(world_metadata: Arc<WorldMetadata>) -> Result<Self, WorldLoadingError> {
+    ///
+    /// Saves from older versions of the game are backed up, then migrated to
+    /// the current save format.
+    pub fn new(
+        world_metadata: Arc<WorldMetadata>,
+        storage: Storage,
+    ) -> Result<Self, WorldLoadingError> {
         let save_path = world_metadata.save_path();
 
         // check if the save exists already
-        if let Some(save) = Self::try_load(world_metadata.clone()) {
-            return Ok(save);
+        if storage.exists(&world_metadata.metadata_path())? {
+            migration::migrate(&storage, &save_path)?;
         } else {
             // no save yet. let's try to create one!
-
-            // make the folder
-            tracing::debug!("Attempting to create new save folder at {:?}", save_path);
-            std::fs::create_dir_all(save_path.as_ref()).map_err(|e| {
-                WorldLoadingError::SavePathCreationFailure(format!(
-                    "error: `{}` at `{}`",
-                    e,
-                    save_path.to_string_lossy()
-                ))
-            })?;
-
-            // try to write metadata
-            world_metadata.write_to_disk()?;
+            tracing::debug!("Attempting to create new save at {:?}", save_path);
+            world_metadata.write(storage.as_ref())?;
         }
 
         // all good! here's the save
         Ok(Self {
             metadata: world_metadata,
+            storage,
         })
     }
 
@@ -67,60 +93,71 @@ impl WorldSave {
         self.metadata.clone()
     }
 
+    /// The storage holding this save.
+    pub fn storage(&self) -> Storage {
+        self.storage.clone()
+    }
+
     /// The name of the world being saved.
     pub fn name(&self) -> Arc<String> {
         Arc::new(self.metadata.name().to_owned())
     }
 
-    /// Attempts to load a world save from disk given its metadata.
-    fn try_load(metadata: Arc<WorldMetadata>) -> Option<Self> {
-        let res = Path::new(metadata.save_path().as_ref()).try_exists();
-
-        // if we found the save, return it
-        if let Ok(true) = res {
-            Some(Self { metadata })
-        } else {
-            None
-        }
+    /// The path of a file in this save, relative to the storage's root.
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.metadata.save_path().join(file)
     }
 
+    /// Loads a whole region from storage.
     pub fn load_region(
         &self,
-        region_coordinate: GlobalCoordinate,
+        region_coordinate: RegionCoordinate,
     ) -> Result<Region, WorldLoadingError> {
+        Ok(Region::load(region_coordinate, self.clone())?)
+    }
+
+    /// Opens a region's file for reading single chunks.
+    ///
+    /// Regions that aren't saved yet give back `None`.
+    pub fn region_file(
+        &self,
+        region_coordinate: RegionCoordinate,
+    ) -> Result<Option<RegionFile>, WorldLoadingError> {
         let region_path = Region::path_from_coordinates(&region_coordinate, self.metadata.clone());
 
-        if !region_path.exists() {
-            return Err(WorldLoadingError::RegionReadFailed(
-                region_path.to_string_lossy().to_string(),
-            ));
+        if !self.storage.exists(&region_path)? {
+            return Ok(None);
         }
 
-        // read region into a buffer
-        let buf = std::fs::read(region_path.as_ref())
-            .map_err(|e| WorldLoadingError::RegionReadFailed(e.to_string()))?;
+        Ok(Some(RegionFile::open(
+            self.storage.clone(),
+            &region_path,
+            region_coordinate,
+        )?))
+    }
 
-        // deserialize that buffer into a region
-        let s: Region = bincode::deserialize(&buf).map_err(|e| {
-            RegionError::RegionReadFailed(format!(
-                "failed to deserialize region from bincode: `{}`",
-                e
-            ))
-        })?;
+    /// Opens a region's file for writing single chunks, creating the region
+    /// if it isn't saved yet.
+    ///
+    /// Changes only reach storage once the file is committed.
+    pub fn writable_region_file(
+        &self,
+        region_coordinate: RegionCoordinate,
+    ) -> Result<RegionFile, WorldLoadingError> {
+        let region_path = Region::path_from_coordinates(&region_coordinate, self.metadata.clone());
 
-        Ok(s)
+        Ok(RegionFile::open(
+            self.storage.clone(),
+            &region_path,
+            region_coordinate,
+        )?)
     }
 }
 
-/// Gets the path where all game saves are kept. This is currently
-/// 'hard-coded', but should later take user configuration during launch.
-/// ```
-/// # use shared::world::save::get_saves_path;
-///
-/// let path = get_saves_path();
-/// assert!(path.to_string_lossy().contains("macaw/saves"));
-/// ```
-pub fn get_saves_path() -> PathBuf {
+/// Gets the folder where the game keeps its files, like saves and backups.
+/// This is currently 'hard-coded', but should later take user configuration
+/// during launch.
+pub fn get_game_path() -> PathBuf {
     let mut path = PathBuf::new();
     path.push(
         directories::BaseDirs::new()
@@ -129,6 +166,17 @@ pub fn get_saves_path() -> PathBuf {
     );
 
     path.push(GAME_DIRECTORY);
-    path.push(SAVES_DIRECTORY);
     path
 }
+
+/// Gets the path where all game saves are kept. This is currently
+/// 'hard-coded', but should later take user configuration during launch.
+/// ```
+/// # use shared::world::save::get_saves_path;
+///
+/// let path = get_saves_path();
+/// assert!(path.to_string_lossy().contains("macaw/saves"));
+/// ```
+pub fn get_saves_path() -> PathBuf {
+    get_game_path().join(SAVES_DIRECTORY)
+}
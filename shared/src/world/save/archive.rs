@@ -0,0 +1,22 @@
+//! # Archive
+//!
+//! Packs a folder into a gzipped tarball (`.tar.gz`), which just about every
+//! system can open.
+
+use std::{fs::File, io, path::Path};
+
+use flate2::{write::GzEncoder, Compression};
+use tar::Builder;
+
+/// Writes everything in `source` into a new tarball at `destination`. Its
+/// contents sit in a folder called `root` inside the archive.
+pub fn write_tar_gz(source: &Path, root: &str, destination: &Path) -> io::Result<()> {
+    let file = File::create(destination)?;
+    let mut builder = Builder::new(GzEncoder::new(file, Compression::default()));
+
+    // links are kept as links, so nothing outside the save sneaks in
+    builder.follow_symlinks(false);
+    builder.append_dir_all(root, source)?;
+
+    builder.into_inner()?.finish()?.sync_all()
+}
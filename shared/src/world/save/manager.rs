@@ -0,0 +1,434 @@
+//! # Manager
+//!
+//! Lists and manages every world save on disk, for world selection screens
+//! and server tooling alike.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use super::{archive, SAVES_DIRECTORY};
+use crate::world::{
+    loader::WorldLoadingError,
+    metadata::WorldMetadata,
+    state::WorldState,
+    storage::{FilesystemStorage, Storage, StorageBackend as _},
+};
+
+/// The folder where deleted saves go. It sits next to the saves folder.
+pub const TRASH_DIRECTORY: &str = "trash";
+
+/// Finds and manages the world saves kept on disk.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SaveManager {
+    /// The storage holding the saves folder.
+    storage: FilesystemStorage,
+}
+
+/// What's known about a save on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveInfo {
+    /// The world's name, as given by its folder.
+    name: String,
+    /// Where the save lives.
+    path: PathBuf,
+    /// The save's metadata, or why it couldn't be read.
+    metadata: Result<WorldMetadata, WorldLoadingError>,
+    /// The last time the world was played, or anything in the save changed
+    /// for saves that don't say.
+    last_played: Option<DateTime<Utc>>,
+    /// How much space the save takes up, in bytes.
+    size: u64,
+    /// Why the save's folder couldn't be looked through, if it couldn't.
+    walk_error: Option<SaveManagerError>,
+}
+
+impl SaveInfo {
+    /// The world's name. Saves with broken metadata still have one!
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Where the save lives on disk.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The save's metadata, or the error from trying to read it.
+    pub fn metadata(&self) -> Result<&WorldMetadata, &WorldLoadingError> {
+        self.metadata.as_ref()
+    }
+
+    /// The last time the world was played, as kept in its state.
+    ///
+    /// Saves made before the state kept track of this go by the last time
+    /// anything in them changed instead.
+    pub fn last_played(&self) -> Option<DateTime<Utc>> {
+        self.last_played
+    }
+
+    /// How much space the save takes up on disk, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The error from looking through the save's folder, if there was one.
+    ///
+    /// Its size and last played time are unknown when there was.
+    pub fn walk_error(&self) -> Option<&SaveManagerError> {
+        self.walk_error.as_ref()
+    }
+}
+
+impl SaveManager {
+    /// Creates a manager for the saves folder in the given storage.
+    pub fn new(storage: FilesystemStorage) -> Self {
+        Self { storage }
+    }
+
+    /// The storage holding the saves, ready for loading worlds out of.
+    pub fn storage(&self) -> Storage {
+        Arc::new(self.storage.clone())
+    }
+
+    /// The folder on disk holding every save.
+    pub fn saves_path(&self) -> PathBuf {
+        self.storage.full_path(Path::new(SAVES_DIRECTORY))
+    }
+
+    /// The folder on disk where deleted saves go.
+    pub fn trash_path(&self) -> PathBuf {
+        self.storage.full_path(Path::new(TRASH_DIRECTORY))
+    }
+
+    /// Where the world with the given name is saved on disk, whether or not
+    /// it exists.
+    pub fn save_path(&self, name: &str) -> PathBuf {
+        self.storage.full_path(&Self::relative_path(name))
+    }
+
+    /// Lists every save, most recently played first.
+    ///
+    /// Saves with unreadable metadata or folders are still listed, along
+    /// with their errors.
+    pub fn list(&self) -> Result<Vec<SaveInfo>, SaveManagerError> {
+        let saves_path = self.saves_path();
+        if !saves_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries =
+            std::fs::read_dir(&saves_path).map_err(|e| SaveManagerError::io(&saves_path, e))?;
+
+        let mut saves = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| SaveManagerError::io(&saves_path, e))?;
+            if !entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                continue;
+            }
+
+            saves.push(self.info_at(entry.path()));
+        }
+
+        saves.sort_by_key(|save| std::cmp::Reverse(save.last_played));
+        Ok(saves)
+    }
+
+    /// Finds out about the save for the world with the given name.
+    pub fn info(&self, name: &str) -> Result<SaveInfo, SaveManagerError> {
+        Ok(self.info_at(self.existing(name)?))
+    }
+
+    /// Renames a world, moving its save to match.
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<WorldMetadata, SaveManagerError> {
+        let from = self.existing(name)?;
+        let to = self.vacant(new_name)?;
+
+        let metadata = WorldMetadata::read(&self.storage, &Self::relative_path(name))?
+            .renamed(new_name.to_owned());
+        std::fs::rename(&from, &to).map_err(|e| SaveManagerError::io(&from, e))?;
+        metadata.write(&self.storage)?;
+
+        Ok(metadata)
+    }
+
+    /// Copies a world into a new save with a different name.
+    pub fn duplicate(&self, name: &str, new_name: &str) -> Result<WorldMetadata, SaveManagerError> {
+        self.existing(name)?;
+        self.vacant(new_name)?;
+
+        let from = Self::relative_path(name);
+        let metadata = WorldMetadata::read(&self.storage, &from)?.renamed(new_name.to_owned());
+        self.storage
+            .copy_all(&from, &Self::relative_path(new_name))
+            .map_err(WorldLoadingError::from)?;
+        metadata.write(&self.storage)?;
+
+        Ok(metadata)
+    }
+
+    /// Moves a world's save into the trash folder, giving back where it
+    /// went.
+    ///
+    /// Nothing is deleted for good, so players can still dig it back out.
+    pub fn delete(&self, name: &str) -> Result<PathBuf, SaveManagerError> {
+        let from = self.existing(name)?;
+
+        let trash = self.trash_path();
+        std::fs::create_dir_all(&trash).map_err(|e| SaveManagerError::io(&trash, e))?;
+
+        // worlds deleted within the same second get counted apart
+        let stem = format!(
+            "{}-{}",
+            WorldMetadata::folder_name_for(name),
+            Utc::now().format("%Y-%m-%d_%H-%M-%S")
+        );
+        let mut to = trash.join(&stem);
+        for count in 2.. {
+            if !to.exists() {
+                break;
+            }
+            to = trash.join(format!("{stem}-{count}"));
+        }
+        std::fs::rename(&from, &to).map_err(|e| SaveManagerError::io(&from, e))?;
+
+        Ok(to)
+    }
+
+    /// Packs a world's save into a `.tar.gz` archive at `destination`.
+    pub fn export(&self, name: &str, destination: &Path) -> Result<(), SaveManagerError> {
+        let from = self.existing(name)?;
+
+        archive::write_tar_gz(&from, &WorldMetadata::folder_name_for(name), destination)
+            .map_err(|e| SaveManagerError::io(destination, e))
+    }
+
+    /// Reads everything there is to know about the save at `path`.
+    fn info_at(&self, path: PathBuf) -> SaveInfo {
+        let folder_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = urlencoding::decode(&folder_name)
+            .map(|name| name.into_owned())
+            .unwrap_or_else(|_| folder_name.clone());
+
+        let ((size, modified), walk_error) = match walk(&path) {
+            Ok(walked) => (walked, None),
+            Err(e) => ((0, None), Some(SaveManagerError::io(&path, e))),
+        };
+
+        // the folder can change without the world being played, like when
+        // it's copied, so the state's own record comes first
+        let relative = Path::new(SAVES_DIRECTORY).join(&folder_name);
+        let played = WorldState::read(&self.storage, &relative)
+            .ok()
+            .flatten()
+            .map(|state| state.last_played);
+
+        SaveInfo {
+            name,
+            metadata: WorldMetadata::read(&self.storage, &relative),
+            last_played: played.or(modified.map(DateTime::<Utc>::from)),
+            size,
+            walk_error,
+            path,
+        }
+    }
+
+    /// Where the world with the given name is saved, relative to the root of
+    /// the storage.
+    fn relative_path(name: &str) -> PathBuf {
+        Path::new(SAVES_DIRECTORY).join(WorldMetadata::folder_name_for(name))
+    }
+
+    /// The path of a save that has to exist.
+    fn existing(&self, name: &str) -> Result<PathBuf, SaveManagerError> {
+        let path = self.save_path(name);
+
+        if !path.is_dir() {
+            return Err(SaveManagerError::SaveNotFound(name.to_owned()));
+        }
+        Ok(path)
+    }
+
+    /// The path of a save that can't exist yet.
+    fn vacant(&self, name: &str) -> Result<PathBuf, SaveManagerError> {
+        let path = self.save_path(name);
+
+        if path.exists() {
+            return Err(SaveManagerError::SaveAlreadyExists(name.to_owned()));
+        }
+        Ok(path)
+    }
+}
+
+impl Default for SaveManager {
+    /// A manager for the saves in the game's usual folder.
+    fn default() -> Self {
+        Self::new(FilesystemStorage::default())
+    }
+}
+
+/// Adds up the size of everything in a folder, along with the last time
+/// anything in it was modified.
+fn walk(path: &Path) -> std::io::Result<(u64, Option<SystemTime>)> {
+    let mut size = 0;
+    let mut modified = None;
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+
+        let (entry_size, entry_modified) = if meta.is_dir() {
+            walk(&entry.path())?
+        } else {
+            (meta.len(), meta.modified().ok())
+        };
+
+        size += entry_size;
+        modified = modified.max(entry_modified);
+    }
+
+    Ok((size, modified))
+}
+
+/// An error from managing saves.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum SaveManagerError {
+    #[error("There's no save for a world named `{0}`.")]
+    SaveNotFound(String),
+    #[error("There's already a save for a world named `{0}`.")]
+    SaveAlreadyExists(String),
+    #[error("Filesystem error at `{path}`: `{error}`.")]
+    Io { path: String, error: String },
+    #[error(transparent)]
+    Loading(#[from] WorldLoadingError),
+}
+
+impl SaveManagerError {
+    fn io(path: &Path, error: std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    use chrono::{TimeZone as _, Utc};
+
+    use super::{SaveManager, SaveManagerError};
+    use crate::world::{
+        metadata::{GeneratorId, WorldMetadata},
+        state::WorldState,
+        storage::FilesystemStorage,
+    };
+
+    #[test]
+    fn manage_saves() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("macaw-saves-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let storage = FilesystemStorage::new(&root);
+        let manager = SaveManager::new(storage.clone());
+
+        let generator = GeneratorId::new("net", "barrettray", "test", None::<String>);
+        let metadata = WorldMetadata::new_now("my world!".into(), 1, generator);
+        metadata.write(&storage)?;
+        std::fs::create_dir_all(manager.saves_path().join("broken"))?;
+
+        let saves = manager.list()?;
+        assert_eq!(saves.len(), 2);
+        let mine = saves.iter().find(|s| s.name() == "my world!").unwrap();
+        assert_eq!(mine.metadata(), Ok(&metadata));
+        assert!(mine.size() > 0 && mine.last_played().is_some());
+        assert_eq!(mine.walk_error(), None);
+        let broken = saves.iter().find(|s| s.name() == "broken").unwrap();
+        assert!(broken.metadata().is_err());
+
+        // renaming moves the folder and fixes the metadata
+        let renamed = manager.rename("my world!", "our world")?;
+        assert_eq!(renamed.name(), "our world");
+        assert_eq!(
+            manager.info("our world")?.metadata().map(|m| m.name()),
+            Ok("our world")
+        );
+        assert_eq!(
+            manager.info("my world!"),
+            Err(SaveManagerError::SaveNotFound("my world!".into()))
+        );
+
+        manager.duplicate("our world", "their world")?;
+        assert_eq!(
+            manager.duplicate("our world", "their world"),
+            Err(SaveManagerError::SaveAlreadyExists("their world".into()))
+        );
+
+        let archive = root.join("our world.tar.gz");
+        manager.export("our world", &archive)?;
+        assert_eq!(std::fs::read(&archive)?[..2], [0x1f, 0x8b]);
+
+        // unpacking it gives back the same save
+        let unpacked = root.join("unpacked");
+        let file = std::fs::File::open(&archive)?;
+        tar::Archive::new(flate2::read::GzDecoder::new(file)).unpack(&unpacked)?;
+        assert_eq!(
+            files(&unpacked.join(WorldMetadata::folder_name_for("our world")))?,
+            files(&manager.save_path("our world"))?
+        );
+
+        // the world's own record of when it was played beats the folder's
+        let played = Utc.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap();
+        let state = WorldState {
+            last_played: played,
+            ..WorldState::default()
+        };
+        state.write(&storage, &renamed.save_path())?;
+        assert_eq!(manager.info("our world")?.last_played(), Some(played));
+
+        let trashed = manager.delete("their world")?;
+        assert!(trashed.starts_with(manager.trash_path()));
+        assert_eq!(manager.list()?.len(), 2);
+
+        // deleting another world by the same name right away doesn't clash
+        manager.duplicate("our world", "their world")?;
+        let again = manager.delete("their world")?;
+        assert_ne!(again, trashed);
+        assert!(trashed.is_dir() && again.is_dir());
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    /// Every file under a folder along with its contents, keyed by its path
+    /// within the folder.
+    fn files(folder: &Path) -> std::io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        let mut folders = vec![folder.to_path_buf()];
+
+        while let Some(next) = folders.pop() {
+            for entry in std::fs::read_dir(next)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    folders.push(path);
+                } else {
+                    let contents = std::fs::read(&path)?;
+                    files.insert(path.strip_prefix(folder).unwrap().to_path_buf(), contents);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
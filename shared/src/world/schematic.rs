@@ -0,0 +1,450 @@
+//! # Schematic
+//!
+//! Saves boxes of blocks to MCEdit's `.schematic` files, so structures can
+//! be shared between worlds and reused by generators.
+//!
+//! A schematic is a gzipped NBT compound holding its size and a pair of
+//! byte arrays with Beta block ids and data, ordered by `y`, then `z`, then
+//! `x`. Blocks are mapped with `block::legacy`, so anything without a Beta
+//! id is saved as air.
+//!
+//! Pasting happens through `Transaction::paste`, so it can be rolled back
+//! like any other edit.
+
+use std::{collections::BTreeMap, path::Path};
+
+use thiserror::Error;
+
+use super::{
+    coordinates::{BoundingBox, GlobalCoordinate},
+    edit::EditError,
+    nbt::{self, Compound, NbtError, Tag},
+    MacawWorld,
+};
+use crate::block::{
+    legacy,
+    state::{Axis, Facing, Property, PropertyValue},
+    Block, BlockType,
+};
+
+/// The only kind of block ids Macaw reads and writes.
+const MATERIALS: &str = "Alpha";
+
+/// A box of blocks, ready to be saved or pasted somewhere.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    /// How far the schematic stretches along the x axis.
+    width: u16,
+    /// How far the schematic stretches along the y axis.
+    height: u16,
+    /// How far the schematic stretches along the z axis.
+    length: u16,
+    /// Every block, ordered by `y`, then `z`, then `x`.
+    blocks: Vec<Block>,
+}
+
+impl Schematic {
+    /// Copies the blocks in a box of the world.
+    ///
+    /// Every chunk the box covers must be loaded.
+    pub fn from_world(
+        world: &MacawWorld,
+        bounds: BoundingBox<GlobalCoordinate>,
+    ) -> Result<Self, SchematicError> {
+        let (smaller, larger) = bounds.bounds();
+        let side = |low: i64, high: i64| {
+            u16::try_from(high - low + 1)
+                .ok()
+                .filter(|side| *side <= i16::MAX as u16)
+        };
+
+        let (Some(width), Some(height), Some(length)) = (
+            side(smaller.x, larger.x),
+            side(smaller.y, larger.y),
+            side(smaller.z, larger.z),
+        ) else {
+            return Err(SchematicError::TooBig(bounds));
+        };
+
+        let mut blocks = Vec::with_capacity(width as usize * height as usize * length as usize);
+        for y in smaller.y..=larger.y {
+            for z in smaller.z..=larger.z {
+                for x in smaller.x..=larger.x {
+                    let coords = GlobalCoordinate::new(x, y, z);
+                    let block = world
+                        .loaded_block(coords)
+                        .ok_or(EditError::ChunkNotLoaded(coords.chunk()))?;
+
+                    blocks.push(block);
+                }
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            length,
+            blocks,
+        })
+    }
+
+    /// How many blocks the schematic stretches along the x, y, and z axes.
+    pub fn size(&self) -> (u16, u16, u16) {
+        (self.width, self.height, self.length)
+    }
+
+    /// Gets the block at an offset from the schematic's smallest corner.
+    pub fn block(&self, x: u16, y: u16, z: u16) -> Option<&Block> {
+        if x >= self.width || y >= self.height || z >= self.length {
+            return None;
+        }
+
+        self.blocks.get(self.index(x, y, z))
+    }
+
+    /// The box this schematic covers when pasted, turned by `rotation`, with
+    /// its smallest corner on `origin`.
+    pub fn bounds_at(
+        &self,
+        origin: GlobalCoordinate,
+        rotation: Rotation,
+    ) -> BoundingBox<GlobalCoordinate> {
+        let (width, length) = match rotation.is_quarter_turn() {
+            true => (self.length, self.width),
+            false => (self.width, self.length),
+        };
+
+        BoundingBox::new(
+            origin,
+            GlobalCoordinate::new(
+                origin.x + width as i64 - 1,
+                origin.y + self.height as i64 - 1,
+                origin.z + length as i64 - 1,
+            ),
+        )
+    }
+
+    /// Every block with its offset from the schematic's smallest corner.
+    pub fn blocks(&self) -> impl Iterator<Item = ((u16, u16, u16), &Block)> + '_ {
+        let (width, length) = (self.width as usize, self.length as usize);
+
+        self.blocks.iter().enumerate().map(move |(index, block)| {
+            let offset = (
+                (index % width) as u16,
+                (index / (width * length)) as u16,
+                (index / width % length) as u16,
+            );
+            (offset, block)
+        })
+    }
+
+    /// Turns the schematic around the y axis, along with the blocks in it.
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        let (width, length) = match rotation.is_quarter_turn() {
+            true => (self.length, self.width),
+            false => (self.width, self.length),
+        };
+
+        let mut rotated = Self {
+            width,
+            height: self.height,
+            length,
+            blocks: vec![Block::default(); self.blocks.len()],
+        };
+
+        for ((x, y, z), block) in self.blocks() {
+            let (new_x, new_z) = match rotation {
+                Rotation::None => (x, z),
+                Rotation::Clockwise90 => (self.length - 1 - z, x),
+                Rotation::Clockwise180 => (self.width - 1 - x, self.length - 1 - z),
+                Rotation::Clockwise270 => (z, self.width - 1 - x),
+            };
+
+            let index = rotated.index(new_x, y, new_z);
+            rotated.blocks[index] = rotation.rotate_block(block);
+        }
+
+        rotated
+    }
+
+    /// Reads a schematic from the contents of a `.schematic` file.
+    ///
+    /// Blocks without a Macaw equivalent become air.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SchematicError> {
+        let (_, root) = nbt::from_gzip(bytes)?;
+
+        let materials = root.string("Materials")?;
+        if materials != MATERIALS {
+            return Err(SchematicError::UnsupportedMaterials(materials.to_owned()));
+        }
+
+        let side = |name: &str| -> Result<u16, SchematicError> {
+            let side = root.short(name)?;
+            u16::try_from(side)
+                .map_err(|_| SchematicError::Malformed(format!("`{name}` is negative ({side})")))
+        };
+        let (width, height, length) = (side("Width")?, side("Height")?, side("Length")?);
+
+        let volume = width as usize * height as usize * length as usize;
+        let (ids, data) = (root.byte_array("Blocks")?, root.byte_array("Data")?);
+        if ids.len() != volume || data.len() != volume {
+            return Err(SchematicError::Malformed(format!(
+                "a {width}x{height}x{length} schematic has {} blocks and {} bytes of data",
+                ids.len(),
+                data.len()
+            )));
+        }
+
+        let mut unknown = BTreeMap::<(u8, u8), u64>::new();
+        let blocks = ids
+            .iter()
+            .zip(data.iter())
+            .map(|(id, data)| {
+                legacy::from_legacy(*id, data & 0xF).unwrap_or_else(|| {
+                    *unknown.entry((*id, data & 0xF)).or_default() += 1;
+                    Block::default()
+                })
+            })
+            .collect();
+
+        for ((id, data), count) in unknown {
+            tracing::warn!(
+                "Replaced {count} blocks of `{id}:{data}` with air, as Macaw doesn't have them"
+            );
+        }
+
+        Ok(Self {
+            width,
+            height,
+            length,
+            blocks,
+        })
+    }
+
+    /// Writes the schematic out as the contents of a `.schematic` file.
+    ///
+    /// Blocks without a Beta equivalent are written as air.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SchematicError> {
+        let mut unknown = BTreeMap::<BlockType, u64>::new();
+        let (ids, data): (Vec<u8>, Vec<u8>) = self
+            .blocks
+            .iter()
+            .map(|block| {
+                legacy::to_legacy(block).unwrap_or_else(|| {
+                    *unknown.entry(block.block_type).or_default() += 1;
+                    (0, 0)
+                })
+            })
+            .unzip();
+
+        for (block_type, count) in unknown {
+            tracing::warn!(
+                "Saved {count} blocks of `{block_type}` as air, as Beta doesn't have them"
+            );
+        }
+
+        let root = Compound::new()
+            .with("Width", Tag::Short(self.width as i16))
+            .with("Height", Tag::Short(self.height as i16))
+            .with("Length", Tag::Short(self.length as i16))
+            .with("Materials", Tag::String(MATERIALS.into()))
+            .with("Blocks", Tag::ByteArray(ids))
+            .with("Data", Tag::ByteArray(data))
+            .with("Entities", Tag::List(Vec::new()))
+            .with("TileEntities", Tag::List(Vec::new()));
+
+        Ok(nbt::to_gzip("Schematic", &root)?)
+    }
+
+    /// Reads a `.schematic` file.
+    pub fn open(path: &Path) -> Result<Self, SchematicError> {
+        let bytes = std::fs::read(path).map_err(|e| SchematicError::io(path, e))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Writes the schematic into a `.schematic` file, replacing anything
+    /// already there.
+    pub fn save(&self, path: &Path) -> Result<(), SchematicError> {
+        std::fs::write(path, self.to_bytes()?).map_err(|e| SchematicError::io(path, e))
+    }
+
+    /// Where a block lives in `blocks`.
+    fn index(&self, x: u16, y: u16, z: u16) -> usize {
+        (y as usize * self.length as usize + z as usize) * self.width as usize + x as usize
+    }
+}
+
+/// How far to turn a schematic around the y axis, as seen from above.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, PartialOrd, Eq, Ord)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Turns a direction by this rotation. Up and down stay put.
+    pub fn rotate_facing(&self, facing: Facing) -> Facing {
+        let turns = match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        };
+
+        (0..turns).fold(facing, |facing, _| match facing {
+            Facing::North => Facing::East,
+            Facing::East => Facing::South,
+            Facing::South => Facing::West,
+            Facing::West => Facing::North,
+            other => other,
+        })
+    }
+
+    /// Whether this rotation swaps the x and z axes.
+    const fn is_quarter_turn(&self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+
+    /// Turns a block's facing and axis by this rotation.
+    ///
+    /// If the block can't face its new way, it's left as it was.
+    pub fn rotate_block(&self, block: &Block) -> Block {
+        let mut rotated = block.clone();
+
+        if let Some(PropertyValue::Facing(facing)) = block.get(Property::Facing) {
+            let _ = rotated.set(PropertyValue::Facing(self.rotate_facing(facing)));
+        }
+
+        if let (true, Some(PropertyValue::Axis(axis))) =
+            (self.is_quarter_turn(), block.get(Property::Axis))
+        {
+            let axis = match axis {
+                Axis::X => Axis::Z,
+                Axis::Z => Axis::X,
+                Axis::Y => Axis::Y,
+            };
+            let _ = rotated.set(PropertyValue::Axis(axis));
+        }
+
+        rotated
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum SchematicError {
+    #[error("Schematics can only be up to 32767 blocks on each side, but `{0:?}` is bigger")]
+    TooBig(BoundingBox<GlobalCoordinate>),
+    #[error("Schematics with `{0}` materials aren't supported")]
+    UnsupportedMaterials(String),
+    #[error("Schematic is broken: {0}")]
+    Malformed(String),
+    #[error("Filesystem error at `{path}`: `{error}`")]
+    Io { path: String, error: String },
+    #[error(transparent)]
+    Nbt(#[from] NbtError),
+    #[error(transparent)]
+    Edit(#[from] EditError),
+}
+
+impl SchematicError {
+    fn io(path: &Path, error: std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Rotation, Schematic};
+    use crate::{
+        block::{
+            state::{Facing, Property, PropertyValue},
+            Block, BlockType,
+        },
+        world::{
+            chunk::Chunk,
+            coordinates::{BoundingBox, ChunkCoordinate, GlobalCoordinate},
+            edit::Transaction,
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn schematics_paste_rotated() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+        world.set_chunk(Chunk::new(ChunkCoordinate::ORIGIN), ChunkCoordinate::ORIGIN);
+
+        // a 2x1x3 strip of stone with a torch facing north at its far end
+        let stone = Block::new(BlockType::STONE, 0);
+        let mut torch = Block::new(BlockType::TORCH, 0);
+        torch.set(PropertyValue::Facing(Facing::North))?;
+
+        let source = BoundingBox::new(
+            GlobalCoordinate::new(0, 0, 0),
+            GlobalCoordinate::new(1, 0, 2),
+        );
+        let mut transaction = Transaction::new();
+        transaction.fill(source, stone.clone()).fill(
+            BoundingBox::new_point(GlobalCoordinate::new(1, 0, 2)),
+            torch.clone(),
+        );
+        world.apply(&transaction)?;
+
+        // saving and reading back changes nothing
+        let schematic = Schematic::from_world(&world, source)?;
+        assert_eq!(schematic.size(), (2, 1, 3));
+        let schematic = Schematic::from_bytes(&schematic.to_bytes()?)?;
+        assert_eq!(schematic.block(1, 0, 2), Some(&torch));
+
+        // turning it a quarter moves the torch to the other corner
+        let rotated = schematic.rotated(Rotation::Clockwise90);
+        assert_eq!(rotated.size(), (3, 1, 2));
+        let turned = rotated.block(0, 0, 1).unwrap();
+        assert_eq!(
+            turned.get(Property::Facing),
+            Some(PropertyValue::Facing(Facing::East))
+        );
+
+        // air in the schematic can leave the world alone
+        let dirt = Block::new(BlockType::DIRT, 0);
+        let origin = GlobalCoordinate::new(8, 4, 8);
+        let mut transaction = Transaction::new();
+        transaction
+            .fill(BoundingBox::new_point(origin), dirt.clone())
+            .paste(
+                Arc::new(Schematic::from_world(
+                    &world,
+                    BoundingBox::new(
+                        GlobalCoordinate::new(0, 0, 0),
+                        GlobalCoordinate::new(1, 1, 2),
+                    ),
+                )?),
+                GlobalCoordinate::new(8, 3, 8),
+                Rotation::None,
+                true,
+            );
+        let applied = world.apply(&transaction)?;
+
+        assert_eq!(world.loaded_block(origin), Some(dirt));
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(9, 3, 10)),
+            Some(torch)
+        );
+
+        // pastes roll back like any other edit
+        world.rollback(applied)?;
+        assert_eq!(
+            world.loaded_block(GlobalCoordinate::new(9, 3, 10)),
+            Some(Block::default())
+        );
+
+        Ok(())
+    }
+}
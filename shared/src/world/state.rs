@@ -0,0 +1,137 @@
+//! # State
+//!
+//! The parts of a world that change as it's played, like the time of day
+//! and the weather, kept next to its metadata in `state.toml`.
+//!
+//! Metadata says what a world _is_. State says where it was when it was last
+//! saved, so reopening it picks up right where it left off.
+
+use std::path::Path;
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use super::{coordinates::GlobalCoordinate, loader::WorldLoadingError, storage::StorageBackend};
+use crate::time::GameTick;
+
+/// The name of the state file in every world save.
+pub const STATE_FILE: &str = "state.toml";
+
+/// Everything about a world that changes while it's played.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct WorldState {
+    /// Where new players first appear.
+    pub spawn_location: GlobalCoordinate,
+    /// The current time in the world.
+    pub time: GameTick,
+    pub weather: Weather,
+    /// When the world was last saved while being played.
+    pub last_played: DateTime<chrono::Utc>,
+}
+
+impl WorldState {
+    /// Creates the state of a brand new world, with players spawning at
+    /// `spawn_location`.
+    pub fn new(spawn_location: GlobalCoordinate) -> Self {
+        Self {
+            spawn_location,
+            time: GameTick::default(),
+            weather: Weather::default(),
+            last_played: chrono::Utc::now(),
+        }
+    }
+
+    /// Reads the state of the world saved at `save_path` in some storage.
+    ///
+    /// Worlds saved before state was kept give back `None`.
+    pub fn read(
+        storage: &dyn StorageBackend,
+        save_path: &Path,
+    ) -> Result<Option<Self>, WorldLoadingError> {
+        let path = save_path.join(STATE_FILE);
+        let Some(bytes) = storage.read(&path)? else {
+            return Ok(None);
+        };
+
+        let s = String::from_utf8(bytes).map_err(|e| {
+            WorldLoadingError::StateReadFailed(format!(
+                "state file at `{}` isn't UTF-8: {e}",
+                path.to_string_lossy()
+            ))
+        })?;
+
+        toml::from_str(&s).map(Some).map_err(|e| {
+            WorldLoadingError::StateReadFailed(format!(
+                "failed to parse state file at `{}`: {e}",
+                path.to_string_lossy()
+            ))
+        })
+    }
+
+    /// Writes this state into the world saved at `save_path` in some storage.
+    pub fn write(
+        &self,
+        storage: &dyn StorageBackend,
+        save_path: &Path,
+    ) -> Result<(), WorldLoadingError> {
+        let s = toml::to_string_pretty(&self).map_err(|e| {
+            WorldLoadingError::StateWriteFailed(format!("failed to serialize state to toml: {e}"))
+        })?;
+
+        storage.write(&save_path.join(STATE_FILE), s.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Default for WorldState {
+    fn default() -> Self {
+        Self::new(GlobalCoordinate::ORIGIN)
+    }
+}
+
+/// The weather in a world, and how long until it changes.
+///
+/// Like in Beta, rain and thunder each have their own timer. When a timer
+/// runs out, that kind of weather flips on or off.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Weather {
+    pub raining: bool,
+    /// Ticks until it starts or stops raining.
+    pub rain_time: u32,
+    pub thundering: bool,
+    /// Ticks until it starts or stops thundering.
+    pub thunder_time: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Weather;
+    use crate::{
+        time::GameTick,
+        world::{coordinates::GlobalCoordinate, MacawWorld},
+    };
+
+    #[test]
+    fn worlds_resume_where_they_left_off() -> anyhow::Result<()> {
+        let mut world = MacawWorld::default();
+
+        let state = world.state_mut();
+        state.spawn_location = GlobalCoordinate::new(-40, 72, 9);
+        state.time = GameTick::new(13_500, 4);
+        state.weather = Weather {
+            raining: true,
+            rain_time: 6_000,
+            thundering: false,
+            thunder_time: 90_000,
+        };
+        world.save()?;
+
+        let loaded = MacawWorld::load(world.metadata(), world.storage())?;
+        assert_eq!(loaded.state(), world.state());
+        assert_eq!(loaded.spawn_location(), GlobalCoordinate::new(-40, 72, 9));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! # Storage
+//!
+//! Where world saves actually live.
+//!
+//! Saves are made of 'blobs': metadata, region files, backups, and so on.
+//! Each blob has a path relative to the root of its storage, like
+//! `saves/my_world/0_0_0.region`. Storage backends decide what that root is,
+//! so worlds can be kept in a game folder, a server's folder, or just in
+//! memory for tests.
+
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use thiserror::Error;
+
+pub use self::{filesystem::FilesystemStorage, memory::MemoryStorage};
+
+pub mod filesystem;
+pub mod memory;
+
+/// A shared handle to some storage backend.
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// Somewhere world saves can be read from and written to.
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Reads a whole blob, if there's one at `path`.
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Writes a whole blob to `path`, replacing anything already there.
+    ///
+    /// This has to be atomic: if it fails partway, the old blob is left
+    /// as it was.
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), StorageError>;
+
+    /// Reads up to `length` bytes of a blob, starting `offset` bytes in, if
+    /// there's one at `path`. Reads running past the end of the blob give
+    /// back fewer bytes.
+    fn read_at(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.read(path)?.map(|data| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(length).min(data.len());
+            data[start..end].to_vec()
+        }))
+    }
+
+    /// Writes runs of bytes into the blob at `path`, each at its own offset,
+    /// after resizing it to `length` bytes. Everything else in the blob is
+    /// kept, and a missing blob starts out empty.
+    ///
+    /// Like `write()`, this has to be atomic: if it fails partway, the old
+    /// blob is left as it was.
+    fn write_at(
+        &self,
+        path: &Path,
+        runs: &[(u64, &[u8])],
+        length: u64,
+    ) -> Result<(), StorageError> {
+        let mut data = self.read(path)?.unwrap_or_default();
+        data.resize(length as usize, 0);
+
+        for (offset, bytes) in runs {
+            let start = *offset as usize;
+            if start + bytes.len() > data.len() {
+                data.resize(start + bytes.len(), 0);
+            }
+            data[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        self.write(path, &data)
+    }
+
+    /// The size of the blob at `path` in bytes, if there's one there.
+    fn size(&self, path: &Path) -> Result<Option<u64>, StorageError> {
+        Ok(self.read(path)?.map(|data| data.len() as u64))
+    }
+
+    /// Removes the blob at `path`. Removing a missing blob does nothing.
+    fn remove(&self, path: &Path) -> Result<(), StorageError>;
+
+    /// Lists the paths of every blob under the `prefix` folder, including
+    /// those in folders inside of it.
+    fn list(&self, prefix: &Path) -> Result<Vec<PathBuf>, StorageError>;
+
+    /// Checks if there's a blob at `path`.
+    fn exists(&self, path: &Path) -> Result<bool, StorageError> {
+        Ok(self.read(path)?.is_some())
+    }
+
+    /// Moves the blob at `from` over to `to`.
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), StorageError> {
+        let data = self
+            .read(from)?
+            .ok_or_else(|| StorageError::NotFound(from.to_string_lossy().to_string()))?;
+
+        self.write(to, &data)?;
+        self.remove(from)
+    }
+
+    /// Copies every blob under the `from` folder into the `to` folder.
+    fn copy_all(&self, from: &Path, to: &Path) -> Result<(), StorageError> {
+        for path in self.list(from)? {
+            let relative = path.strip_prefix(from).unwrap_or(&path);
+
+            if let Some(data) = self.read(&path)? {
+                self.write(&to.join(relative), &data)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An error from a storage backend.
+#[derive(Clone, Debug, Error, PartialEq, PartialOrd, Hash)]
+pub enum StorageError {
+    #[error("Nothing is stored at `{0}`")]
+    NotFound(String),
+    #[error("Failed to read `{path}` from storage: `{error}`")]
+    ReadFailed { path: String, error: String },
+    #[error("Failed to write `{path}` to storage: `{error}`")]
+    WriteFailed { path: String, error: String },
+}
+
+impl StorageError {
+    pub(crate) fn read_failed(path: &Path, error: impl ToString) -> Self {
+        Self::ReadFailed {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+
+    pub(crate) fn write_failed(path: &Path, error: impl ToString) -> Self {
+        Self::WriteFailed {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+}
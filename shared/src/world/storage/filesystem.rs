@@ -0,0 +1,246 @@
+//! # Filesystem
+//!
+//! Storage in a folder on disk.
+//!
+//! Writes go to a temporary file first, which is flushed to disk and then
+//! renamed over the real one. A crash mid-save leaves the old blob as it was.
+//! Ranged reads and writes seek around inside files instead of reading them
+//! whole.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+};
+
+use super::{StorageBackend, StorageError};
+use crate::world::save::get_game_path;
+
+/// Keeps blobs as files inside of a root folder.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FilesystemStorage {
+    root: PathBuf,
+}
+
+impl FilesystemStorage {
+    /// Creates storage rooted at the given folder. Nothing is created until
+    /// something gets written.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The folder holding everything in this storage.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where a blob's file is, whether or not it exists.
+    pub fn full_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Fills a temporary file with `fill`, then flushes it and renames it
+    /// over the blob's real file.
+    fn replace(
+        &self,
+        path: &Path,
+        fill: impl FnOnce(&Path) -> std::io::Result<File>,
+    ) -> Result<(), StorageError> {
+        let full_path = self.full_path(path);
+        let failed = |e: std::io::Error| StorageError::write_failed(path, e);
+
+        let folder = full_path.parent().unwrap_or(&self.root);
+        std::fs::create_dir_all(folder).map_err(failed)?;
+
+        let mut temporary = full_path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let file = fill(Path::new(&temporary)).map_err(failed)?;
+        file.sync_all().map_err(failed)?;
+        drop(file);
+
+        std::fs::rename(&temporary, &full_path).map_err(failed)?;
+
+        // the rename only survives a crash once its folder is flushed too.
+        // not every platform can open folders, so this is best-effort
+        if let Ok(folder) = File::open(folder) {
+            let _ = folder.sync_all();
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for FilesystemStorage {
+    /// Storage in the game's usual folder, inside the user's config
+    /// directory.
+    fn default() -> Self {
+        Self::new(get_game_path())
+    }
+}
+
+impl StorageBackend for FilesystemStorage {
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, StorageError> {
+        match std::fs::read(self.full_path(path)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(StorageError::read_failed(path, e)),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), StorageError> {
+        self.replace(path, |temporary| {
+            let mut file = File::create(temporary)?;
+            file.write_all(data)?;
+            Ok(file)
+        })
+    }
+
+    fn read_at(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let failed = |e: std::io::Error| StorageError::read_failed(path, e);
+
+        let mut file = match File::open(self.full_path(path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(failed(e)),
+        };
+
+        let mut data = Vec::with_capacity(length);
+        file.seek(SeekFrom::Start(offset)).map_err(failed)?;
+        file.take(length as u64)
+            .read_to_end(&mut data)
+            .map_err(failed)?;
+
+        Ok(Some(data))
+    }
+
+    fn write_at(
+        &self,
+        path: &Path,
+        runs: &[(u64, &[u8])],
+        length: u64,
+    ) -> Result<(), StorageError> {
+        let full_path = self.full_path(path);
+
+        self.replace(path, |temporary| {
+            // the copy takes the changes, so the real file stays untouched
+            // until it's replaced
+            match std::fs::copy(&full_path, temporary) {
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => drop(File::create(temporary)?),
+                Err(e) => return Err(e),
+            }
+
+            let mut file = OpenOptions::new().write(true).open(temporary)?;
+            file.set_len(length)?;
+            for (offset, bytes) in runs {
+                file.seek(SeekFrom::Start(*offset))?;
+                file.write_all(bytes)?;
+            }
+
+            Ok(file)
+        })
+    }
+
+    fn size(&self, path: &Path) -> Result<Option<u64>, StorageError> {
+        match std::fs::metadata(self.full_path(path)) {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(StorageError::read_failed(path, e)),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), StorageError> {
+        match std::fs::remove_file(self.full_path(path)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(StorageError::write_failed(path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, prefix: &Path) -> Result<Vec<PathBuf>, StorageError> {
+        let mut paths = Vec::new();
+        let mut folders = vec![prefix.to_path_buf()];
+
+        while let Some(folder) = folders.pop() {
+            let entries = match std::fs::read_dir(self.full_path(&folder)) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(StorageError::read_failed(&folder, e)),
+            };
+
+            for entry in entries {
+                let entry = entry.map_err(|e| StorageError::read_failed(&folder, e))?;
+                let path = folder.join(entry.file_name());
+
+                if entry.path().is_dir() {
+                    folders.push(path);
+                } else if path.extension().is_none_or(|ext| ext != "tmp") {
+                    // leftover temporary files aren't real blobs
+                    paths.push(path);
+                }
+            }
+        }
+
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, StorageError> {
+        Ok(self.full_path(path).is_file())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), StorageError> {
+        let failed = |e: std::io::Error| StorageError::write_failed(to, e);
+        let to = self.full_path(to);
+
+        if let Some(folder) = to.parent() {
+            std::fs::create_dir_all(folder).map_err(failed)?;
+        }
+
+        std::fs::rename(self.full_path(from), to).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => StorageError::NotFound(from.to_string_lossy().to_string()),
+            _ => failed(e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::FilesystemStorage;
+    use crate::world::storage::StorageBackend;
+
+    #[test]
+    fn writes_replace_files() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("macaw-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let storage = FilesystemStorage::new(&root);
+        let path = Path::new("saves/world/0_0_0.region");
+
+        assert_eq!(storage.read(path)?, None);
+        storage.write(path, &[1, 2, 3])?;
+        storage.write(path, &[4, 5])?;
+        assert_eq!(storage.read(path)?, Some(vec![4, 5]));
+
+        // ranged writes keep everything around them
+        storage.write_at(path, &[(1, &[6]), (4, &[7])], 3)?;
+        assert_eq!(storage.read(path)?, Some(vec![4, 6, 0, 0, 7]));
+        assert_eq!(storage.read_at(path, 3, 10)?, Some(vec![0, 7]));
+        assert_eq!(storage.size(path)?, Some(5));
+
+        // no temporary files are left behind
+        assert_eq!(
+            storage.list(Path::new("saves"))?,
+            vec![PathBuf::from("saves/world/0_0_0.region")]
+        );
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
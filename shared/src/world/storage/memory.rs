@@ -0,0 +1,131 @@
+//! # Memory
+//!
+//! Storage that only lives as long as the game does. Nothing ever touches
+//! the disk, which makes it great for tests and throwaway worlds.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use super::{StorageBackend, StorageError};
+
+/// Keeps every blob in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    blobs: RwLock<BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Creates some empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, StorageError> {
+        let blobs = self
+            .blobs
+            .read()
+            .map_err(|e| StorageError::read_failed(path, e))?;
+
+        Ok(blobs.get(path).cloned())
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), StorageError> {
+        let mut blobs = self
+            .blobs
+            .write()
+            .map_err(|e| StorageError::write_failed(path, e))?;
+
+        blobs.insert(path.to_path_buf(), data.to_vec());
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let blobs = self
+            .blobs
+            .read()
+            .map_err(|e| StorageError::read_failed(path, e))?;
+
+        Ok(blobs.get(path).map(|data| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(length).min(data.len());
+            data[start..end].to_vec()
+        }))
+    }
+
+    fn size(&self, path: &Path) -> Result<Option<u64>, StorageError> {
+        let blobs = self
+            .blobs
+            .read()
+            .map_err(|e| StorageError::read_failed(path, e))?;
+
+        Ok(blobs.get(path).map(|data| data.len() as u64))
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), StorageError> {
+        let mut blobs = self
+            .blobs
+            .write()
+            .map_err(|e| StorageError::write_failed(path, e))?;
+
+        blobs.remove(path);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &Path) -> Result<Vec<PathBuf>, StorageError> {
+        let blobs = self
+            .blobs
+            .read()
+            .map_err(|e| StorageError::read_failed(prefix, e))?;
+
+        Ok(blobs
+            .keys()
+            .filter(|path| path.starts_with(prefix) && path.as_path() != prefix)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::MemoryStorage;
+    use crate::world::storage::StorageBackend;
+
+    #[test]
+    fn blobs_round_trip() -> anyhow::Result<()> {
+        let storage = MemoryStorage::new();
+        let path = Path::new("saves/world/save.toml");
+
+        assert_eq!(storage.read(path)?, None);
+        storage.write(path, b"name = \"world\"")?;
+        storage.write(Path::new("saves/other/save.toml"), b"")?;
+        assert_eq!(
+            storage.read(path)?.as_deref(),
+            Some(&b"name = \"world\""[..])
+        );
+
+        // folders only hold what's in them
+        assert_eq!(
+            storage.list(Path::new("saves/world"))?,
+            vec![PathBuf::from("saves/world/save.toml")]
+        );
+
+        storage.copy_all(Path::new("saves/world"), Path::new("backups/world"))?;
+        storage.rename(path, Path::new("saves/world/old.toml"))?;
+        assert!(!storage.exists(path)?);
+        assert!(storage.exists(Path::new("backups/world/save.toml"))?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+//! # Streaming
+//!
+//! Keeps the chunks around players loaded, and lets go of everything else.
+//!
+//! Each update, missing chunks within the render distance of a player are
+//! loaded in the background, nearest first. Chunks no player is near anymore
+//! are saved and unloaded, so memory stays bounded however far players walk.
+//!
+//! Chunks held by a ticket are loaded and kept around too, wherever the
+//! players are. Chunks that turn out not to be saved anywhere are generated.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{coordinates::ChunkCoordinate, MacawWorld};
+
+/// How far around a player chunks are kept loaded, in chunks.
+///
+/// The horizontal distance makes a square around the player, like in Beta.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub struct RenderDistance {
+    /// How many chunks are loaded along the x and z axes, on each side.
+    pub horizontal: u32,
+    /// How many chunks are loaded above and below.
+    pub vertical: u32,
+}
+
+impl RenderDistance {
+    /// Creates a `RenderDistance`.
+    pub const fn new(horizontal: u32, vertical: u32) -> Self {
+        Self {
+            horizontal,
+            vertical,
+        }
+    }
+
+    /// Whether `chunk` is within range of a player in the `centre` chunk.
+    pub fn contains(&self, centre: ChunkCoordinate, chunk: ChunkCoordinate) -> bool {
+        (chunk.x - centre.x).unsigned_abs() <= self.horizontal as u64
+            && (chunk.z - centre.z).unsigned_abs() <= self.horizontal as u64
+            && (chunk.y - centre.y).unsigned_abs() <= self.vertical as u64
+    }
+
+    /// Every chunk within range of a player in the `centre` chunk.
+    pub fn chunks_around(&self, centre: ChunkCoordinate) -> Vec<ChunkCoordinate> {
+        let (h, v) = (self.horizontal as i64, self.vertical as i64);
+
+        let mut chunks = Vec::new();
+        for x in -h..=h {
+            for y in -v..=v {
+                for z in -h..=h {
+                    chunks.push(centre.offset(x, y, z));
+                }
+            }
+        }
+
+        chunks
+    }
+}
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self::new(8, 4)
+    }
+}
+
+/// How far apart two chunks are, squared.
+fn distance_squared(a: ChunkCoordinate, b: ChunkCoordinate) -> i64 {
+    let (x, y, z) = (a.x - b.x, a.y - b.y, a.z - b.z);
+    x * x + y * y + z * z
+}
+
+impl MacawWorld {
+    /// Loads the chunks within `distance` of the players standing in the
+    /// `centres` chunks, and saves and unloads the chunks out of everyone's
+    /// range.
+    ///
+    /// Missing chunks are asked for nearest first, followed by any chunks
+    /// held by tickets, and picked up by `poll()`. The ones that aren't saved
+    /// are generated, a step per call. Gives back the chunks that were
+    /// unloaded.
+    pub fn stream_around(
+        &mut self,
+        centres: &[ChunkCoordinate],
+        distance: RenderDistance,
+    ) -> Vec<ChunkCoordinate> {
+        // how far each wanted chunk is from the closest player
+        let mut wanted = HashMap::new();
+        for centre in centres {
+            for chunk in distance.chunks_around(*centre) {
+                let d = distance_squared(*centre, chunk);
+                wanted
+                    .entry(chunk)
+                    .and_modify(|closest: &mut i64| *closest = (*closest).min(d))
+                    .or_insert(d);
+            }
+        }
+
+        let mut missing = wanted
+            .iter()
+            .filter(|(chunk, _)| !self.chunks().contains_key(chunk))
+            .map(|(chunk, d)| (*d, *chunk))
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+
+        let held = self.loader.tickets().held_chunks().clone();
+        let held_missing = held
+            .iter()
+            .filter(|chunk| !self.chunks().contains_key(chunk))
+            .copied()
+            .collect::<Vec<_>>();
+        let missing = missing
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .chain(held_missing)
+            .collect::<Vec<_>>();
+        self.loader.load_chunks_async(missing.iter().copied());
+        self.generate_chunks(missing);
+
+        // chunks just past the edge hang around for a bit, so walking back
+        // and forth over it doesn't keep loading and unloading them
+        let keep = RenderDistance::new(distance.horizontal + 1, distance.vertical + 1);
+        let leaving = self
+            .chunks()
+            .keys()
+            .filter(|chunk| !centres.iter().any(|centre| keep.contains(*centre, **chunk)))
+            .copied()
+            .collect::<Vec<_>>();
+
+        // forget about chunks nobody wants anymore, even half-made ones
+        let still_wanted =
+            |chunk: &ChunkCoordinate| wanted.contains_key(chunk) || held.contains(chunk);
+        self.loader.retain_absent(still_wanted);
+        self.loader
+            .generating_mut()
+            .retain(|chunk, _| still_wanted(chunk));
+        self.loader.unload_chunks(leaving)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderDistance;
+    use crate::{
+        block::{Block, BlockType},
+        world::{
+            chunk::Chunk,
+            coordinates::{ChunkBlockCoordinate, ChunkCoordinate, GlobalCoordinate},
+            MacawWorld,
+        },
+    };
+
+    #[test]
+    fn chunks_follow_the_player() -> anyhow::Result<()> {
+        let stone = Block::new(BlockType::STONE, 0);
+        let (near, far) = (ChunkCoordinate::new(1, 0, 0), ChunkCoordinate::new(6, 0, 0));
+
+        let mut world = MacawWorld::default();
+        for coords in [near, far] {
+            world.set_chunk(Chunk::new_filled(stone.clone(), coords), coords);
+        }
+
+        // keep the spawn area's ticket out of the way
+        world.state_mut().spawn_location = GlobalCoordinate::new(-4096, 0, 0);
+        world.save()?;
+
+        // only the chunk in range comes in
+        let distance = RenderDistance::new(2, 1);
+        let mut streamed = MacawWorld::load_async(world.metadata(), world.storage())?;
+        assert!(streamed
+            .stream_around(&[ChunkCoordinate::ORIGIN], distance)
+            .is_empty());
+        streamed.loader.finish();
+        assert_eq!(streamed.chunks().keys().collect::<Vec<_>>(), vec![&near]);
+
+        // chunks that aren't saved aren't asked for again
+        assert!(streamed.loader.is_absent(ChunkCoordinate::ORIGIN));
+
+        // walk over to the far chunk, leaving a change behind
+        let dirt = Block::new(BlockType::DIRT, 0);
+        let corner = ChunkBlockCoordinate::ORIGIN;
+        streamed
+            .chunk(near)
+            .unwrap()
+            .set_block(dirt.clone(), corner);
+
+        streamed.stream_around(&[far], distance);
+        streamed.loader.finish();
+        assert_eq!(streamed.stream_around(&[far], distance), vec![near]);
+        streamed.loader.finish();
+        assert_eq!(streamed.chunks().keys().collect::<Vec<_>>(), vec![&far]);
+
+        // the change was saved on the way out
+        let reloaded = MacawWorld::load(world.metadata(), world.storage())?;
+        assert_eq!(reloaded.chunks()[&near].block(&corner), Some(dirt));
+
+        Ok(())
+    }
+}